@@ -1499,7 +1499,7 @@ class ToolDispatcher:
                 "setinstanceproperties": "SetInstanceProperties",
                 "getinstanceproperties": "GetInstanceProperties",
                 "callinstancemethod": "CallInstanceMethod",
-                "deleteinstance": "delete_instance", # Luau script is lowercase
+                "deleteinstance": "DeleteInstance",
                 "selectinstances": "SelectInstances",
                 "getselection": "get_selection", # Luau script is lowercase
                 "runcode": "RunCode",
@@ -1546,7 +1546,7 @@ class ToolDispatcher:
                 "set_instance_properties": "SetInstanceProperties",
                 "get_instance_properties": "GetInstanceProperties",
                 "call_instance_method": "CallInstanceMethod",
-                "delete_instance": "delete_instance", # Explicitly map snake_case to lowercase if Luau is lowercase
+                "delete_instance": "DeleteInstance",
                 "select_instances": "SelectInstances",
                 "get_selection": "get_selection", # Luau script is lowercase
                 "run_code": "RunCode",
@@ -0,0 +1,127 @@
+// audit.rs - append-only JSON-lines record of every task dispatched to the plugin (`--audit-file`)
+
+use crate::rbx_studio_server::{TaskCompletion, ToolArgumentValues};
+use color_eyre::eyre::{Result, WrapErr};
+use rmcp::Error as McpError;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Handle to the audit file. The file is only ever opened in append mode, so
+/// existing records are never truncated or rewritten.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("Could not open audit file {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends one line describing a finished (or failed) task and flushes it.
+    /// Write failures are logged rather than failing the tool call.
+    pub fn record(
+        &self,
+        task_id: Uuid,
+        args: &ToolArgumentValues,
+        outcome: &Result<TaskCompletion, McpError>,
+        duration: Duration,
+    ) {
+        let (status, error) = match outcome {
+            Ok(completion) if completion.result.is_error.unwrap_or(false) => ("error", None),
+            Ok(_) => ("ok", None),
+            Err(e) => ("failed", Some(e.message.to_string())),
+        };
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let record = serde_json::json!({
+            "timestamp_ms": timestamp_ms,
+            "task_id": task_id.to_string(),
+            "tool": args.variant_name(),
+            "arguments": args,
+            "status": status,
+            "error": error,
+            "duration_ms": duration.as_millis() as u64,
+        });
+
+        let mut line = record.to_string();
+        line.push('\n');
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(e) = file.write_all(line.as_bytes()).and_then(|_| file.flush()) {
+            warn!(target: "mcp_server", task_id = %task_id, "Failed to write audit record: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rmcp::model::{CallToolResult, Content};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn appends_one_json_line_per_task() {
+        let path =
+            env::temp_dir().join(format!("rbx-studio-mcp-{}-audit.jsonl", std::process::id()));
+        fs::write(&path, "{\"earlier\":true}\n").unwrap();
+        let audit = AuditLog::open(&path).unwrap();
+        let completed = Ok(TaskCompletion {
+            result: CallToolResult::success(vec![Content::text("ok")]),
+            queue_wait: None,
+            response_bytes: 2,
+        });
+        let failed = Err(McpError::internal_error("Tool execution timed out.", None));
+        let first = Uuid::new_v4();
+        audit.record(
+            first,
+            &ToolArgumentValues::GetPlaceInfo {},
+            &completed,
+            Duration::from_millis(12),
+        );
+        audit.record(
+            Uuid::new_v4(),
+            &ToolArgumentValues::CheckLuau {
+                code: "print(1)".to_string(),
+            },
+            &failed,
+            Duration::from_millis(30),
+        );
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3, "earlier records are kept: {}", contents);
+        let records: Vec<serde_json::Value> = lines[1..]
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records[0]["task_id"], first.to_string());
+        assert_eq!(records[0]["tool"], "GetPlaceInfo");
+        assert_eq!(records[0]["status"], "ok");
+        assert_eq!(records[0]["duration_ms"], 12);
+        assert_eq!(records[1]["tool"], "CheckLuau");
+        assert_eq!(records[1]["arguments"]["CheckLuau"]["code"], "print(1)");
+        assert_eq!(records[1]["status"], "failed");
+        assert_eq!(records[1]["error"], "Tool execution timed out.");
+        assert!(records.iter().all(|record| record["timestamp_ms"].is_u64()));
+        fs::remove_file(&path).unwrap();
+    }
+}
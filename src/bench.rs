@@ -0,0 +1,151 @@
+// bench.rs - measures plugin round trips through the normal dispatch path (`--bench <n>`)
+
+use crate::rbx_studio_server::{
+    dispatch_and_wait, DispatchOptions, StateManagerCommand, ToolArgumentValues, ToolArguments,
+};
+use color_eyre::eyre::{eyre, Result};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Latency distribution of the round trips that completed.
+pub struct BenchReport {
+    pub completed: usize,
+    pub failed: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+    /// Completed round trips per second over the whole run.
+    pub throughput: f64,
+}
+
+impl BenchReport {
+    /// Summarises `latencies` (completed round trips, in any order) from a run that took `elapsed`.
+    pub fn from_latencies(
+        mut latencies: Vec<Duration>,
+        failed: usize,
+        elapsed: Duration,
+    ) -> Option<Self> {
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort();
+        // Nearest-rank percentile: the smallest sample with at least `p` of the samples at or below it.
+        let percentile = |p: f64| {
+            latencies[((p * latencies.len() as f64).ceil() as usize).clamp(1, latencies.len()) - 1]
+        };
+        Some(Self {
+            completed: latencies.len(),
+            failed,
+            min: latencies[0],
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max: latencies[latencies.len() - 1],
+            throughput: latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        })
+    }
+}
+
+/// Dispatches `count` no-op tasks one after another and prints the round-trip latency
+/// distribution. Each task compiles an empty chunk in the plugin, so the numbers are
+/// dominated by queueing, polling and HTTP overhead.
+pub async fn run_bench(
+    count: u32,
+    sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+) -> Result<()> {
+    println!("INFO: Benchmarking {} round trip(s); make sure Studio is open with the MCP plugin connected.", count);
+    let report = measure(count, sm_command_tx).await?;
+    println!(
+        "INFO: {} completed, {} failed. Round trip min {:?}, median {:?}, p95 {:?}, max {:?}; {:.1} tasks/s.",
+        report.completed, report.failed, report.min, report.median, report.p95, report.max, report.throughput
+    );
+    Ok(())
+}
+
+async fn measure(
+    count: u32,
+    sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+) -> Result<BenchReport> {
+    let mut latencies = Vec::with_capacity(count as usize);
+    let mut failed = 0;
+    let started = Instant::now();
+    for index in 0..count {
+        let (args, _) = ToolArguments::new_with_id(ToolArgumentValues::CheckLuau {
+            code: String::new(),
+        });
+        let sent = Instant::now();
+        match dispatch_and_wait(sm_command_tx, args, DispatchOptions::default()).await {
+            Ok(_) => latencies.push(sent.elapsed()),
+            Err(e) => {
+                failed += 1;
+                println!("[{}/{}] failed: {}", index + 1, count, e.message);
+            }
+        }
+    }
+    BenchReport::from_latencies(latencies, failed, started.elapsed())
+        .ok_or_else(|| eyre!("No round trip completed; is the Studio plugin connected?"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rbx_studio_server::{PollOutcome, StateManager};
+    use rmcp::model::{CallToolResult, Content};
+    use tokio::sync::oneshot;
+
+    // Answers every task the moment it is polled, over the `StateManager` channel the HTTP
+    // handlers use, so only the dispatch path itself is measured.
+    async fn instant_poller(sm_command_tx: mpsc::Sender<StateManagerCommand>) {
+        loop {
+            let (response_tx, response_rx) = oneshot::channel();
+            let poll = StateManagerCommand::PollForTask {
+                session: Some("bench".to_string()),
+                response_tx,
+            };
+            if sm_command_tx.send(poll).await.is_err() {
+                return;
+            }
+            let Ok(PollOutcome::Task(task)) = response_rx.await else {
+                continue;
+            };
+            let (accepted_tx, _) = oneshot::channel();
+            let submit = StateManagerCommand::SubmitTaskResult {
+                task_id: task.id().unwrap(),
+                result: CallToolResult::success(vec![Content::text("ok")]),
+                response_bytes: 2,
+                accepted_tx,
+            };
+            if sm_command_tx.send(submit).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn the_bench_measures_every_round_trip_against_an_instant_poller() {
+        let (sm_command_tx, sm_command_rx) = mpsc::channel(100);
+        tokio::spawn(StateManager::new().run(sm_command_rx));
+        tokio::spawn(instant_poller(sm_command_tx.clone()));
+
+        let report = measure(20, &sm_command_tx).await.unwrap();
+
+        assert_eq!((report.completed, report.failed), (20, 0));
+        assert!(report.min <= report.median, "min {:?}", report.min);
+        assert!(report.median <= report.p95, "median {:?}", report.median);
+        assert!(report.p95 <= report.max, "p95 {:?}", report.p95);
+        assert!(report.throughput > 0.0);
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let latencies = (1..=20).rev().map(Duration::from_millis).collect();
+        let report = BenchReport::from_latencies(latencies, 3, Duration::from_secs(4)).unwrap();
+
+        assert_eq!(report.min, Duration::from_millis(1));
+        assert_eq!(report.median, Duration::from_millis(10));
+        assert_eq!(report.p95, Duration::from_millis(19));
+        assert_eq!(report.max, Duration::from_millis(20));
+        assert_eq!(report.throughput, 5.0);
+        assert!(BenchReport::from_latencies(Vec::new(), 1, Duration::from_secs(1)).is_none());
+    }
+}
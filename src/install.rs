@@ -1,4 +1,9 @@
+use crate::luau;
+use crate::rbx_studio_server::STUDIO_PLUGIN_PORT;
 use color_eyre::eyre::{eyre, Result, WrapErr};
+use color_eyre::Section;
+use rbx_dom_weak::types::Variant;
+use rbx_dom_weak::ustr;
 use roblox_install::RobloxStudio;
 use serde_json::{json, Value};
 use std::fs::File;
@@ -8,6 +13,51 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::{env, fs, io};
 
+// Installer output goes through `say!` rather than `println!`: a console that rejects the
+// bytes (closed handle, legacy codepage) must not abort or stall the install.
+macro_rules! say {
+    () => { say_line("") };
+    ($($arg:tt)*) => { say_line(&format!($($arg)*)) };
+}
+
+fn say_line(message: &str) {
+    let message = if console_supports_unicode() {
+        message.into()
+    } else {
+        sanitize_console_text(message)
+    };
+    let _ = writeln!(io::stdout().lock(), "{}", message);
+}
+
+// Legacy Windows consoles default to an OEM codepage; Windows Terminal and redirected output
+// handle UTF-8 fine.
+fn console_supports_unicode() -> bool {
+    use std::io::IsTerminal;
+    !cfg!(target_os = "windows")
+        || !io::stdout().is_terminal()
+        || env::var_os("WT_SESSION").is_some()
+}
+
+/// Replaces characters a non-UTF-8 console can't show: common punctuation becomes its ASCII
+/// look-alike and anything else outside ASCII becomes `?`.
+fn sanitize_console_text(message: &str) -> std::borrow::Cow<'_, str> {
+    if message.is_ascii() {
+        return message.into();
+    }
+    message
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() => c,
+            '\u{2018}' | '\u{2019}' => '\'',
+            '\u{201C}' | '\u{201D}' => '"',
+            '\u{2013}' | '\u{2014}' => '-',
+            '\u{00A0}' => ' ',
+            _ => '?',
+        })
+        .collect::<String>()
+        .into()
+}
+
 // Original get_message, renamed:
 fn get_message_claude_cursor(successes: String) -> String {
     format!(
@@ -19,15 +69,15 @@ fn get_message_claude_cursor(successes: String) -> String {
 
         Note: Connecting a third-party LLM to Roblox Studio via an MCP server will share your data with that external service provider. \
         Please review their privacy practices carefully before proceeding.
-        To uninstall, delete MCPStudioPlugin.rbxm from your Plugins directory and remove entries from client configurations.",
+        To uninstall, run this program again with --uninstall.",
         successes
     )
 }
 
 // New message function for gemini_python_broker mode:
+#[cfg(feature = "gemini_python_broker")]
 fn get_message_gemini_python_broker() -> String {
-    format!(
-        "Roblox Studio MCP (Gemini Python Broker Mode) is set up!
+    "Roblox Studio MCP (Gemini Python Broker Mode) is set up!
         The necessary Roblox Studio plugin (MCPStudioPlugin.rbxm) has been installed.
 
         To use the Gemini AI capabilities with Roblox Studio:
@@ -37,8 +87,8 @@ fn get_message_gemini_python_broker() -> String {
         4. The `run_rust_server.bat` script can be used to start the MCP server that communicates with Roblox Studio (if not already started by another process or if you need to run it manually).
 
         The Python agent will connect to this MCP server to interact with Roblox Studio.
-        To uninstall, delete MCPStudioPlugin.rbxm from your Plugins directory."
-    )
+        To uninstall, run this program again with --uninstall."
+        .to_string()
 }
 
 // returns OS dependant claude_desktop_config.json path
@@ -54,6 +104,13 @@ fn get_claude_config() -> Result<PathBuf> {
         Path::new(&app_data)
             .join("Claude")
             .join("claude_desktop_config.json")
+    } else if cfg!(target_os = "linux") {
+        // Claude has no official Linux build; the community packages read the XDG config dir.
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home_dir.map(|home| Path::new(&home).join(".config")))
+            .ok_or_else(|| eyre!("Could not find HOME directory"))?;
+        config_dir.join("Claude").join("claude_desktop_config.json")
     } else {
         return Err(eyre!("Unsupported operating system"));
     };
@@ -61,6 +118,46 @@ fn get_claude_config() -> Result<PathBuf> {
     Ok(config_path)
 }
 
+// Studio doesn't run natively on Linux, so look for it in a Wine prefix: $WINEPREFIX, else ~/.wine.
+fn find_wine_plugins_dir() -> Result<PathBuf> {
+    let prefix = env::var_os("WINEPREFIX")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".wine")))
+        .filter(|prefix| prefix.is_dir())
+        .ok_or_else(|| eyre!("No Wine prefix found (set WINEPREFIX); pass --plugins-dir with Studio's Plugins folder instead"))?;
+    let users_dir = prefix.join("drive_c").join("users");
+    let mut roblox_dirs: Vec<PathBuf> = fs::read_dir(&users_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join("AppData").join("Local").join("Roblox"))
+                .collect()
+        })
+        .unwrap_or_default();
+    roblox_dirs.retain(|dir| dir.is_dir());
+    roblox_dirs.sort();
+    match roblox_dirs.first() {
+        Some(roblox_dir) => Ok(roblox_dir.join("Plugins")),
+        None => Err(eyre!(
+            "Roblox Studio was not found in the Wine prefix {}; run Studio there once, or pass --plugins-dir with its Plugins folder",
+            prefix.display()
+        )),
+    }
+}
+
+// `--plugins-dir` wins; otherwise ask roblox_install, which knows the Windows and macOS layouts.
+fn get_plugins_dir(options: &InstallOptions) -> Result<PathBuf> {
+    if let Some(plugins_dir) = &options.plugins_dir {
+        return Ok(plugins_dir.clone());
+    }
+    if cfg!(target_os = "linux") {
+        return find_wine_plugins_dir();
+    }
+    let studio = RobloxStudio::locate()
+        .wrap_err("Could not locate Roblox Studio; pass --plugins-dir with its Plugins folder")?;
+    Ok(studio.plugins_path().to_path_buf())
+}
+
 fn get_cursor_config() -> Result<PathBuf> {
     let home_dir = env::var_os("HOME")
         .or_else(|| env::var_os("USERPROFILE"))
@@ -68,6 +165,151 @@ fn get_cursor_config() -> Result<PathBuf> {
     Ok(Path::new(&home_dir).join(".cursor").join("mcp.json"))
 }
 
+fn home_dir() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .ok_or_else(|| eyre!("Could not find HOME directory"))
+}
+
+// The user-level mcp.json VS Code reads next to its settings.json.
+fn get_vscode_config() -> Result<PathBuf> {
+    let user_dir = if cfg!(target_os = "macos") {
+        home_dir()?.join("Library/Application Support/Code/User")
+    } else if cfg!(target_os = "windows") {
+        let app_data =
+            env::var_os("APPDATA").ok_or_else(|| eyre!("Could not find APPDATA directory"))?;
+        Path::new(&app_data).join("Code").join("User")
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .map_or_else(|| home_dir().map(|home| home.join(".config")), Ok)?
+            .join("Code")
+            .join("User")
+    };
+    Ok(user_dir.join("mcp.json"))
+}
+
+// Continue picks up Claude-style JSON files dropped into its mcpServers folder.
+fn get_continue_config() -> Result<PathBuf> {
+    Ok(home_dir()?
+        .join(".continue")
+        .join("mcpServers")
+        .join("roblox-studio.json"))
+}
+
+fn get_windsurf_config() -> Result<PathBuf> {
+    Ok(home_dir()?
+        .join(".codeium")
+        .join("windsurf")
+        .join("mcp_config.json"))
+}
+
+/// MCP clients the installer can configure.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Client {
+    Claude,
+    Cursor,
+    Vscode,
+    Continue,
+    Windsurf,
+}
+
+/// Where a client keeps its MCP config and the shape of the entry it expects.
+pub struct ClientTarget {
+    pub client: Client,
+    pub name: &'static str,
+    config_path: fn() -> Result<PathBuf>,
+    /// Top-level object holding the servers keyed by name.
+    servers_key: &'static str,
+    server_entry: fn(&Path, &[String]) -> Value,
+    /// Configured even when the client doesn't look installed, as the installer always did.
+    always: bool,
+}
+
+fn stdio_entry(exe_path: &Path, args: &[String]) -> Value {
+    json!({ "command": exe_path, "args": args })
+}
+
+// VS Code requires the transport type on every server.
+fn typed_stdio_entry(exe_path: &Path, args: &[String]) -> Value {
+    json!({ "type": "stdio", "command": exe_path, "args": args })
+}
+
+const CLIENTS: &[ClientTarget] = &[
+    ClientTarget {
+        client: Client::Claude,
+        name: "Claude",
+        config_path: get_claude_config,
+        servers_key: "mcpServers",
+        server_entry: stdio_entry,
+        always: true,
+    },
+    ClientTarget {
+        client: Client::Cursor,
+        name: "Cursor",
+        config_path: get_cursor_config,
+        servers_key: "mcpServers",
+        server_entry: stdio_entry,
+        always: true,
+    },
+    ClientTarget {
+        client: Client::Vscode,
+        name: "VS Code",
+        config_path: get_vscode_config,
+        servers_key: "servers",
+        server_entry: typed_stdio_entry,
+        always: false,
+    },
+    ClientTarget {
+        client: Client::Continue,
+        name: "Continue",
+        config_path: get_continue_config,
+        servers_key: "mcpServers",
+        server_entry: stdio_entry,
+        always: false,
+    },
+    ClientTarget {
+        client: Client::Windsurf,
+        name: "Windsurf",
+        config_path: get_windsurf_config,
+        servers_key: "mcpServers",
+        server_entry: stdio_entry,
+        always: false,
+    },
+];
+
+impl ClientTarget {
+    // A client counts as installed when the app's own config folder exists. Continue's
+    // mcpServers folder may not exist yet, so look one level further up.
+    fn detected(&self) -> bool {
+        let Ok(path) = (self.config_path)() else {
+            return false;
+        };
+        let levels = if self.client == Client::Continue {
+            2
+        } else {
+            1
+        };
+        path.ancestors().nth(levels).is_some_and(Path::is_dir)
+    }
+}
+
+/// The clients named by `--clients`, or by default Claude and Cursor plus any other client
+/// that appears to be installed.
+fn selected_clients(options: &InstallOptions) -> Vec<&'static ClientTarget> {
+    CLIENTS
+        .iter()
+        .filter(|target| {
+            if options.clients.is_empty() {
+                target.always || target.detected()
+            } else {
+                options.clients.contains(&target.client)
+            }
+        })
+        .collect()
+}
+
 #[cfg(target_os = "macos")]
 fn get_exe_path() -> Result<PathBuf> {
     use core_foundation::url::CFURL;
@@ -85,146 +327,542 @@ fn get_exe_path() -> io::Result<PathBuf> {
     env::current_exe()
 }
 
-pub fn install_to_config<'a>(
-    config_path: Result<PathBuf>,
+/// How client config files are serialized when they are rewritten.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigStyle {
+    /// Indented, one key per line.
+    #[default]
+    Pretty,
+    /// A single line with no whitespace between tokens.
+    Compact,
+}
+
+impl ConfigStyle {
+    fn serialize(self, config: &serde_json::Map<String, Value>) -> serde_json::Result<String> {
+        match self {
+            ConfigStyle::Pretty => serde_json::to_string_pretty(config),
+            ConfigStyle::Compact => serde_json::to_string(config),
+        }
+    }
+}
+
+/// Options for the install flow, set from the command line.
+#[derive(Clone, Debug)]
+pub struct InstallOptions {
+    /// Leave an existing "Roblox Studio" entry alone when it points at a different executable.
+    pub keep_existing_path: bool,
+    /// Serializer used when writing client configs. Key order is preserved from the existing file.
+    pub config_style: ConfigStyle,
+    /// Fail (with a non-zero exit) unless at least one client config was actually written.
+    pub require_client: bool,
+    /// Plugin port the server was asked to use; passed on to clients when it isn't the default.
+    pub port: u16,
+    /// Token the server requires from the plugin; written into the plugin and passed on to clients.
+    pub auth_token: Option<String>,
+    /// Install the plugin here instead of the Studio Plugins folder found automatically.
+    pub plugins_dir: Option<PathBuf>,
+    /// Clients to configure; empty means the default set from `selected_clients`.
+    pub clients: Vec<Client>,
+    /// Resolve paths and build the configs, but print what would change instead of writing.
+    pub dry_run: bool,
+    /// Read and write every client's config at this path instead of the client's own.
+    pub config_path_override: Option<PathBuf>,
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            keep_existing_path: false,
+            config_style: ConfigStyle::default(),
+            require_client: false,
+            port: STUDIO_PLUGIN_PORT,
+            auth_token: None,
+            plugins_dir: None,
+            clients: Vec::new(),
+            dry_run: false,
+            config_path_override: None,
+        }
+    }
+}
+
+// Where `target`'s config lives, unless the options point somewhere else.
+fn config_path_for(target: &ClientTarget, options: &InstallOptions) -> Result<PathBuf> {
+    match &options.config_path_override {
+        Some(path) => Ok(path.clone()),
+        None => (target.config_path)(),
+    }
+}
+
+/// What `install_to_config` did with a client's config file.
+#[derive(Debug)]
+pub enum ConfigWrite<'a> {
+    Updated(&'a str),
+    /// Left alone because of `--keep-existing-path`.
+    Kept(&'a str),
+}
+
+// The `command` of an already-configured "Roblox Studio" server entry, if any.
+fn existing_server_command(
+    config: &serde_json::Map<String, Value>,
+    servers_key: &str,
+) -> Option<String> {
+    config
+        .get(servers_key)
+        .and_then(|servers| servers.get("Roblox Studio"))
+        .and_then(|entry| entry.get("command"))
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+pub fn install_to_config(
+    target: &ClientTarget,
     exe_path: &Path,
-    name: &'a str,
-) -> Result<&'a str> {
-    let config_path = config_path?;
+    options: &InstallOptions,
+) -> Result<ConfigWrite<'static>> {
+    let name = target.name;
+    let config_path = config_path_for(target, options)?;
 
     // 1. Ensure parent directory exists
-    if let Some(parent_dir) = config_path.parent() {
+    if let Some(parent_dir) = config_path.parent().filter(|_| !options.dry_run) {
         if !parent_dir.exists() {
             fs::create_dir_all(parent_dir).map_err(|e| {
-                eyre!("Could not create parent directory {parent_dir:?} for {name} config: {e:#?}", parent_dir = parent_dir.display(), name = name)
+                eyre!(
+                    "Could not create parent directory {parent_dir:?} for {name} config: {e:#?}",
+                    parent_dir = parent_dir.display(),
+                    name = name
+                )
             })?;
-            println!("INFO: Created parent directory {} for {} configuration.", parent_dir.display(), name);
+            say!(
+                "INFO: Created parent directory {} for {} configuration.",
+                parent_dir.display(),
+                name
+            );
         }
     }
 
-    let mut config: serde_json::Map<String, Value> = {
+    let mut config: serde_json::Map<String, Value> = if options.dry_run && !config_path.exists() {
+        serde_json::Map::new()
+    } else {
         if !config_path.exists() {
             let mut file = File::create(&config_path).map_err(|e| {
-                eyre!("Could not create {name} config file at {config_path}: {e:#?}", config_path = config_path.display(), name = name)
+                eyre!(
+                    "Could not create {name} config file at {config_path}: {e:#?}",
+                    config_path = config_path.display(),
+                    name = name
+                )
             })?;
             // Initialize with an empty JSON object {}
             file.write_all(serde_json::to_string(&serde_json::Map::new())?.as_bytes())?;
-            println!("INFO: Created empty config file for {} at {}.", name, config_path.display());
+            say!(
+                "INFO: Created empty config file for {} at {}.",
+                name,
+                config_path.display()
+            );
         }
 
-        let config_file = File::open(&config_path)
-            .map_err(|error| eyre!("Could not open {name} config file at {config_path}: {error:#?}", name = name, config_path = config_path.display()))?;
+        let config_file = File::open(&config_path).map_err(|error| {
+            eyre!(
+                "Could not open {name} config file at {config_path}: {error:#?}",
+                name = name,
+                config_path = config_path.display()
+            )
+        })?;
         let reader = BufReader::new(config_file);
 
         // 2. Enhance JSON parsing error context
         serde_json::from_reader(reader).map_err(|e| {
-            eyre!("Could not parse JSON from {name} config file at {config_path}: {e:#?}", name = name, config_path = config_path.display())
+            eyre!(
+                "Could not parse JSON from {name} config file at {config_path}: {e:#?}",
+                name = name,
+                config_path = config_path.display()
+            )
         })?
     };
 
-    if !matches!(config.get("mcpServers"), Some(Value::Object(_))) {
-        config.insert("mcpServers".to_string(), json!({}));
+    // Installing from a second build location used to flip `command` silently between copies.
+    if let Some(existing_command) =
+        existing_server_command(&config, target.servers_key).filter(|c| Path::new(c) != exe_path)
+    {
+        if options.keep_existing_path {
+            say!(
+                "NOTICE: {} is configured to run {}; keeping it because --keep-existing-path was given (this build is {}).",
+                name,
+                existing_command,
+                exe_path.display()
+            );
+            return Ok(ConfigWrite::Kept(name));
+        }
+        say!(
+            "NOTICE: {} was configured to run {}; replacing it with {}.",
+            name,
+            existing_command,
+            exe_path.display()
+        );
     }
 
-    config["mcpServers"]["Roblox Studio"] = json!({
-      "command": exe_path, // Corrected: exe_path is already &Path
-      "args": [
-        "--stdio"
-      ]
-    });
+    if !matches!(config.get(target.servers_key), Some(Value::Object(_))) {
+        config.insert(target.servers_key.to_string(), json!({}));
+    }
+
+    let mut server_args = vec!["--stdio".to_string()];
+    if options.port != STUDIO_PLUGIN_PORT {
+        server_args.extend(["--port".to_string(), options.port.to_string()]);
+    }
+    if let Some(token) = &options.auth_token {
+        server_args.extend(["--auth-token".to_string(), token.clone()]);
+    }
+    config[target.servers_key]["Roblox Studio"] = (target.server_entry)(exe_path, &server_args);
+
+    if options.dry_run {
+        say!(
+            "DRY RUN: would write {} config {}:",
+            name,
+            config_path.display()
+        );
+        say!("{}", options.config_style.serialize(&config)?);
+        return Ok(ConfigWrite::Updated(name));
+    }
 
     // Re-open for writing (truncate) - this also benefits from parent dir creation
     let mut file = File::create(&config_path).map_err(|e| {
-        eyre!("Could not open {name} config file for writing at {config_path}: {e:#?}", name = name, config_path = config_path.display())
+        eyre!(
+            "Could not open {name} config file for writing at {config_path}: {e:#?}",
+            name = name,
+            config_path = config_path.display()
+        )
     })?;
-    file.write_all(serde_json::to_string_pretty(&config)?.as_bytes())
-        .map_err(|e| eyre!("Could not write to {name} config file at {config_path}: {e:#?}", name = name, config_path = config_path.display()))?;
+    file.write_all(options.config_style.serialize(&config)?.as_bytes())
+        .map_err(|e| {
+            eyre!(
+                "Could not write to {name} config file at {config_path}: {e:#?}",
+                name = name,
+                config_path = config_path.display()
+            )
+        })?;
 
     // 3. Update success println message
-    println!("INFO: Successfully configured {} to use this Roblox Studio MCP server. Details in {}.", name, config_path.display());
+    say!(
+        "INFO: Successfully configured {} to use this Roblox Studio MCP server. Details in {}.",
+        name,
+        config_path.display()
+    );
 
-    Ok(name)
+    Ok(ConfigWrite::Updated(name))
 }
 
-async fn install_internal() -> Result<String> {
+/// What `uninstall_from_config` found in a client's config file.
+#[derive(Debug)]
+pub enum ConfigRemoval<'a> {
+    Removed,
+    /// There was no config file, or it had no "Roblox Studio" entry.
+    NotPresent(&'a str),
+}
+
+/// Removes the "Roblox Studio" entry that `install_to_config` writes, leaving other servers
+/// and the rest of the file as they were.
+pub fn uninstall_from_config(
+    target: &ClientTarget,
+    options: &InstallOptions,
+) -> Result<ConfigRemoval<'static>> {
+    let name = target.name;
+    let config_path = config_path_for(target, options)?;
+    if !config_path.exists() {
+        return Ok(ConfigRemoval::NotPresent(name));
+    }
+    let config_file = File::open(&config_path).map_err(|error| {
+        eyre!(
+            "Could not open {name} config file at {config_path}: {error:#?}",
+            name = name,
+            config_path = config_path.display()
+        )
+    })?;
+    let mut config: serde_json::Map<String, Value> =
+        serde_json::from_reader(BufReader::new(config_file)).map_err(|e| {
+            eyre!(
+                "Could not parse JSON from {name} config file at {config_path}: {e:#?}",
+                name = name,
+                config_path = config_path.display()
+            )
+        })?;
+
+    let removed = config
+        .get_mut(target.servers_key)
+        .and_then(Value::as_object_mut)
+        .and_then(|servers| servers.shift_remove("Roblox Studio"))
+        .is_some();
+    if !removed {
+        return Ok(ConfigRemoval::NotPresent(name));
+    }
+    if options.dry_run {
+        say!(
+            "DRY RUN: would remove the Roblox Studio MCP server from {} ({}), leaving:",
+            name,
+            config_path.display()
+        );
+        say!("{}", options.config_style.serialize(&config)?);
+        return Ok(ConfigRemoval::Removed);
+    }
+
+    let mut file = File::create(&config_path).map_err(|e| {
+        eyre!(
+            "Could not open {name} config file for writing at {config_path}: {e:#?}",
+            name = name,
+            config_path = config_path.display()
+        )
+    })?;
+    file.write_all(options.config_style.serialize(&config)?.as_bytes())
+        .map_err(|e| {
+            eyre!(
+                "Could not write to {name} config file at {config_path}: {e:#?}",
+                name = name,
+                config_path = config_path.display()
+            )
+        })?;
+    say!(
+        "INFO: Removed the Roblox Studio MCP server from {} ({}).",
+        name,
+        config_path.display()
+    );
+    Ok(ConfigRemoval::Removed)
+}
+
+/// Reverses `install`: deletes the plugin file and the client config entries. Anything already
+/// gone is reported and skipped, and a step that fails doesn't stop the others.
+pub async fn uninstall(options: InstallOptions) -> Result<()> {
+    // Every client is checked unless --clients narrows it; ones without an entry are skipped.
+    let targets: Vec<&ClientTarget> = if options.clients.is_empty() {
+        CLIENTS.iter().collect()
+    } else {
+        selected_clients(&options)
+    };
+    uninstall_from(&options, &targets)
+}
+
+// `uninstall` for the given clients.
+fn uninstall_from(options: &InstallOptions, targets: &[&ClientTarget]) -> Result<()> {
+    let mut errors: Vec<color_eyre::Report> = uninstall_plugin(options).err().into_iter().collect();
+    for target in targets {
+        match uninstall_from_config(target, options) {
+            Ok(ConfigRemoval::Removed) => {}
+            Ok(ConfigRemoval::NotPresent(name)) => {
+                say!("INFO: {} has no Roblox Studio MCP server configured.", name)
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        let report = errors.into_iter().fold(
+            eyre!("Some parts of Roblox Studio MCP could not be removed."),
+            |report, e| report.note(e),
+        );
+        return Err(report.wrap_err("Uninstall incomplete"));
+    }
+    say!();
+    if options.dry_run {
+        say!("Dry run: nothing was changed.");
+    } else {
+        say!("Roblox Studio MCP has been uninstalled. Restart Studio and any MCP clients to apply the changes.");
+    }
+    Ok(())
+}
+
+fn uninstall_plugin(options: &InstallOptions) -> Result<()> {
+    let plugin_path = get_plugins_dir(options)?.join("MCPStudioPlugin.rbxm");
+    if options.dry_run {
+        if plugin_path.exists() {
+            say!(
+                "DRY RUN: would remove Roblox Studio plugin {}",
+                plugin_path.display()
+            );
+        } else {
+            say!("INFO: No plugin installed at {}", plugin_path.display());
+        }
+        return Ok(());
+    }
+    match fs::remove_file(&plugin_path) {
+        Ok(()) => say!(
+            "INFO: Removed Roblox Studio plugin {}",
+            plugin_path.display()
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            say!("INFO: No plugin installed at {}", plugin_path.display())
+        }
+        Err(e) => {
+            return Err(e).wrap_err_with(|| {
+                format!(
+                    "Could not remove Roblox Plugin file at {}",
+                    plugin_path.display()
+                )
+            })
+        }
+    }
+    Ok(())
+}
+
+// Names of the clients `install_to_config` configured, or the collected causes when none was.
+#[cfg(not(feature = "gemini_python_broker"))]
+fn configured_clients<'a>(
+    results: Vec<Result<ConfigWrite<'a>>>,
+    options: &InstallOptions,
+) -> Result<Vec<&'a str>> {
+    let mut errors = vec![];
+    let mut updated = vec![];
+    let mut kept = vec![];
+    for result in results {
+        match result {
+            Ok(ConfigWrite::Updated(name)) => updated.push(name),
+            Ok(ConfigWrite::Kept(name)) => kept.push(name),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    // Entries kept by --keep-existing-path count as configured, except in strict mode
+    // where the point is to prove this install wrote something.
+    if updated.is_empty() && (kept.is_empty() || options.require_client) {
+        let headline = if options.require_client {
+            "--require-client: no client config was updated."
+        } else {
+            "Failed to configure integration for any MCP client."
+        };
+        let error_detail = errors
+            .into_iter()
+            .fold(eyre!(headline), |report, e| report.note(e));
+        let error_detail = kept.iter().fold(error_detail, |report, name| {
+            report.note(eyre!(
+                "{} was left unchanged because of --keep-existing-path",
+                name
+            ))
+        });
+        return Err(error_detail.wrap_err("MCP Server setup for external AI tools failed"));
+    }
+    Ok(updated.into_iter().chain(kept).collect())
+}
+
+// The plugin's Config module: how Studio reaches the server this install configures.
+fn plugin_config_source(options: &InstallOptions) -> String {
+    let mut fields = vec![("port", options.port.to_string())];
+    if let Some(token) = &options.auth_token {
+        fields.push(("auth_token", luau::string(token)));
+    }
+    format!(
+        "-- Written by the installer.\nreturn {}\n",
+        luau::table(&fields)
+    )
+}
+
+// The bundled plugin with its Config module rewritten for `options`. Plugins can't be given
+// settings from outside Studio, so the installer bakes them into the model instead.
+fn configured_plugin(plugin_bytes: &[u8], options: &InstallOptions) -> Result<Vec<u8>> {
+    let mut dom =
+        rbx_binary::from_reader(plugin_bytes).wrap_err("Could not read the bundled plugin")?;
+    let config = dom
+        .descendants()
+        .find(|instance| instance.name == "Config" && instance.class == "ModuleScript")
+        .map(|instance| instance.referent())
+        .ok_or_else(|| eyre!("The bundled plugin has no Config module"))?;
+    dom.get_by_ref_mut(config).unwrap().properties.insert(
+        ustr("Source"),
+        Variant::String(plugin_config_source(options)),
+    );
+    let mut bytes = Vec::new();
+    rbx_binary::to_writer(&mut bytes, &dom, dom.root().children())
+        .wrap_err("Could not write the configured plugin")?;
+    Ok(bytes)
+}
+
+async fn install_internal(options: &InstallOptions) -> Result<String> {
     // Part 1: Install MCPStudioPlugin.rbxm (Always runs)
-    let plugin_bytes = include_bytes!(concat!(env!("OUT_DIR"), "/MCPStudioPlugin.rbxm"));
-    let studio = RobloxStudio::locate()?;
-    let plugins_dir_path = studio.plugins_path(); // Renamed for clarity from 'plugins'
-    if let Err(err) = fs::create_dir_all(&plugins_dir_path) { // Ensure parent dir for plugins exists
-        // Note: create_dir_all doesn't error if path already exists and is a directory.
-        // We only need to check if it's NOT ErrorKind::AlreadyExists if we were using fs::create_dir
-        // For create_dir_all, any error is problematic.
-        return Err(err).wrap_err("Failed to create Roblox Studio plugins directory");
-    }
-    let output_plugin_path = plugins_dir_path.join("MCPStudioPlugin.rbxm"); // Renamed for clarity
-    {
+    let plugin_bytes = configured_plugin(
+        include_bytes!(concat!(env!("OUT_DIR"), "/MCPStudioPlugin.rbxm")),
+        options,
+    )?;
+    let plugins_dir_path = get_plugins_dir(options)?;
+    let output_plugin_path = plugins_dir_path.join("MCPStudioPlugin.rbxm");
+    if options.dry_run {
+        say!(
+            "DRY RUN: would install Roblox Studio plugin ({} bytes) to {}",
+            plugin_bytes.len(),
+            output_plugin_path.display()
+        );
+    } else {
+        if let Err(err) = fs::create_dir_all(&plugins_dir_path) {
+            // Ensure parent dir for plugins exists
+            // Note: create_dir_all doesn't error if path already exists and is a directory.
+            // We only need to check if it's NOT ErrorKind::AlreadyExists if we were using fs::create_dir
+            // For create_dir_all, any error is problematic.
+            return Err(err).wrap_err("Failed to create Roblox Studio plugins directory");
+        }
         let mut file = File::create(&output_plugin_path).wrap_err_with(|| {
             format!(
                 "Could not write Roblox Plugin file at {}",
                 output_plugin_path.display()
             )
         })?;
-        file.write_all(plugin_bytes)?;
+        file.write_all(&plugin_bytes)?;
+        say!(
+            "INFO: Installed Roblox Studio plugin to {}",
+            output_plugin_path.display()
+        );
     }
-    println!(
-        "INFO: Installed Roblox Studio plugin to {}",
-        output_plugin_path.display()
-    );
 
     // Part 2: Conditional Logic based on feature flag
     #[cfg(not(feature = "gemini_python_broker"))]
     {
         // Original logic for Claude/Cursor integration
         let this_exe = get_exe_path()?;
-        let mut errors = vec![];
-        let results = vec![
-            install_to_config(get_claude_config(), &this_exe, "Claude"),
-            install_to_config(get_cursor_config(), &this_exe, "Cursor"),
-        ];
-        let successes: Vec<_> = results
+        let results = selected_clients(options)
             .into_iter()
-            .filter_map(|r| r.map_err(|e| errors.push(e)).ok())
+            .map(|target| install_to_config(target, &this_exe, options))
             .collect();
+        let successes = configured_clients(results, options)?;
 
-        if successes.is_empty() {
-            let error_detail = errors.into_iter().fold(
-                eyre!("Failed to configure integration for either Claude or Cursor."),
-                |report, e| report.note(e),
+        say!();
+        if options.dry_run {
+            let msg = format!(
+                "Dry run: nothing was written. These clients would be configured:\n{}",
+                successes.join("\n")
             );
-            return Err(error_detail.wrap_err("MCP Server setup for external AI tools failed"));
+            say!("{}", msg);
+            return Ok(msg);
         }
-
-        println!();
         let msg = get_message_claude_cursor(successes.join("\n"));
-        println!("{}", msg);
+        say!("{}", msg);
         Ok(msg)
     }
 
     #[cfg(feature = "gemini_python_broker")]
     {
         // New logic for Gemini Python Broker mode
-        println!();
+        if options.require_client {
+            return Err(eyre!("--require-client: this build (gemini_python_broker) does not write client configs."));
+        }
+        say!();
         let msg = get_message_gemini_python_broker();
-        println!("{}", msg);
+        say!("{}", msg);
         Ok(msg)
     }
 }
 
 #[cfg(target_os = "windows")]
-pub async fn install() -> Result<()> {
+pub async fn install(options: InstallOptions) -> Result<()> {
     use std::process::Command;
-    if let Err(e) = install_internal().await {
+    let result = install_internal(&options).await;
+    if let Err(e) = &result {
         tracing::error!("Failed initialize Roblox MCP: {:#}", e);
     }
     let _ = Command::new("cmd.exe").arg("/c").arg("pause").status();
-    Ok(())
+    // Without --require-client a failure is reported but the exit code stays 0, as before.
+    match result {
+        Err(e) if options.require_client => Err(e),
+        _ => Ok(()),
+    }
 }
 
 #[cfg(target_os = "macos")]
-pub async fn install() -> Result<()> {
+pub async fn install(options: InstallOptions) -> Result<()> {
     use native_dialog::{DialogBuilder, MessageLevel};
-    let alert_builder = match install_internal().await {
+    let result = install_internal(&options).await;
+    let alert_builder = match &result {
         Err(e) => DialogBuilder::message()
             .set_level(MessageLevel::Error)
             .set_text(format!("Errors occurred: {:#}", e)),
@@ -233,11 +871,394 @@ pub async fn install() -> Result<()> {
             .set_text(msg),
     };
     let _ = alert_builder.set_title("Roblox Studio MCP").alert().show();
-    Ok(())
+    match result {
+        Err(e) if options.require_client => Err(e),
+        _ => Ok(()),
+    }
 }
 
 #[cfg(not(any(target_os = "macos", target_os = "windows")))]
-pub async fn install() -> Result<()> {
-    install_internal().await?;
+pub async fn install(options: InstallOptions) -> Result<()> {
+    install_internal(&options).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh, empty folder for one test, under the system temp dir.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rbx-studio-mcp-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A config file in a fresh folder; tests point `config_path_override` at it.
+    fn test_config(name: &str) -> PathBuf {
+        test_dir(name).join("config.json")
+    }
+
+    fn missing_config_path() -> Result<PathBuf> {
+        Err(eyre!("Could not find the config directory"))
+    }
+
+    // A client with no config of its own, so tests only write where the options say.
+    fn test_target() -> ClientTarget {
+        ClientTarget {
+            client: Client::Claude,
+            name: "Test client",
+            config_path: missing_config_path,
+            servers_key: "mcpServers",
+            server_entry: stdio_entry,
+            always: true,
+        }
+    }
+
+    fn read_config(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn uninstall_removes_only_the_roblox_studio_entry() {
+        let config_path = test_config("uninstall");
+        fs::write(
+            &config_path,
+            r#"{"mcpServers": {"Other": {"command": "other"}}, "theme": "dark"}"#,
+        )
+        .unwrap();
+        let target = test_target();
+        let plugins_dir = test_dir("uninstall-plugins");
+        fs::write(plugins_dir.join("MCPStudioPlugin.rbxm"), b"plugin").unwrap();
+        let options = InstallOptions {
+            plugins_dir: Some(plugins_dir.clone()),
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+
+        install_to_config(&target, Path::new("/opt/rbx-studio-mcp"), &options).unwrap();
+        assert!(read_config(&config_path)["mcpServers"]
+            .get("Roblox Studio")
+            .is_some());
+        uninstall_from(&options, &[&target]).unwrap();
+
+        let config = read_config(&config_path);
+        assert!(config["mcpServers"].get("Roblox Studio").is_none());
+        assert_eq!(config["mcpServers"]["Other"]["command"], "other");
+        assert_eq!(config["theme"], "dark");
+        assert!(!plugins_dir.join("MCPStudioPlugin.rbxm").exists());
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[test]
+    fn uninstall_still_cleans_configs_when_the_plugin_cannot_be_removed() {
+        let config_path = test_config("plugin-failure-config");
+        fs::write(
+            &config_path,
+            r#"{"mcpServers": {"Roblox Studio": {"command": "x"}, "Other": {}}}"#,
+        )
+        .unwrap();
+        // A plugins "folder" that is a file makes removing the plugin fail with something other than NotFound.
+        let not_a_dir = test_dir("plugin-failure").join("Plugins");
+        fs::write(&not_a_dir, b"").unwrap();
+        let options = InstallOptions {
+            plugins_dir: Some(not_a_dir),
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+
+        // Notes only show in a report's Debug output once color_eyre's handler is installed.
+        let _ = color_eyre::install();
+        let error = uninstall_from(&options, &[&test_target()]).unwrap_err();
+        assert!(
+            format!("{:?}", error).contains("Could not remove Roblox Plugin file"),
+            "{:?}",
+            error
+        );
+        let config = read_config(&config_path);
+        assert!(config["mcpServers"].get("Roblox Studio").is_none());
+        assert!(config["mcpServers"].get("Other").is_some());
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[test]
+    fn an_entry_for_another_build_is_detected_and_kept_on_request() {
+        let config_path = test_config("other-build");
+        let existing = r#"{"mcpServers": {"Roblox Studio": {"command": "/old/rbx-studio-mcp", "args": ["--stdio"]}}}"#;
+        fs::write(&config_path, existing).unwrap();
+        let config: serde_json::Map<String, Value> = serde_json::from_str(existing).unwrap();
+        assert_eq!(
+            existing_server_command(&config, "mcpServers").as_deref(),
+            Some("/old/rbx-studio-mcp")
+        );
+        assert_eq!(existing_server_command(&config, "servers"), None);
+
+        let target = test_target();
+        let new_exe = Path::new("/new/rbx-studio-mcp");
+        let replace = InstallOptions {
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+        let keep = InstallOptions {
+            keep_existing_path: true,
+            ..replace.clone()
+        };
+        let write = install_to_config(&target, new_exe, &keep).unwrap();
+        assert!(matches!(write, ConfigWrite::Kept(_)), "{:?}", write);
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), existing);
+
+        let write = install_to_config(&target, new_exe, &replace).unwrap();
+        assert!(matches!(write, ConfigWrite::Updated(_)), "{:?}", write);
+        assert_eq!(
+            read_config(&config_path)["mcpServers"]["Roblox Studio"]["command"],
+            "/new/rbx-studio-mcp"
+        );
+        // The same build again is not a different path, so it's rewritten even with the flag.
+        let write = install_to_config(&target, new_exe, &keep).unwrap();
+        assert!(matches!(write, ConfigWrite::Updated(_)), "{:?}", write);
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[test]
+    fn compact_style_writes_one_line_without_whitespace() {
+        let config_path = test_config("compact");
+        fs::write(
+            &config_path,
+            "{\n  \"theme\": \"dark\",\n  \"mcpServers\": {\n    \"Other\": { \"command\": \"other\" }\n  }\n}",
+        )
+        .unwrap();
+        let options = InstallOptions {
+            config_style: ConfigStyle::Compact,
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+        install_to_config(&test_target(), Path::new("/opt/rbx-studio-mcp"), &options).unwrap();
+
+        let written = fs::read_to_string(&config_path).unwrap();
+        // The only space left is the one in the "Roblox Studio" key.
+        assert_eq!(
+            written.matches(char::is_whitespace).count(),
+            1,
+            "{}",
+            written
+        );
+        assert_eq!(
+            written,
+            r#"{"theme":"dark","mcpServers":{"Other":{"command":"other"},"Roblox Studio":{"command":"/opt/rbx-studio-mcp","args":["--stdio"]}}}"#
+        );
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[cfg(not(feature = "gemini_python_broker"))]
+    #[test]
+    fn require_client_fails_when_no_config_could_be_written() {
+        let _ = color_eyre::install();
+        let exe = Path::new("/opt/rbx-studio-mcp");
+        let missing = [test_target(), test_target()];
+        let attempt = |options: &InstallOptions| {
+            let results = missing
+                .iter()
+                .map(|target| install_to_config(target, exe, options))
+                .collect();
+            configured_clients(results, options)
+        };
+
+        let strict = InstallOptions {
+            require_client: true,
+            ..InstallOptions::default()
+        };
+        let error = format!("{:?}", attempt(&strict).unwrap_err());
+        assert!(
+            error.contains("--require-client: no client config was updated."),
+            "{}",
+            error
+        );
+        assert_eq!(
+            error.matches("Could not find the config directory").count(),
+            2,
+            "{}",
+            error
+        );
+        // Without the flag it is still a failure when nothing at all was configured.
+        let error = format!("{:?}", attempt(&InstallOptions::default()).unwrap_err());
+        assert!(
+            error.contains("Failed to configure integration for any MCP client."),
+            "{}",
+            error
+        );
+
+        // A kept entry is enough normally, but not in strict mode.
+        let kept = || vec![Ok(ConfigWrite::Kept("Test client"))];
+        assert_eq!(
+            configured_clients(kept(), &InstallOptions::default()).unwrap(),
+            ["Test client"]
+        );
+        let error = format!("{:?}", configured_clients(kept(), &strict).unwrap_err());
+        assert!(
+            error.contains("Test client was left unchanged because of --keep-existing-path"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn console_text_falls_back_to_ascii() {
+        assert!(matches!(
+            sanitize_console_text("INFO: plain text"),
+            std::borrow::Cow::Borrowed("INFO: plain text")
+        ));
+        assert_eq!(
+            sanitize_console_text(
+                "\u{201C}Claude\u{201D} \u{2013} it\u{2019}s ready\u{00A0}\u{2705}"
+            ),
+            "\"Claude\" - it's ready ?"
+        );
+    }
+
+    #[test]
+    fn each_client_gets_the_entry_shape_it_expects() {
+        let config_path = test_config("client-shape");
+        let exe = Path::new("/opt/rbx-studio-mcp");
+        let stdio = json!({ "command": exe, "args": ["--stdio", "--port", "45000"] });
+        let typed =
+            json!({ "type": "stdio", "command": exe, "args": ["--stdio", "--port", "45000"] });
+        let expected = [
+            (Client::Claude, "mcpServers", &stdio),
+            (Client::Cursor, "mcpServers", &stdio),
+            (Client::Vscode, "servers", &typed),
+            (Client::Continue, "mcpServers", &stdio),
+            (Client::Windsurf, "mcpServers", &stdio),
+        ];
+        assert_eq!(CLIENTS.len(), expected.len());
+        let options = InstallOptions {
+            port: 45000,
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+        for (client, servers_key, entry) in expected {
+            let real = CLIENTS
+                .iter()
+                .find(|target| target.client == client)
+                .unwrap();
+            // The client's own shape, written to the override file instead of its real config.
+            let target = ClientTarget {
+                client,
+                servers_key: real.servers_key,
+                server_entry: real.server_entry,
+                ..test_target()
+            };
+            fs::write(&config_path, r#"{"other": true}"#).unwrap();
+            install_to_config(&target, exe, &options).unwrap();
+
+            let config = read_config(&config_path);
+            assert_eq!(
+                config,
+                json!({ "other": true, servers_key: { "Roblox Studio": entry } }),
+                "{:?}",
+                client
+            );
+        }
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    #[test]
+    fn dry_run_leaves_configs_and_folders_untouched() {
+        let config_path = test_config("dry-run");
+        let original = r#"{"mcpServers": {"Roblox Studio": {"command": "/old/build"}}}"#;
+        fs::write(&config_path, original).unwrap();
+        let plugins_dir = test_dir("dry-run-plugins");
+        fs::write(plugins_dir.join("MCPStudioPlugin.rbxm"), b"plugin").unwrap();
+        let options = InstallOptions {
+            dry_run: true,
+            plugins_dir: Some(plugins_dir.clone()),
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+        let exe = Path::new("/opt/rbx-studio-mcp");
+
+        let target = test_target();
+        assert!(matches!(
+            install_to_config(&target, exe, &options).unwrap(),
+            ConfigWrite::Updated("Test client")
+        ));
+        uninstall_from(&options, &[&target]).unwrap();
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), original);
+        assert!(plugins_dir.join("MCPStudioPlugin.rbxm").exists());
+
+        let missing = test_dir("dry-run-missing")
+            .join("Client")
+            .join("config.json");
+        let options = InstallOptions {
+            config_path_override: Some(missing.clone()),
+            ..options
+        };
+        install_to_config(&target, exe, &options).unwrap();
+        assert!(
+            !missing.parent().unwrap().exists(),
+            "a dry run creates no folders"
+        );
+        fs::remove_file(&config_path).unwrap();
+    }
+
+    // The Config module's source in an installed plugin.
+    fn installed_config(options: &InstallOptions) -> String {
+        let bundled = include_bytes!(concat!(env!("OUT_DIR"), "/MCPStudioPlugin.rbxm"));
+        let plugin = configured_plugin(bundled, options).unwrap();
+        let dom = rbx_binary::from_reader(plugin.as_slice()).unwrap();
+        assert!(
+            dom.descendants()
+                .any(|instance| instance.name == "Main" && instance.class == "Script"),
+            "the rest of the plugin is kept"
+        );
+        let config = dom
+            .descendants()
+            .find(|instance| instance.name == "Config")
+            .unwrap();
+        match config.properties.get(&ustr("Source")) {
+            Some(Variant::String(source)) => source.clone(),
+            other => panic!("Config has no source: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_installed_plugin_connects_on_the_configured_port() {
+        let options = InstallOptions {
+            port: 45000,
+            ..InstallOptions::default()
+        };
+        assert_eq!(
+            installed_config(&options),
+            "-- Written by the installer.\nreturn { port = 45000 }\n"
+        );
+        assert!(installed_config(&InstallOptions::default()).contains("port = 44755"));
+    }
+
+    #[test]
+    fn an_auth_token_reaches_the_plugin_and_the_client_config() {
+        let config_path = test_config("auth-token");
+        let options = InstallOptions {
+            auth_token: Some("s3cret \"quoted\"".to_string()),
+            config_path_override: Some(config_path.clone()),
+            ..InstallOptions::default()
+        };
+        assert_eq!(
+            installed_config(&options),
+            "-- Written by the installer.\nreturn { port = 44755, auth_token = \"s3cret \\\"quoted\\\"\" }\n"
+        );
+        install_to_config(&test_target(), Path::new("/opt/rbx-studio-mcp"), &options).unwrap();
+        assert_eq!(
+            read_config(&config_path)["mcpServers"]["Roblox Studio"]["args"],
+            json!(["--stdio", "--auth-token", "s3cret \"quoted\""])
+        );
+
+        // The plugin sends Config's token with every request.
+        let main = fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("plugin/src/Main.server.luau"),
+        )
+        .unwrap();
+        assert!(main.contains("local AUTH_TOKEN = Config.auth_token"));
+        assert!(main.contains(r#"headers["Authorization"] = "Bearer " .. AUTH_TOKEN"#));
+        fs::remove_file(&config_path).unwrap();
+    }
+}
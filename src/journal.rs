@@ -0,0 +1,132 @@
+// journal.rs - write-ahead record of queued tasks so they survive a server restart (`--queue-journal`)
+
+use crate::rbx_studio_server::ToolArguments;
+use color_eyre::eyre::{Result, WrapErr};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tracing::warn;
+use uuid::Uuid;
+
+/// One line of the journal.
+#[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JournalRecord {
+    /// The task was accepted for dispatch.
+    Queued(ToolArguments),
+    /// A plugin took the task. It may already have changed the place, so it is never replayed.
+    Delivered(Uuid),
+    /// The task resolved: a result arrived, or it timed out or was cancelled.
+    Completed(Uuid),
+}
+
+/// Append-only journal owned by the `StateManager`. Opening it compacts the file down to the
+/// tasks that were queued but never delivered, which are handed back for re-queueing.
+pub struct QueueJournal {
+    file: File,
+}
+
+impl QueueJournal {
+    pub fn open(path: &Path) -> Result<(Self, Vec<ToolArguments>)> {
+        let outstanding = match fs::read_to_string(path) {
+            Ok(contents) => outstanding_tasks(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e)
+                    .wrap_err_with(|| format!("Could not read queue journal {}", path.display()))
+            }
+        };
+        let mut compacted = String::new();
+        for task in &outstanding {
+            compacted.push_str(&record_line(&JournalRecord::Queued(task.clone())));
+        }
+        fs::write(path, compacted)
+            .wrap_err_with(|| format!("Could not rewrite queue journal {}", path.display()))?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .wrap_err_with(|| format!("Could not open queue journal {}", path.display()))?;
+        Ok((Self { file }, outstanding))
+    }
+
+    pub fn record_queued(&mut self, task: &ToolArguments) {
+        self.append(&JournalRecord::Queued(task.clone()));
+    }
+
+    pub fn record_delivered(&mut self, task_id: Uuid) {
+        self.append(&JournalRecord::Delivered(task_id));
+    }
+
+    pub fn record_completed(&mut self, task_id: Uuid) {
+        self.append(&JournalRecord::Completed(task_id));
+    }
+
+    // Write failures are logged rather than failing the task; the journal is a safety net.
+    fn append(&mut self, record: &JournalRecord) {
+        if let Err(e) = self
+            .file
+            .write_all(record_line(record).as_bytes())
+            .and_then(|_| self.file.flush())
+        {
+            warn!(target: "state_manager", "Failed to write queue journal record: {}", e);
+        }
+    }
+}
+
+fn record_line(record: &JournalRecord) -> String {
+    let mut line = serde_json::to_string(record).unwrap_or_default();
+    line.push('\n');
+    line
+}
+
+// Tasks with a `queued` line and neither a `delivered` nor a `completed` one, in queue order.
+// Unreadable lines (e.g. one cut short by a crash) are skipped.
+fn outstanding_tasks(contents: &str) -> Vec<ToolArguments> {
+    let mut queued = Vec::new();
+    let mut settled = HashSet::new();
+    for (index, line) in contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+    {
+        match serde_json::from_str::<JournalRecord>(line) {
+            Ok(JournalRecord::Queued(task)) => queued.push(task),
+            Ok(JournalRecord::Delivered(task_id) | JournalRecord::Completed(task_id)) => {
+                settled.insert(task_id);
+            }
+            Err(e) => {
+                warn!(target: "state_manager", "Skipping unreadable queue journal line {}: {}", index + 1, e)
+            }
+        }
+    }
+    queued.retain(|task| task.id().is_some_and(|task_id| !settled.contains(&task_id)));
+    queued
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rbx_studio_server::ToolArgumentValues;
+
+    #[test]
+    fn only_tasks_never_delivered_or_completed_are_outstanding() {
+        let tasks: Vec<(ToolArguments, Uuid)> = (0..4)
+            .map(|_| ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {}))
+            .collect();
+        let mut contents = String::new();
+        for (task, _) in &tasks {
+            contents.push_str(&record_line(&JournalRecord::Queued(task.clone())));
+        }
+        contents.push_str(&record_line(&JournalRecord::Delivered(tasks[0].1)));
+        contents.push_str("not json\n\n");
+        contents.push_str(&record_line(&JournalRecord::Completed(tasks[2].1)));
+
+        let outstanding: Vec<Uuid> = outstanding_tasks(&contents)
+            .iter()
+            .filter_map(ToolArguments::id)
+            .collect();
+        assert_eq!(outstanding, [tasks[1].1, tasks[3].1]);
+    }
+}
@@ -0,0 +1,135 @@
+// json_repair.rs - best-effort normalization of non-standard JSON from Luau encoders (`--lenient-json`)
+
+/// Rewrites the quirks hand-rolled Luau JSON encoders emit into standard JSON:
+/// `NaN`/`Infinity`/`-Infinity` become `null`, unquoted object keys are quoted,
+/// and trailing commas before `}`/`]` are dropped. String contents are never
+/// touched. Returns `None` when nothing needed repairing.
+pub fn repair_json(input: &str) -> Option<String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::with_capacity(input.len());
+    let mut changed = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' => {
+                let end = string_end(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            ',' => {
+                let next = next_non_whitespace(&chars, i + 1);
+                if matches!(next.map(|j| chars[j]), Some('}') | Some(']')) {
+                    changed = true;
+                } else {
+                    out.push(',');
+                }
+                i += 1;
+            }
+            '-' if starts_with_word(&chars, i + 1, "Infinity") => {
+                out.push_str("null");
+                changed = true;
+                i += 1 + "Infinity".len();
+            }
+            c if c.is_ascii_alphabetic() || c == '_' || c == '$' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let is_key = next_non_whitespace(&chars, i).map(|j| chars[j]) == Some(':');
+                if is_key {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                    changed = true;
+                } else if word == "NaN" || word == "Infinity" {
+                    out.push_str("null");
+                    changed = true;
+                } else {
+                    out.push_str(&word);
+                }
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    changed.then_some(out)
+}
+
+// Index just past the closing quote of the string starting at `start`.
+fn string_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+fn next_non_whitespace(chars: &[char], from: usize) -> Option<usize> {
+    (from..chars.len()).find(|&j| !chars[j].is_whitespace())
+}
+
+fn starts_with_word(chars: &[char], at: usize, word: &str) -> bool {
+    let word: Vec<char> = word.chars().collect();
+    chars.len() >= at + word.len() && chars[at..at + word.len()] == word[..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rmcp::model::CallToolResult;
+
+    fn parse_repaired(body: &str) -> CallToolResult {
+        assert!(
+            serde_json::from_str::<CallToolResult>(body).is_err(),
+            "{}",
+            body
+        );
+        serde_json::from_str(&repair_json(body).expect("repair applied")).unwrap()
+    }
+
+    #[test]
+    fn infinity_becomes_null() {
+        let result = parse_repaired(
+            r#"{"content":[{"type":"text","text":"Infinity stays in strings"}],"isError":false,"ratio":-Infinity,"other":NaN}"#,
+        );
+        assert_eq!(
+            result.content[0].as_text().unwrap().text,
+            "Infinity stays in strings"
+        );
+        assert_eq!(
+            repair_json(r#"[Infinity, -Infinity, NaN]"#).unwrap(),
+            "[null, null, null]"
+        );
+    }
+
+    #[test]
+    fn trailing_commas_are_dropped() {
+        let result = parse_repaired(
+            "{\"content\": [{\"type\": \"text\", \"text\": \"a,]\"},\n],\"isError\": true,}",
+        );
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(result.content[0].as_text().unwrap().text, "a,]");
+    }
+
+    #[test]
+    fn unquoted_keys_are_quoted_and_valid_json_is_left_alone() {
+        assert_eq!(
+            repair_json(r#"{isError: false, content: []}"#).unwrap(),
+            r#"{"isError": false, "content": []}"#
+        );
+        assert_eq!(repair_json(r#"{"content": [], "isError": false}"#), None);
+    }
+}
@@ -0,0 +1,374 @@
+// luau.rs - helpers for emitting Luau source that the plugin `loadstring`s
+
+/// Encodes `value` as a double-quoted Luau string literal.
+/// Quotes, backslashes and control characters are escaped, so the result is
+/// always a single well-formed token regardless of what the caller passed in.
+pub fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_ascii_control() => out.push_str(&format!("\\{:03}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encodes `value` as a long-bracket literal (`[[...]]`, `[=[...]=]`, ...) at the lowest
+/// level whose closing bracket can't occur inside it, so Luau source passes through
+/// verbatim. Falls back to `string` when `value` starts with a line break, which the
+/// lexer would drop after the opening bracket.
+pub fn long_string(value: &str) -> String {
+    if value.starts_with(['\n', '\r']) {
+        return string(value);
+    }
+    let level = (0..)
+        .find(|&level| {
+            let close = format!("]{}]", "=".repeat(level));
+            // A trailing `]=..=` would join the closing bracket's own `]` into an early close.
+            !value.contains(&close) && !value.ends_with(&close[..close.len() - 1])
+        })
+        .expect("some bracket level is always free");
+    let equals = "=".repeat(level);
+    format!("[{equals}[{value}]{equals}]")
+}
+
+/// Encodes an optional string, emitting `nil` when absent.
+pub fn optional_string(value: Option<&str>) -> String {
+    value.map_or_else(|| "nil".to_string(), string)
+}
+
+/// Builds a Luau table constructor from `key = value` pairs. Values must
+/// already be encoded Luau expressions; keys must be plain identifiers.
+pub fn table(fields: &[(&str, String)]) -> String {
+    if fields.is_empty() {
+        return "{}".to_string();
+    }
+    let body = fields
+        .iter()
+        .map(|(key, value)| format!("{} = {}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{{ {} }}", body)
+}
+
+/// Encodes a list of strings as a Luau array table.
+pub fn string_list(values: &[String]) -> String {
+    if values.is_empty() {
+        return "{}".to_string();
+    }
+    let items = values.iter().map(|value| string(value)).collect::<Vec<_>>();
+    format!("{{ {} }}", items.join(", "))
+}
+
+/// Builds a Luau array table from already-encoded Luau expressions.
+pub fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        return "{}".to_string();
+    }
+    format!("{{ {} }}", items.join(", "))
+}
+
+/// Builds a Luau table constructor with arbitrary string keys (`["key"] = value`).
+/// Values must already be encoded Luau expressions.
+pub fn string_keyed_table(fields: &[(String, String)]) -> String {
+    if fields.is_empty() {
+        return "{}".to_string();
+    }
+    let body = fields
+        .iter()
+        .map(|(key, value)| format!("[{}] = {}", string(key), value))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{{ {} }}", body)
+}
+
+/// Encodes a JSON value as a Luau expression. `null` becomes `nil`, arrays
+/// become array tables, and objects become string-keyed tables, except that an
+/// object with a `"$type"` key is built as that Roblox datatype, e.g.
+/// `{"$type": "Vector3", "x": 1, "y": 2, "z": 3}` becomes `Vector3.new(1, 2, 3)`.
+pub fn from_json(value: &serde_json::Value) -> Result<String, String> {
+    use serde_json::Value;
+    match value {
+        Value::Null => Ok("nil".to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(string(s)),
+        Value::Array(items) => {
+            let items = items.iter().map(from_json).collect::<Result<Vec<_>, _>>()?;
+            Ok(if items.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", items.join(", "))
+            })
+        }
+        Value::Object(map) => match map.get("$type") {
+            Some(Value::String(type_name)) => tagged_datatype(type_name, map),
+            Some(_) => Err("'$type' must be a string naming a Roblox datatype.".to_string()),
+            None => {
+                let fields = map
+                    .iter()
+                    .map(|(key, value)| Ok(format!("[{}] = {}", string(key), from_json(value)?)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                Ok(if fields.is_empty() {
+                    "{}".to_string()
+                } else {
+                    format!("{{ {} }}", fields.join(", "))
+                })
+            }
+        },
+    }
+}
+
+/// Encodes a JSON value to assign to an instance property. Like `from_json`, plus untagged
+/// objects whose keys are exactly `x, y, z` (numbers) become `Vector3.new(..)`, `x, y` become
+/// `Vector2.new(..)` and `r, g, b` (0-1) become `Color3.new(..)`. Other objects and arrays are
+/// encoded as by `from_json`, and strings stay strings (the plugin resolves `"Enum.X.Y"`).
+/// `null` is rejected: a property can't be cleared this way.
+pub fn property_value(value: &serde_json::Value) -> Result<String, String> {
+    use serde_json::Value;
+    match value {
+        Value::Null => Err("null can't be assigned to a property.".to_string()),
+        Value::Object(map) if !map.contains_key("$type") => {
+            let mut keys: Vec<&str> = map.keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            let inferred = match keys.as_slice() {
+                ["x", "y", "z"] => Some("Vector3"),
+                ["x", "y"] => Some("Vector2"),
+                ["b", "g", "r"] => Some("Color3"),
+                _ => None,
+            };
+            match inferred {
+                Some(type_name) if map.values().all(Value::is_number) => {
+                    tagged_datatype(type_name, map)
+                }
+                _ => from_json(value),
+            }
+        }
+        _ => from_json(value),
+    }
+}
+
+fn tagged_datatype(
+    type_name: &str,
+    map: &serde_json::Map<String, serde_json::Value>,
+) -> Result<String, String> {
+    let number = |key: &str| -> Result<String, String> {
+        map.get(key)
+            .and_then(serde_json::Value::as_f64)
+            .map(|n| n.to_string())
+            .ok_or_else(|| format!("{} requires a numeric '{}' field.", type_name, key))
+    };
+    let numbers = |keys: &[&str]| -> Result<String, String> {
+        Ok(keys
+            .iter()
+            .map(|key| number(key))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", "))
+    };
+    match type_name {
+        "Vector3" => Ok(format!("Vector3.new({})", numbers(&["x", "y", "z"])?)),
+        "Vector2" => Ok(format!("Vector2.new({})", numbers(&["x", "y"])?)),
+        "Color3" => Ok(format!("Color3.new({})", numbers(&["r", "g", "b"])?)),
+        "UDim" => Ok(format!("UDim.new({})", numbers(&["scale", "offset"])?)),
+        "UDim2" => Ok(format!(
+            "UDim2.new({})",
+            numbers(&["x_scale", "x_offset", "y_scale", "y_offset"])?
+        )),
+        "NumberRange" => Ok(format!("NumberRange.new({})", numbers(&["min", "max"])?)),
+        // `components` is what `CFrame:GetComponents()` returns (position, then the rotation
+        // matrix by rows); without it only the position is set.
+        "CFrame" => match map.get("components") {
+            Some(components) => {
+                let components = components
+                    .as_array()
+                    .filter(|components| components.len() == 12)
+                    .and_then(|components| {
+                        components
+                            .iter()
+                            .map(|n| n.as_f64().map(|n| n.to_string()))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .ok_or_else(|| {
+                        "CFrame 'components' must be an array of 12 numbers.".to_string()
+                    })?;
+                Ok(format!("CFrame.new({})", components.join(", ")))
+            }
+            None => Ok(format!("CFrame.new({})", numbers(&["x", "y", "z"])?)),
+        },
+        "BrickColor" => match map.get("name").and_then(serde_json::Value::as_str) {
+            Some(name) => Ok(format!("BrickColor.new({})", string(name))),
+            None => Err("BrickColor requires a string 'name' field.".to_string()),
+        },
+        // Emitted as the `Enum.Type.Item` expression itself, so it must be exactly that shape.
+        "EnumItem" => match map.get("value").and_then(serde_json::Value::as_str) {
+            Some(value) if is_enum_item_path(value) => Ok(value.to_string()),
+            _ => Err("EnumItem requires a 'value' like \"Enum.Material.Plastic\".".to_string()),
+        },
+        // The instance at `path` (as `GetFullName` gives it) when the chunk runs, or `nil`.
+        "Instance" => match map.get("path").and_then(serde_json::Value::as_str) {
+            Some(path) => Ok(format!(
+                "(function(path) local current = game for name in string.gmatch(path, \"[^.]+\") do current = current and current:FindFirstChild(name) end return current end)({})",
+                string(path)
+            )),
+            None => Err("Instance requires a string 'path' field.".to_string()),
+        },
+        other => Err(format!("Unsupported '$type' '{}'.", other)),
+    }
+}
+
+fn is_enum_item_path(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('.').collect();
+    matches!(parts.as_slice(), ["Enum", enum_type, item]
+    if [enum_type, item].iter().all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn cframes_keep_their_rotation() {
+        let components: Vec<f64> = (1..=12).map(f64::from).collect();
+        assert_eq!(
+            from_json(
+                &json!({"$type": "CFrame", "x": 1, "y": 2, "z": 3, "components": components})
+            )
+            .unwrap(),
+            "CFrame.new(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)"
+        );
+        assert_eq!(
+            from_json(&json!({"$type": "CFrame", "x": 1, "y": 2.5, "z": -3})).unwrap(),
+            "CFrame.new(1, 2.5, -3)"
+        );
+        assert!(from_json(&json!({"$type": "CFrame", "components": [1, 2, 3]})).is_err());
+    }
+
+    #[test]
+    fn enum_items_and_instances_come_back_as_what_was_read() {
+        assert_eq!(
+            from_json(&json!({"$type": "EnumItem", "value": "Enum.Material.Plastic"})).unwrap(),
+            "Enum.Material.Plastic"
+        );
+        for value in [
+            "Enum.Material",
+            "Enum.Material.Plastic; os.exit()",
+            "Material.Plastic",
+        ] {
+            assert!(
+                from_json(&json!({"$type": "EnumItem", "value": value})).is_err(),
+                "{}",
+                value
+            );
+        }
+        let instance =
+            from_json(&json!({"$type": "Instance", "path": "Workspace.Door \"A\""})).unwrap();
+        assert!(instance.starts_with("(function(path)"), "{}", instance);
+        assert!(
+            instance.ends_with(r#")("Workspace.Door \"A\"")"#),
+            "{}",
+            instance
+        );
+    }
+
+    // Reads back a string literal the way the Luau lexer would, panicking unless `literal` is
+    // exactly one well-formed token.
+    fn read_literal(literal: &str) -> String {
+        if let Some(body) = literal.strip_prefix('"') {
+            let mut out = String::new();
+            let mut chars = body.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        assert!(chars.as_str().is_empty(), "trailing text in {}", literal);
+                        return out;
+                    }
+                    '\\' => match chars.next().unwrap() {
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        digit @ '0'..='9' => {
+                            let rest: String = chars.by_ref().take(2).collect();
+                            let code: u32 = format!("{}{}", digit, rest).parse().unwrap();
+                            out.push(char::from_u32(code).unwrap());
+                        }
+                        escaped => out.push(escaped),
+                    },
+                    c => out.push(c),
+                }
+            }
+            panic!("unterminated string {}", literal);
+        }
+        let after_open = literal.strip_prefix('[').expect("a string literal");
+        let level = after_open.chars().take_while(|c| *c == '=').count();
+        let body = after_open[level..]
+            .strip_prefix('[')
+            .expect("a long bracket");
+        let close = format!("]{}]", "=".repeat(level));
+        let end = body.find(&close).expect("a closing bracket");
+        assert_eq!(end + close.len(), body.len(), "early close in {}", literal);
+        // The lexer drops a line break right after the opening bracket.
+        let body = &body[..end];
+        body.strip_prefix('\n').unwrap_or(body).to_string()
+    }
+
+    #[test]
+    fn long_strings_round_trip_closing_brackets_newlines_and_backslashes() {
+        for value in [
+            "print(t[[1]])",
+            "local s = [==[ x ]==] ]] ]=]",
+            "ends with ]",
+            "ends with ]=",
+            "ends with ]==",
+            "line one\nline two\r\n",
+            "\nstarts with a newline",
+            r#"C:\path\to\"file" \n is not a newline"#,
+            "",
+        ] {
+            let encoded = long_string(value);
+            assert_eq!(read_literal(&encoded), value, "via {}", encoded);
+            assert_eq!(read_literal(&string(value)), value, "via {}", string(value));
+        }
+        assert_eq!(long_string("a ]] b"), "[=[a ]] b]=]");
+        assert_eq!(long_string("a ]] ]=] b"), "[==[a ]] ]=] b]==]");
+        assert_eq!(long_string("plain"), "[[plain]]");
+    }
+
+    #[test]
+    fn property_values_infer_datatypes_from_their_keys() {
+        assert_eq!(
+            property_value(&json!({"x": 1, "y": 2})).unwrap(),
+            "Vector2.new(1, 2)"
+        );
+        assert_eq!(
+            property_value(&json!({"z": 3, "x": 1, "y": 2})).unwrap(),
+            "Vector3.new(1, 2, 3)"
+        );
+        assert_eq!(
+            property_value(&json!({"b": 1, "g": 0, "r": 0})).unwrap(),
+            "Color3.new(0, 0, 1)"
+        );
+        assert_eq!(property_value(&json!(-4.5)).unwrap(), "-4.5");
+        assert_eq!(
+            property_value(&json!("Enum.Material.Neon")).unwrap(),
+            r#""Enum.Material.Neon""#
+        );
+        assert!(property_value(&json!(null)).is_err());
+        // Keys that only look like a datatype are encoded as a plain table.
+        assert_eq!(
+            property_value(&json!({"x": "1", "y": 2, "z": 3})).unwrap(),
+            from_json(&json!({"x": "1", "y": 2, "z": 3})).unwrap()
+        );
+    }
+}
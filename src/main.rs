@@ -1,25 +1,48 @@
 // src/main.rs - FINAL, CORRECTED VERSION
 
-use axum::routing::post; // Changed from get/post to just post
+use clap::builder::TypedValueParser;
 use clap::Parser;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 // Corrected imports to use the new unified_handler
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use rbx_studio_server::{
-    discover_luau_tools, unified_handler, AxumSharedState, DiscoveredTool, RBXStudioServer,
-    StateManager, StateManagerCommand, STUDIO_PLUGIN_PORT,
+    discover_luau_tools, plugin_router, shutdown_state_manager, watch_tools_dir, AxumSharedState,
+    DiscoveredTool, NoMatchPolicy, RBXStudioServer, ServerOptions, StateManager,
+    StateManagerCommand, ToolRegistry, DEFAULT_MAX_QUEUED_TASKS, DEFAULT_RETRY_PATTERNS,
+    MAX_TOOL_EXECUTION_TIMEOUT, MIN_PENDING_TASK_TTL, PENDING_TASK_TTL, STUDIO_PLUGIN_PORT,
+    TOOL_EXECUTION_TIMEOUT,
 };
+use rmcp::transport::sse_server::{SseServer, SseServerConfig};
 use rmcp::ServiceExt;
-use std::io;
-use std::net::Ipv4Addr;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{self, EnvFilter};
-use std::path::PathBuf;
-use std::collections::HashMap;
 
+mod audit;
+mod bench;
 mod error;
 mod install;
+mod journal;
+mod json_repair;
+mod luau;
+mod metrics;
+mod mock_plugin;
 mod rbx_studio_server;
+mod replay;
+mod tree_diff;
 
 /// Simple MCP proxy for Roblox Studio
 /// Run without arguments to install the plugin
@@ -29,11 +52,168 @@ struct Args {
     /// Run as MCP server on stdio
     #[arg(short, long)]
     stdio: bool,
+
+    /// Append server-side timing and payload sizes to every tool result
+    #[arg(long)]
+    result_timing: bool,
+
+    /// When installing, keep an existing client entry that points at a different executable
+    #[arg(long)]
+    keep_existing_path: bool,
+
+    /// When installing, how to format the rewritten client config files
+    #[arg(long, value_enum, default_value_t = install::ConfigStyle::Pretty)]
+    config_style: install::ConfigStyle,
+
+    /// When installing, exit with an error unless at least one client config was written
+    #[arg(long)]
+    require_client: bool,
+
+    /// Port the Studio plugin connects to; the installer writes it into the plugin and passes a
+    /// non-default value on to client configs
+    #[arg(long, default_value_t = STUDIO_PLUGIN_PORT)]
+    port: u16,
+
+    /// Remove the plugin and the client config entries that installing created, then exit
+    #[arg(long, conflicts_with_all = ["stdio", "replay", "bench"])]
+    uninstall: bool,
+
+    /// When installing or uninstalling, print the plugin path and resulting client configs without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// When installing or uninstalling, only touch these clients' configs
+    /// (default: Claude and Cursor, plus VS Code, Continue and Windsurf when they're installed)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    clients: Vec<install::Client>,
+
+    /// When installing or uninstalling, use this Studio Plugins folder instead of the detected one
+    /// (on Linux the default is looked up in $WINEPREFIX or ~/.wine)
+    #[arg(long, value_name = "DIR")]
+    plugins_dir: Option<PathBuf>,
+
+    /// Folder of Luau tools to discover; defaults to $RBX_STUDIO_MCP_TOOLS_DIR, then the first
+    /// `plugin/src/Tools` found beside the executable or in a folder above it
+    #[arg(long, value_name = "DIR")]
+    tools_dir: Option<PathBuf>,
+
+    /// Enable execute_luau_file for the Luau scripts in this folder (and its subfolders)
+    #[arg(long, value_name = "DIR")]
+    luau_files_dir: Option<PathBuf>,
+
+    /// Re-dispatch the tasks recorded in a JSON-lines file to the plugin, print each result, and exit
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Allow replaying tasks that may modify the place, and enable destructive tools such as delete_tagged
+    #[arg(long)]
+    allow_dangerous: bool,
+
+    /// Append a JSON line per tool task (tool, arguments, status, duration) to this file
+    #[arg(long, value_name = "FILE")]
+    audit_file: Option<PathBuf>,
+
+    /// Journal queued tasks to this file and, on start, re-queue the ones a previous run never
+    /// delivered. Re-queued tasks run without their original caller, so only use this when that's safe
+    #[arg(long, value_name = "FILE")]
+    queue_journal: Option<PathBuf>,
+
+    /// Send a discovered tool's current source from disk with every call, so Studio never runs a stale bundled copy
+    #[arg(long)]
+    ship_tool_source: bool,
+
+    /// Require the plugin to send this token (`Authorization: Bearer`); defaults to $RBX_STUDIO_MCP_AUTH_TOKEN.
+    /// Run the installer with the same token to write it into the plugin
+    #[arg(long, value_name = "TOKEN")]
+    auth_token: Option<String>,
+
+    /// Refuse any tool that may modify the place (discovered tools must be marked `--- @readonly`)
+    #[arg(long)]
+    read_only: bool,
+
+    /// Repair NaN/Infinity, unquoted keys and trailing commas in plugin result JSON
+    #[arg(long)]
+    lenient_json: bool,
+
+    /// What insert_model returns when a search matches no assets
+    #[arg(long, value_enum, default_value_t = NoMatchPolicy::Error)]
+    insert_on_no_match: NoMatchPolicy,
+
+    /// Measure round-trip latency with this many no-op tasks against a live plugin instead of serving MCP
+    #[arg(long, value_name = "N", conflicts_with_all = ["stdio", "replay"], value_parser = clap::value_parser!(u32).range(1..))]
+    bench: Option<u32>,
+
+    /// Also serve MCP over HTTP+SSE on this address (`GET /sse`, `POST /message`), for clients
+    /// that can't spawn a stdio process; an `X-MCP-Priority` header sets a tool call's priority.
+    /// Separate from the plugin port; without --stdio, runs until Ctrl+C
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["replay", "bench"])]
+    mcp_http: Option<SocketAddr>,
+
+    /// Answer tasks from a built-in stand-in for the Studio plugin, so MCP clients and the
+    /// dispatch loop can be tried out without Studio. Every task "succeeds" without running
+    #[arg(long, conflicts_with = "no_http")]
+    mock_plugin: bool,
+
+    /// Don't bind the plugin HTTP port; run only the stdio MCP service
+    #[arg(long, conflicts_with_all = ["replay", "bench"])]
+    no_http: bool,
+
+    /// MCP client names (as sent in `initialize`) to treat as text-only even if their declared capabilities say otherwise; other content is flattened to text for them
+    #[arg(long, value_delimiter = ',')]
+    text_only_clients: Vec<String>,
+
+    /// Seconds a started tool task may run before the call times out (calls can override with `timeout_seconds`)
+    #[arg(long, value_name = "SECONDS", default_value_t = TOOL_EXECUTION_TIMEOUT.as_secs(), value_parser = clap::value_parser!(u64).range(1..=MAX_TOOL_EXECUTION_TIMEOUT.as_secs()))]
+    tool_timeout: u64,
+
+    /// Dispatch a task again, up to this many times with exponential backoff, when the plugin
+    /// fails it with a transient error (see --retry-pattern)
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(u32).range(..=10))]
+    retries: u32,
+
+    /// Error text (case-insensitive substring) that marks a plugin failure as transient; repeat for
+    /// several. Replaces the defaults: "Studio is compiling", "attempt to yield across", "Studio is busy"
+    #[arg(long = "retry-pattern", value_name = "TEXT")]
+    retry_patterns: Vec<String>,
+
+    /// Tasks that may wait for the plugin at once; further calls fail with a "server busy" error until the queue drains
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MAX_QUEUED_TASKS, value_parser = clap::value_parser!(u64).range(1..).map(|n| n as usize))]
+    max_queued_tasks: usize,
+
+    /// Seconds a task may stay pending before the server drops it, e.g. after its caller went away
+    #[arg(long, value_name = "SECONDS", default_value_t = PENDING_TASK_TTL.as_secs(), value_parser = clap::value_parser!(u64).range(MIN_PENDING_TASK_TTL.as_secs()..))]
+    pending_task_ttl: u64,
+
+    /// How to write the log on stderr; `json` emits one object per line, with task and session ids as fields
+    #[arg(long, value_enum, default_value_t = LogFormat::Human)]
+    log_format: LogFormat,
+
+    /// Seconds between health summaries in the log; 0 disables them
+    #[arg(long, default_value_t = 300)]
+    summary_interval: u64,
+}
+
+/// Format of the log written to stderr.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum LogFormat {
+    /// Readable lines with the message and fields inline.
+    #[default]
+    Human,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+// --auth-token, or $RBX_STUDIO_MCP_AUTH_TOKEN without it; an empty token means none.
+fn configured_auth_token(args: &Args) -> Option<String> {
+    args.auth_token
+        .clone()
+        .or_else(|| std::env::var("RBX_STUDIO_MCP_AUTH_TOKEN").ok())
+        .filter(|token| !token.is_empty())
 }
 
 // You can keep or remove the worker_threads count; the new architecture is robust either way.
 // Let's keep it for good measure.
-#[tokio::main(worker_threads = 10)] 
+#[tokio::main(worker_threads = 10)]
 async fn main() -> Result<()> {
     color_eyre::install()?;
     let filter = EnvFilter::try_from_default_env()
@@ -42,75 +222,833 @@ async fn main() -> Result<()> {
         .add_directive("mcp_server=info".parse().unwrap())
         .add_directive("state_manager=info".parse().unwrap());
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(io::stderr)
-        .with_target(true)
-        .with_thread_ids(true)
-        .init();
-
     let args = Args::parse();
-    if !args.stdio {
-        return install::install().await;
+    log_subscriber(args.log_format, filter, io::stderr).init();
+    if args.uninstall {
+        return install::uninstall(install::InstallOptions {
+            config_style: args.config_style,
+            plugins_dir: args.plugins_dir.clone(),
+            clients: args.clients.clone(),
+            dry_run: args.dry_run,
+            ..Default::default()
+        })
+        .await;
+    }
+    if !args.stdio && args.mcp_http.is_none() && args.replay.is_none() && args.bench.is_none() {
+        return install::install(install::InstallOptions {
+            keep_existing_path: args.keep_existing_path,
+            config_style: args.config_style,
+            require_client: args.require_client,
+            port: args.port,
+            auth_token: configured_auth_token(&args),
+            plugins_dir: args.plugins_dir.clone(),
+            clients: args.clients.clone(),
+            dry_run: args.dry_run,
+            config_path_override: None,
+        })
+        .await;
     }
 
     tracing::debug!("Debug MCP tracing enabled");
 
     // --- State Initialization ---
     let (sm_command_tx, sm_command_rx) = mpsc::channel::<StateManagerCommand>(100);
-    let state_manager = StateManager::new();
+    let mut state_manager = StateManager::new()
+        .with_summary_interval(Duration::from_secs(args.summary_interval))
+        .with_task_ttl(Duration::from_secs(args.pending_task_ttl))
+        .with_max_queued_tasks(args.max_queued_tasks);
+    if let Some(path) = &args.queue_journal {
+        state_manager = state_manager.with_queue_journal(path)?;
+    }
     tokio::spawn(state_manager.run(sm_command_rx));
 
-    let tools_dir = PathBuf::from("./plugin/src/Tools");
+    let tools_dir = resolve_tools_dir(args.tools_dir.clone());
+    if !tools_dir.is_dir() {
+        tracing::warn!("Luau tools folder {} does not exist, so no tools will be discovered. Pass --tools-dir or set RBX_STUDIO_MCP_TOOLS_DIR.", tools_dir.display());
+    }
     let discovered_luau_tools_map: HashMap<String, DiscoveredTool> =
         discover_luau_tools(&tools_dir);
-    let arc_discovered_luau_tools = Arc::new(discovered_luau_tools_map);
+    let arc_discovered_luau_tools = ToolRegistry::new(discovered_luau_tools_map);
+    tokio::spawn(watch_tools_dir(
+        arc_discovered_luau_tools.clone(),
+        tools_dir.clone(),
+    ));
 
     let axum_shared_state = AxumSharedState {
         sm_command_tx: sm_command_tx.clone(),
+        lenient_json: args.lenient_json,
+        discovered_luau_tools: arc_discovered_luau_tools.clone(),
+        auth_token: configured_auth_token(&args).map(Arc::from),
     };
-    
+
     // --- HTTP Server Setup ---
     let (close_tx, close_rx) = tokio::sync::oneshot::channel();
-    let listener =
-        tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), STUDIO_PLUGIN_PORT)).await;
-
-    let server_handle = if let Ok(listener) = listener {
-        // ===================================================================
-        // THE FIX IS HERE: We now only have one route to the unified_handler
-        // ===================================================================
-        let app = axum::Router::new()
-            .route("/mcp", post(unified_handler)) // Use the single endpoint
-            .with_state(axum_shared_state.clone());
-        
-        tracing::info!("This MCP instance is HTTP server listening on {STUDIO_PLUGIN_PORT}");
-        tokio::spawn(async {
-            axum::serve(listener, app)
-                .with_graceful_shutdown(async move {
-                    _ = close_rx.await;
-                })
-                .await
-                .unwrap();
-        })
-    } else {
-        tracing::warn!("Failed to bind to port {}. HTTP server functionality will be unavailable.", STUDIO_PLUGIN_PORT);
-        tokio::spawn(async move {
-            _ = close_rx.await;
-        })
+    let server_handle =
+        start_plugin_http(args.no_http, args.port, axum_shared_state.clone(), close_rx).await?;
+
+    if args.mock_plugin {
+        tracing::warn!("--mock-plugin: tasks are answered by a stand-in and never reach Studio.");
+        tokio::spawn(
+            mock_plugin::MockPlugin::new(
+                args.port,
+                axum_shared_state.auth_token.as_deref().map(String::from),
+            )?
+            .run(),
+        );
+    }
+
+    if let Some(count) = args.bench {
+        let bench_result = bench::run_bench(count, &sm_command_tx).await;
+        shutdown(&sm_command_tx).await;
+        close_tx.send(()).ok();
+        server_handle.await.ok();
+        return bench_result;
+    }
+
+    if let Some(replay_path) = &args.replay {
+        let replay_result = replay::run_replay(
+            replay_path,
+            &sm_command_tx,
+            args.allow_dangerous,
+            Duration::from_secs(args.tool_timeout),
+        )
+        .await;
+        shutdown(&sm_command_tx).await;
+        close_tx.send(()).ok();
+        server_handle.await.ok();
+        return replay_result;
+    }
+
+    // --- MCP Service Setup ---
+    let audit_log = match &args.audit_file {
+        Some(path) => Some(Arc::new(audit::AuditLog::open(path)?)),
+        None => None,
+    };
+    let server_options = ServerOptions {
+        result_timing: args.result_timing,
+        audit_log,
+        read_only: args.read_only,
+        insert_on_no_match: args.insert_on_no_match,
+        allow_dangerous: args.allow_dangerous,
+        text_only_clients: args.text_only_clients,
+        execution_timeout: Duration::from_secs(args.tool_timeout),
+        ship_tool_source: args.ship_tool_source,
+        max_retries: args.retries,
+        retry_patterns: if args.retry_patterns.is_empty() {
+            DEFAULT_RETRY_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect()
+        } else {
+            args.retry_patterns
+        },
+        luau_files_dir: args.luau_files_dir,
     };
+    if args.read_only {
+        let discovered_luau_tools = arc_discovered_luau_tools.snapshot();
+        let readonly_tools = discovered_luau_tools
+            .values()
+            .filter(|tool| tool.readonly)
+            .count();
+        tracing::info!(
+            "Read-only mode: {} of {} discovered Luau tools are available.",
+            readonly_tools,
+            discovered_luau_tools.len()
+        );
+    }
+    let mcp_http_ct = match args.mcp_http {
+        Some(addr) => {
+            let ct = serve_mcp_http(
+                addr,
+                per_connection_server(
+                    sm_command_tx.clone(),
+                    arc_discovered_luau_tools.clone(),
+                    server_options.clone(),
+                ),
+            )
+            .await?;
+            tracing::info!("Serving MCP over HTTP+SSE on http://{}/sse", addr);
+            Some(ct)
+        }
+        None => None,
+    };
+    if !args.stdio {
+        tokio::signal::ctrl_c().await.ok();
+        if let Some(ct) = mcp_http_ct {
+            ct.cancel();
+        }
+        shutdown(&sm_command_tx).await;
+        close_tx.send(()).ok();
+        server_handle.await.ok();
+        tracing::info!("Bye!");
+        return Ok(());
+    }
 
-    // --- Stdio Service Setup ---
-    let service = RBXStudioServer::new(sm_command_tx.clone(), arc_discovered_luau_tools.clone())
-        .serve(rmcp::transport::stdio())
-        .await
-        .inspect_err(|e| {
+    let service = RBXStudioServer::new(
+        sm_command_tx.clone(),
+        arc_discovered_luau_tools.clone(),
+        server_options,
+    )
+    .serve(rmcp::transport::stdio())
+    .await;
+    let service = match service {
+        Ok(service) => service,
+        Err(e) => {
             tracing::error!("serving error: {:?}", e);
-        })?;
-    service.waiting().await?;
+            if let Some(hint) = stdio_failure_hint(&format!("{e:?}"), io::stdin().is_terminal()) {
+                eprintln!("{hint}");
+            }
+            if let Some(ct) = mcp_http_ct {
+                ct.cancel();
+            }
+            shutdown(&sm_command_tx).await;
+            close_tx.send(()).ok();
+            server_handle.await.ok();
+            return Err(e.into());
+        }
+    };
+    let waiting = service.waiting().await;
 
+    if let Some(ct) = mcp_http_ct {
+        ct.cancel();
+    }
+    shutdown(&sm_command_tx).await;
     close_tx.send(()).ok();
     tracing::info!("Waiting for web server to gracefully shutdown");
     server_handle.await.ok();
     tracing::info!("Bye!");
+    waiting?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// Serves the plugin routes on `port` until `close_rx` fires. With `no_http` nothing is bound and
+// the returned task just waits for `close_rx`, so shutdown is the same either way.
+async fn start_plugin_http(
+    no_http: bool,
+    port: u16,
+    axum_shared_state: AxumSharedState,
+    close_rx: oneshot::Receiver<()>,
+) -> Result<JoinHandle<()>> {
+    if no_http {
+        tracing::info!(
+            "HTTP server disabled by --no-http; tasks must be delivered by another transport."
+        );
+        return Ok(tokio::spawn(async move {
+            _ = close_rx.await;
+        }));
+    }
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), port)).await.map_err(|e| {
+        eyre!("Could not bind the plugin port {}: {}. Another rbx-studio-mcp instance may already be running; stop it or pick a different --port.", port, e)
+    })?;
+    let app = plugin_router(axum_shared_state);
+
+    tracing::info!("This MCP instance is HTTP server listening on {}", port);
+    Ok(tokio::spawn(async {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                _ = close_rx.await;
+            })
+            .await
+            .unwrap();
+    }))
+}
+
+fn log_subscriber<W>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_target(true)
+        .with_thread_ids(true);
+    match format {
+        LogFormat::Human => Box::new(subscriber.finish()),
+        // Event fields are flattened next to `message` so `task_id` and `session` are top-level keys.
+        LogFormat::Json => Box::new(
+            subscriber
+                .json()
+                .flatten_event(true)
+                .with_current_span(false)
+                .finish(),
+        ),
+    }
+}
+
+// Serves MCP over HTTP+SSE on `addr`, with a server from `servers` for each connection, until the
+// returned token is cancelled.
+async fn serve_mcp_http(
+    addr: SocketAddr,
+    servers: impl Fn() -> RBXStudioServer + Send + 'static,
+) -> Result<CancellationToken> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| eyre!("Could not bind the MCP HTTP address {}: {}", addr, e))?;
+    let (sse_server, router) = SseServer::new(SseServerConfig {
+        bind: addr,
+        sse_path: "/sse".to_string(),
+        post_path: "/message".to_string(),
+        ct: CancellationToken::new(),
+    });
+    let router = router.layer(axum::middleware::from_fn(priority_header_to_meta));
+    let ct = sse_server.with_service(servers);
+    let stopped = ct.clone();
+    tokio::spawn(async move {
+        let server = axum::serve(listener, router)
+            .with_graceful_shutdown(async move { stopped.cancelled().await });
+        if let Err(e) = server.await {
+            tracing::error!("The MCP HTTP server stopped: {}", e);
+        }
+    });
+    Ok(ct)
+}
+
+// Sets a tool call's priority over HTTP; stdio clients send the same values as `_meta.priority`.
+const PRIORITY_HEADER: &str = "X-MCP-Priority";
+// axum's default body limit, which rmcp's message handler applies anyway.
+const MAX_MCP_HTTP_MESSAGE_BYTES: usize = 2 * 1024 * 1024;
+
+// Copies a tool call's `X-MCP-Priority` header into its `_meta.priority`, which is where the call's
+// priority is read from. A priority already in `_meta` is kept.
+async fn priority_header_to_meta(request: Request, next: Next) -> Response {
+    let Some(priority) = request
+        .headers()
+        .get(PRIORITY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+    else {
+        return next.run(request).await;
+    };
+    let (mut parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_MCP_HTTP_MESSAGE_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(mut message) if message["method"] == "tools/call" && message["params"].is_object() => {
+            let meta = message["params"]
+                .as_object_mut()
+                .unwrap()
+                .entry("_meta")
+                .or_insert_with(|| serde_json::json!({}));
+            if let Some(meta) = meta.as_object_mut() {
+                meta.entry("priority").or_insert(priority.into());
+            }
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(message.to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    next.run(Request::from_parts(parts, body)).await
+}
+
+// Each SSE connection gets its own server so per-client state (text-only, target session) isn't shared.
+fn per_connection_server(
+    sm_command_tx: mpsc::Sender<StateManagerCommand>,
+    registry: ToolRegistry,
+    options: ServerOptions,
+) -> impl Fn() -> RBXStudioServer + Send + 'static {
+    move || RBXStudioServer::new(sm_command_tx.clone(), registry.clone(), options.clone())
+}
+
+// Where discovered tools come from. The default is looked up from the executable rather than the
+// working directory, so `target/debug/rbx-studio-mcp` finds the repository's tools wherever it's started.
+fn resolve_tools_dir(explicit: Option<PathBuf>) -> PathBuf {
+    if let Some(dir) = explicit.or_else(|| {
+        std::env::var_os("RBX_STUDIO_MCP_TOOLS_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    }) {
+        return dir;
+    }
+    let relative = Path::new("plugin").join("src").join("Tools");
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    exe_dir
+        .iter()
+        .flat_map(|dir| dir.ancestors())
+        .map(|dir| dir.join(&relative))
+        .find(|candidate| candidate.is_dir())
+        .unwrap_or_else(|| Path::new(".").join(relative))
+}
+
+// Runs before the HTTP server closes, so no plugin poll is left holding a task.
+async fn shutdown(sm_command_tx: &mpsc::Sender<StateManagerCommand>) {
+    let failed = shutdown_state_manager(sm_command_tx).await;
+    if failed > 0 {
+        tracing::warn!("Failed {} outstanding task(s) at shutdown.", failed);
+    }
+}
+
+/// Guidance for the usual reason the stdio transport fails: the binary was started by hand
+/// rather than by an MCP client, so stdin is a terminal or already closed.
+fn stdio_failure_hint(error: &str, stdin_is_terminal: bool) -> Option<&'static str> {
+    // Spaces are dropped so Debug names like `ConnectionClosed` match as well as messages.
+    let error = error.to_ascii_lowercase().replace(' ', "");
+    let stdin_unusable = [
+        "eof",
+        "brokenpipe",
+        "connectionclosed",
+        "stdin",
+        "unexpectedend",
+    ]
+    .iter()
+    .any(|needle| error.contains(needle));
+    if !stdin_is_terminal && !stdin_unusable {
+        return None;
+    }
+    Some(
+        "The MCP stdio transport could not start. `--stdio` expects an MCP client (Claude, Cursor, ...) \
+         to launch this binary and talk to it over stdin/stdout.\n\
+         Add it to your client's MCP configuration instead of running it directly, \
+         or run it without arguments to install the plugin and configure your clients.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stdio_failures_from_a_missing_client_get_guidance() {
+        // Started by hand: stdin is the terminal, whatever the error was.
+        assert!(stdio_failure_hint("initialize failed", true).is_some());
+        // Launched with stdin closed or a pipe that went away.
+        for error in [
+            "Io(Error { kind: UnexpectedEof, message: \"EOF\" })",
+            "Io(Os { code: 32, kind: BrokenPipe, message: \"Broken pipe\" })",
+            "ConnectionClosed(\"initialize request\")",
+        ] {
+            let hint = stdio_failure_hint(error, false).expect(error);
+            assert!(hint.contains("expects an MCP client"), "{}", hint);
+        }
+        // A client that connected and then sent something invalid is a real error.
+        assert_eq!(
+            stdio_failure_hint("ExpectedInitRequest(Some(Notification))", false),
+            None
+        );
+    }
+
+    fn test_state() -> AxumSharedState {
+        let (sm_command_tx, _) = mpsc::channel(1);
+        AxumSharedState {
+            sm_command_tx,
+            lenient_json: false,
+            discovered_luau_tools: ToolRegistry::new(HashMap::new()),
+            auth_token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn no_http_runs_without_binding_the_plugin_port() {
+        // Hold a port so binding it again would fail.
+        let taken = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let error = start_plugin_http(false, port, test_state(), oneshot::channel().1)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("Could not bind the plugin port"),
+            "{}",
+            error
+        );
+
+        let (close_tx, close_rx) = oneshot::channel();
+        let server = start_plugin_http(true, port, test_state(), close_rx)
+            .await
+            .unwrap();
+        assert!(!server.is_finished());
+        close_tx.send(()).unwrap();
+        server.await.unwrap();
+    }
+
+    // Reads server-sent events off a streaming response, one `(event, data)` pair at a time.
+    struct SseReader {
+        response: reqwest::Response,
+        buffer: String,
+    }
+
+    impl SseReader {
+        async fn next_event(&mut self) -> (String, String) {
+            loop {
+                if let Some(end) = self.buffer.find("\n\n") {
+                    let block: String = self.buffer.drain(..end + 2).collect();
+                    let field = |name: &str| {
+                        block
+                            .lines()
+                            .find_map(|line| line.strip_prefix(name))
+                            .map(|value| value.trim_start().to_string())
+                            .unwrap_or_default()
+                    };
+                    return (field("event:"), field("data:"));
+                }
+                let chunk = self.response.chunk().await.unwrap().expect("stream ended");
+                self.buffer.push_str(&String::from_utf8_lossy(&chunk));
+            }
+        }
+    }
+
+    // An MCP client on the HTTP+SSE transport: messages are POSTed, answers come on the stream.
+    struct SseClient {
+        client: reqwest::Client,
+        post_url: String,
+        events: SseReader,
+    }
+
+    impl SseClient {
+        // Opens the stream and completes the `initialize` handshake.
+        async fn connect(addr: SocketAddr) -> Self {
+            let client = reqwest::Client::new();
+            let mut events = SseReader {
+                response: client
+                    .get(format!("http://{}/sse", addr))
+                    .send()
+                    .await
+                    .unwrap(),
+                buffer: String::new(),
+            };
+            let (event, endpoint) = events.next_event().await;
+            assert_eq!(event, "endpoint");
+            let mut sse = Self {
+                client,
+                post_url: format!("http://{}{}", addr, endpoint),
+                events,
+            };
+            sse.post(serde_json::json!({
+                "jsonrpc": "2.0", "id": 1, "method": "initialize",
+                "params": {
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": {},
+                    "clientInfo": { "name": "smoke-test", "version": "0" }
+                }
+            }))
+            .send()
+            .await
+            .unwrap();
+            let initialized = sse.next_message().await;
+            assert_eq!(initialized["id"], 1);
+            assert!(
+                initialized["result"]["serverInfo"].is_object(),
+                "{}",
+                initialized
+            );
+            sse.post(
+                serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            )
+            .send()
+            .await
+            .unwrap();
+            sse
+        }
+
+        fn post(&self, message: serde_json::Value) -> reqwest::RequestBuilder {
+            self.client.post(&self.post_url).json(&message)
+        }
+
+        async fn next_message(&mut self) -> serde_json::Value {
+            let (event, data) = self.events.next_event().await;
+            assert_eq!(event, "message");
+            serde_json::from_str(&data).unwrap()
+        }
+    }
+
+    fn free_addr() -> SocketAddr {
+        std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn an_sse_client_can_initialize_and_list_tools() {
+        let addr = free_addr();
+        let (sm_command_tx, _) = mpsc::channel(1);
+        let ct = serve_mcp_http(
+            addr,
+            per_connection_server(
+                sm_command_tx,
+                ToolRegistry::new(HashMap::new()),
+                ServerOptions::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let mut sse = SseClient::connect(addr).await;
+        sse.post(serde_json::json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }))
+            .send()
+            .await
+            .unwrap();
+        let listed = sse.next_message().await;
+        assert_eq!(listed["id"], 2);
+        let names: Vec<&str> = listed["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|tool| tool["name"].as_str())
+            .collect();
+        assert!(names.contains(&"get_place_info"), "{:?}", names);
+        ct.cancel();
+    }
+
+    // A `get_place_info` call with a string id and a progress token.
+    fn call_with_progress_token(id: &str, token: &str) -> serde_json::Value {
+        serde_json::json!({
+            "jsonrpc": "2.0", "id": id, "method": "tools/call",
+            "params": {
+                "name": "get_place_info",
+                "arguments": {},
+                "_meta": { "progressToken": token }
+            }
+        })
+    }
+
+    // Takes the next dispatched task and reports it picked up; send the result on what's returned.
+    async fn take_dispatched_task(
+        sm_command_rx: &mut mpsc::Receiver<StateManagerCommand>,
+    ) -> rbx_studio_server::TaskResponder {
+        let Some(StateManagerCommand::DispatchTask {
+            response_tx,
+            started_tx,
+            ..
+        }) = sm_command_rx.recv().await
+        else {
+            panic!("expected a dispatched task")
+        };
+        started_tx.send(()).unwrap();
+        response_tx
+    }
+
+    fn place_info_completion() -> rbx_studio_server::TaskCompletion {
+        rbx_studio_server::TaskCompletion {
+            result: rmcp::model::CallToolResult::success(vec![rmcp::model::Content::text(
+                "{\"name\": \"Place1\"}",
+            )]),
+            queue_wait: None,
+            response_bytes: 20,
+        }
+    }
+
+    #[tokio::test]
+    async fn an_sse_tool_call_reports_progress_to_its_token() {
+        let addr = free_addr();
+        let (sm_command_tx, mut sm_command_rx) = mpsc::channel(1);
+        let ct = serve_mcp_http(
+            addr,
+            per_connection_server(
+                sm_command_tx,
+                ToolRegistry::new(HashMap::new()),
+                ServerOptions::default(),
+            ),
+        )
+        .await
+        .unwrap();
+        let mut sse = SseClient::connect(addr).await;
+
+        sse.post(call_with_progress_token("call-1", "sse-progress"))
+            .send()
+            .await
+            .unwrap();
+        let response_tx = take_dispatched_task(&mut sm_command_rx).await;
+        // Queued, then picked up.
+        for progress in [1, 2] {
+            let notification = sse.next_message().await;
+            assert_eq!(notification["method"], "notifications/progress");
+            assert_eq!(notification["params"]["progressToken"], "sse-progress");
+            assert_eq!(notification["params"]["progress"], progress);
+        }
+        response_tx.send(Ok(place_info_completion())).unwrap();
+        let result = sse.next_message().await;
+        assert_eq!(result["id"], "call-1");
+        assert!(result["result"]["content"].is_array(), "{}", result);
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn a_high_priority_header_jumps_the_queue_over_http() {
+        let addr = free_addr();
+        let (sm_command_tx, sm_command_rx) = mpsc::channel(8);
+        tokio::spawn(StateManager::new().run(sm_command_rx));
+        let ct = serve_mcp_http(
+            addr,
+            per_connection_server(
+                sm_command_tx.clone(),
+                ToolRegistry::new(HashMap::new()),
+                ServerOptions::default(),
+            ),
+        )
+        .await
+        .unwrap();
+        let mut sse = SseClient::connect(addr).await;
+
+        for (id, command, priority) in [
+            (2, "print('normal')", None),
+            (3, "print('high')", Some("high")),
+        ] {
+            let mut post = sse.post(serde_json::json!({
+                "jsonrpc": "2.0", "id": id, "method": "tools/call",
+                "params": {
+                    "name": "run_command",
+                    "arguments": { "command": command },
+                    "_meta": { "progressToken": id }
+                }
+            }));
+            if let Some(priority) = priority {
+                post = post.header(PRIORITY_HEADER, priority);
+            }
+            post.send().await.unwrap();
+            // Wait for the "queued" progress so the calls are queued in this order.
+            let queued = sse.next_message().await;
+            assert_eq!(queued["params"]["progressToken"], id, "{}", queued);
+        }
+
+        let (response_tx, response_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::PollForTask {
+                session: None,
+                response_tx,
+            })
+            .await
+            .unwrap();
+        let Ok(rbx_studio_server::PollOutcome::Task(task)) = response_rx.await else {
+            panic!("expected a task")
+        };
+        let task = task.to_luau_string();
+        assert!(task.contains("print('high')"), "{}", task);
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn a_stdio_tool_call_reports_progress_to_its_token() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        let (client, server_io) = tokio::io::duplex(64 * 1024);
+        let (sm_command_tx, mut sm_command_rx) = mpsc::channel(1);
+        let server = RBXStudioServer::new(
+            sm_command_tx,
+            ToolRegistry::new(HashMap::new()),
+            ServerOptions::default(),
+        );
+        tokio::spawn(async move {
+            let service = server.serve(tokio::io::split(server_io)).await.unwrap();
+            service.waiting().await
+        });
+
+        let (client_read, mut client_write) = tokio::io::split(client);
+        for message in [
+            serde_json::json!({
+                "jsonrpc": "2.0", "id": "init", "method": "initialize",
+                "params": {
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": {},
+                    "clientInfo": { "name": "smoke-test", "version": "0" }
+                }
+            }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            call_with_progress_token("call-1", "stdio-progress"),
+        ] {
+            let line = format!("{}\n", message);
+            client_write.write_all(line.as_bytes()).await.unwrap();
+        }
+        let mut lines = BufReader::new(client_read).lines();
+        let mut messages = Vec::new();
+        let response_tx = take_dispatched_task(&mut sm_command_rx).await;
+        // The initialize answer, then the call's queued and picked-up notifications.
+        while messages.len() < 3 {
+            let line = lines.next_line().await.unwrap().expect("stdout closed");
+            messages.push(serde_json::from_str::<serde_json::Value>(&line).unwrap());
+        }
+        response_tx.send(Ok(place_info_completion())).unwrap();
+        let line = lines.next_line().await.unwrap().expect("stdout closed");
+        messages.push(serde_json::from_str(&line).unwrap());
+
+        assert_eq!(messages[0]["id"], "init");
+        for (notification, progress) in messages[1..3].iter().zip([1, 2]) {
+            assert_eq!(notification["method"], "notifications/progress");
+            assert_eq!(notification["params"]["progressToken"], "stdio-progress");
+            assert_eq!(notification["params"]["progress"], progress);
+        }
+        assert_eq!(messages[3]["id"], "call-1");
+        assert!(
+            messages[3]["result"]["content"].is_array(),
+            "{}",
+            messages[3]
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl io::Write for LogBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_logs_carry_ids_as_fields_and_keep_target_directives() {
+        let buffer = LogBuffer::default();
+        let writer = buffer.clone();
+        let filter = EnvFilter::new("warn").add_directive("state_manager=info".parse().unwrap());
+        let subscriber = log_subscriber(LogFormat::Json, filter, move || writer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "state_manager", task_id = "3f2a", session = "studio-1", "Dispatching task.");
+            tracing::info!(target: "elsewhere", "Filtered out below warn.");
+        });
+
+        let logged = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1, "{}", logged);
+        assert_eq!(lines[0]["target"], "state_manager");
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "Dispatching task.");
+        assert_eq!(lines[0]["task_id"], "3f2a");
+        assert_eq!(lines[0]["session"], "studio-1");
+    }
+
+    #[test]
+    fn an_explicit_tools_dir_is_used_wherever_the_server_starts() {
+        let dir =
+            std::env::temp_dir().join(format!("rbx-studio-mcp-tools-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Lighting")).unwrap();
+        std::fs::write(dir.join("Spin.luau"), "return function() end").unwrap();
+        std::fs::write(
+            dir.join("Lighting").join("SetAmbient.luau"),
+            "return function() end",
+        )
+        .unwrap();
+
+        let resolved = resolve_tools_dir(Some(dir.clone()));
+        assert_eq!(resolved, dir);
+        let mut names: Vec<String> = discover_luau_tools(&resolved).into_keys().collect();
+        names.sort();
+        assert_eq!(names, ["Lighting.SetAmbient", "Spin"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_default_tools_dir_is_found_from_the_executable() {
+        if std::env::var_os("RBX_STUDIO_MCP_TOOLS_DIR").is_some() {
+            return;
+        }
+        // The test binary sits under target/, so the repository's folder is one of its ancestors'.
+        let resolved = resolve_tools_dir(None);
+        assert!(resolved.is_absolute(), "{}", resolved.display());
+        assert!(resolved.ends_with(Path::new("plugin").join("src").join("Tools")));
+        assert!(resolved.is_dir(), "{}", resolved.display());
+    }
+}
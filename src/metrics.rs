@@ -0,0 +1,163 @@
+// metrics.rs - task counters and plugin poll timings served by `GET /metrics` in the Prometheus text format
+
+use std::fmt::Write;
+use std::time::Duration;
+
+// Upper bounds, in seconds, of the poll wait buckets. A poll that gets no task ends after
+// `LONG_POLL_DURATION` (25s), so the 30s bucket holds every empty poll.
+const POLL_WAIT_BUCKETS: &[f64] = &[0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 20.0, 30.0];
+
+/// How long plugin long-polls waited before they were answered, with or without a task.
+#[derive(Clone, Debug)]
+pub struct PollWaitHistogram {
+    /// Observations per bucket (not cumulative); the last slot counts values above every bound.
+    buckets: [u64; POLL_WAIT_BUCKETS.len() + 1],
+    count: u64,
+    sum_seconds: f64,
+}
+
+impl Default for PollWaitHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; POLL_WAIT_BUCKETS.len() + 1],
+            count: 0,
+            sum_seconds: 0.0,
+        }
+    }
+}
+
+impl PollWaitHistogram {
+    pub fn observe(&mut self, waited: Duration) {
+        let seconds = waited.as_secs_f64();
+        let index = POLL_WAIT_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(POLL_WAIT_BUCKETS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.sum_seconds += seconds;
+    }
+}
+
+/// Totals since the server started, kept by the `StateManager`.
+#[derive(Clone, Debug, Default)]
+pub struct TaskMetrics {
+    pub dispatched: u64,
+    pub completed: u64,
+    pub timed_out: u64,
+    /// Cancelled by the client, addressed to a plugin session that went away, or reaped after the task TTL.
+    pub cancelled: u64,
+    pub poll_wait: PollWaitHistogram,
+}
+
+/// What `GET /metrics` reports: the running totals plus the current queue state.
+#[derive(Clone, Debug)]
+pub struct MetricsSnapshot {
+    pub totals: TaskMetrics,
+    pub queue_depth: usize,
+    pub pending_tasks: usize,
+}
+
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "rbx_studio_mcp_tasks_dispatched_total",
+            "Tasks accepted for dispatch to the plugin.",
+            snapshot.totals.dispatched,
+        ),
+        (
+            "rbx_studio_mcp_tasks_completed_total",
+            "Tasks the plugin returned a result for.",
+            snapshot.totals.completed,
+        ),
+        (
+            "rbx_studio_mcp_tasks_timed_out_total",
+            "Tasks that missed their queue wait or execution deadline.",
+            snapshot.totals.timed_out,
+        ),
+        (
+            "rbx_studio_mcp_tasks_cancelled_total",
+            "Tasks dropped by cancel_task, session expiry or the pending task TTL.",
+            snapshot.totals.cancelled,
+        ),
+    ];
+    for (name, help, value) in counters {
+        let _ = writeln!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+        );
+    }
+    let gauges = [
+        (
+            "rbx_studio_mcp_queue_depth",
+            "Tasks waiting for a plugin to pick them up.",
+            snapshot.queue_depth,
+        ),
+        (
+            "rbx_studio_mcp_pending_tasks",
+            "Tasks queued or running whose result hasn't arrived.",
+            snapshot.pending_tasks,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        let _ = writeln!(
+            out,
+            "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}"
+        );
+    }
+
+    let histogram = &snapshot.totals.poll_wait;
+    let name = "rbx_studio_mcp_poll_wait_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Time plugin long-polls waited for an answer.\n# TYPE {name} histogram"
+    );
+    let mut cumulative = 0;
+    for (bound, count) in POLL_WAIT_BUCKETS.iter().zip(histogram.buckets.iter()) {
+        cumulative += count;
+        let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count);
+    let _ = writeln!(
+        out,
+        "{name}_sum {}\n{name}_count {}",
+        histogram.sum_seconds, histogram.count
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_wait_buckets_are_rendered_cumulatively() {
+        let mut totals = TaskMetrics::default();
+        for millis in [10, 80, 700, 40_000] {
+            totals.poll_wait.observe(Duration::from_millis(millis));
+        }
+        let rendered = render(&MetricsSnapshot {
+            totals,
+            queue_depth: 2,
+            pending_tasks: 3,
+        });
+        for line in [
+            "rbx_studio_mcp_poll_wait_seconds_bucket{le=\"0.05\"} 1",
+            "rbx_studio_mcp_poll_wait_seconds_bucket{le=\"0.1\"} 2",
+            "rbx_studio_mcp_poll_wait_seconds_bucket{le=\"1\"} 3",
+            "rbx_studio_mcp_poll_wait_seconds_bucket{le=\"30\"} 3",
+            "rbx_studio_mcp_poll_wait_seconds_bucket{le=\"+Inf\"} 4",
+            "rbx_studio_mcp_poll_wait_seconds_count 4",
+            "rbx_studio_mcp_queue_depth 2",
+            "rbx_studio_mcp_pending_tasks 3",
+        ] {
+            assert!(
+                rendered.lines().any(|rendered| rendered == line),
+                "missing {:?} in\n{}",
+                line,
+                rendered
+            );
+        }
+    }
+}
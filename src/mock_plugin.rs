@@ -0,0 +1,157 @@
+// mock_plugin.rs - stands in for the Studio plugin on the `/mcp` long-poll protocol (`--mock-plugin`),
+// so the dispatch -> poll -> submit loop can be exercised end to end without Studio
+
+use crate::rbx_studio_server::{
+    DISPATCH_PAUSED_HEADER, PLUGIN_PROTOCOL_VERSION, PLUGIN_VERSION_HEADER,
+};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use reqwest::StatusCode;
+use rmcp::model::{CallToolResult, Content};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+// Session id the mock polls as, so it shows up by name in list_plugin_sessions.
+const MOCK_SESSION: &str = "mock-plugin";
+// Outlasts the server's 25s long-poll so an empty poll ends with its 204, not a client timeout.
+const POLL_REQUEST_TIMEOUT: Duration = Duration::from_secs(35);
+// Pause between polls after the server said dispatch is paused or a request failed.
+const POLL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// What one long-poll returned.
+#[derive(Debug)]
+pub enum Poll {
+    /// A task, as the Luau chunk the real plugin would `loadstring`.
+    Task {
+        task_id: Uuid,
+        tool: String,
+        chunk: String,
+    },
+    /// The poll ran out with nothing to do (204).
+    Empty,
+    /// The server answered 204 with the dispatch-paused header.
+    Paused,
+}
+
+/// A plugin session that speaks the same HTTP as `Main.server.luau`: it polls `/mcp` with no task
+/// header and posts results back with `X-MCP-Task-ID`, naming the current plugin protocol version.
+pub struct MockPlugin {
+    client: reqwest::Client,
+    url: String,
+    auth_token: Option<String>,
+}
+
+impl MockPlugin {
+    pub fn new(port: u16, auth_token: Option<String>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(POLL_REQUEST_TIMEOUT)
+            .build()
+            .wrap_err("Could not build the mock plugin's HTTP client")?;
+        Ok(Self {
+            client,
+            url: format!("http://127.0.0.1:{}/mcp", port),
+            auth_token,
+        })
+    }
+
+    fn post(&self) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .post(&self.url)
+            .header("X-MCP-Plugin-Session", MOCK_SESSION)
+            .header(PLUGIN_VERSION_HEADER, PLUGIN_PROTOCOL_VERSION.to_string());
+        match &self.auth_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    pub async fn poll(&self) -> Result<Poll> {
+        let response = self
+            .post()
+            .send()
+            .await
+            .wrap_err("Mock plugin poll failed")?;
+        match response.status() {
+            StatusCode::OK => {
+                let chunk = response
+                    .text()
+                    .await
+                    .wrap_err("Could not read the polled task")?;
+                let task_id = task_id_from_chunk(&chunk)
+                    .ok_or_else(|| eyre!("Polled task has no id: {}", chunk))?;
+                let tool = tool_from_chunk(&chunk).unwrap_or("?").to_string();
+                Ok(Poll::Task {
+                    task_id,
+                    tool,
+                    chunk,
+                })
+            }
+            StatusCode::NO_CONTENT if response.headers().contains_key(DISPATCH_PAUSED_HEADER) => {
+                Ok(Poll::Paused)
+            }
+            StatusCode::NO_CONTENT => Ok(Poll::Empty),
+            status => Err(eyre!("Mock plugin poll answered {}", status)),
+        }
+    }
+
+    /// Posts `result` for `task_id` and returns the server's status: 204 when it was accepted,
+    /// 404 when the task is unknown or already resolved.
+    pub async fn submit(&self, task_id: Uuid, result: &CallToolResult) -> Result<StatusCode> {
+        let body = serde_json::to_string(result)?;
+        let response = self
+            .post()
+            .header("X-MCP-Task-ID", task_id.to_string())
+            .body(body)
+            .send()
+            .await
+            .wrap_err("Mock plugin submit failed")?;
+        Ok(response.status())
+    }
+
+    /// Answers every task with a success result naming the tool, for as long as the server runs.
+    pub async fn run(self) {
+        info!(target: "mcp_server", session = MOCK_SESSION, "Mock plugin polling {}.", self.url);
+        loop {
+            match self.poll().await {
+                Ok(Poll::Task {
+                    task_id,
+                    tool,
+                    chunk,
+                }) => {
+                    debug!(target: "mcp_server", task_id=%task_id, chunk = chunk.as_str(), "Mock plugin received task.");
+                    let result = CallToolResult::success(vec![Content::text(format!(
+                        "Mock plugin ran {}.",
+                        tool
+                    ))]);
+                    match self.submit(task_id, &result).await {
+                        Ok(StatusCode::NO_CONTENT) => {
+                            info!(target: "mcp_server", task_id=%task_id, tool = tool.as_str(), "Mock plugin answered task.")
+                        }
+                        Ok(status) => {
+                            warn!(target: "mcp_server", task_id=%task_id, "Mock plugin result was refused with {}.", status)
+                        }
+                        Err(e) => warn!(target: "mcp_server", task_id=%task_id, "{:#}", e),
+                    }
+                }
+                Ok(Poll::Empty) => {}
+                Ok(Poll::Paused) => tokio::time::sleep(POLL_BACKOFF).await,
+                Err(e) => {
+                    warn!(target: "mcp_server", "{:#}", e);
+                    tokio::time::sleep(POLL_BACKOFF).await;
+                }
+            }
+        }
+    }
+}
+
+// Chunks look like `return { id = "<uuid>", args = { <Tool> = { .. } } }`.
+pub fn task_id_from_chunk(chunk: &str) -> Option<Uuid> {
+    let rest = &chunk[chunk.find("id = \"")? + 6..];
+    Uuid::parse_str(&rest[..rest.find('"')?]).ok()
+}
+
+fn tool_from_chunk(chunk: &str) -> Option<&str> {
+    let rest = &chunk[chunk.find("args = { ")? + 9..];
+    rest.split(" =").next().filter(|tool| !tool.is_empty())
+}
@@ -1,206 +1,9182 @@
 // rbx_studio_server.rs - THE FINAL, DEFINITIVE FIX
 
+use crate::audit::AuditLog;
 use crate::error::Result;
+use crate::journal::QueueJournal;
+use crate::json_repair;
+use crate::luau;
+use crate::metrics::{self, MetricsSnapshot, TaskMetrics};
+use crate::tree_diff;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::http::{HeaderMap, StatusCode};
 use axum::response::IntoResponse;
+use axum::routing::{get, post};
 use axum::{extract::State, Json};
+use rmcp::handler::server::tool::ToolCallContext;
 use rmcp::model::{
-    CallToolResult, Content, Implementation, ProtocolVersion, ServerCapabilities, ServerInfo,
+    CallToolRequestParam, CallToolResult, ClientCapabilities, ClientInfo, Content, Implementation,
+    InitializeRequestParam, InitializeResult, JsonObject, ListToolsResult, PaginatedRequestParam,
+    ProgressNotificationParam, ProgressToken, ProtocolVersion, RawContent, RequestId,
+    ServerCapabilities, ServerInfo,
 };
+use rmcp::service::{AtomicU32RequestIdProvider, RequestContext};
 use rmcp::tool;
-use rmcp::{Error as McpError, ServerHandler};
+use rmcp::{Error as McpError, Peer, RoleServer, ServerHandler};
+use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::{mpsc, oneshot};
-use tokio::time::Duration;
-use tracing::{info, warn, error};
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 pub const STUDIO_PLUGIN_PORT: u16 = 44755;
+// Roblox's HttpService gives up on a request after about 30s, so a long-poll must be answered
+// (empty, if nothing arrived) comfortably before that or the plugin sees a failed request.
+const PLUGIN_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
 const LONG_POLL_DURATION: Duration = Duration::from_secs(25);
-const TOOL_EXECUTION_TIMEOUT: Duration = Duration::from_secs(30);
+const _: () = assert!(
+    LONG_POLL_DURATION.as_secs() + 5 <= PLUGIN_HTTP_TIMEOUT.as_secs(),
+    "LONG_POLL_DURATION must stay at least 5s under PLUGIN_HTTP_TIMEOUT"
+);
+/// Default for how long a started task may run; see `--tool-timeout` and `timeout_seconds`.
+pub const TOOL_EXECUTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// Upper bound for any execution timeout override.
+pub const MAX_TOOL_EXECUTION_TIMEOUT: Duration = Duration::from_secs(300);
+const QUEUE_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Default for `--max-queued-tasks`.
+pub const DEFAULT_MAX_QUEUED_TASKS: usize = 1_000;
+/// Default age at which the `StateManager` drops a task nobody resolved, and how often it checks.
+pub const PENDING_TASK_TTL: Duration = Duration::from_secs(600);
+const PENDING_TASK_REAP_INTERVAL: Duration = Duration::from_secs(30);
+/// Longest a well-behaved caller waits: the queue wait plus the longest execution timeout, with slack.
+pub const MIN_PENDING_TASK_TTL: Duration =
+    Duration::from_secs(QUEUE_WAIT_TIMEOUT.as_secs() + MAX_TOOL_EXECUTION_TIMEOUT.as_secs() + 30);
+/// Set on an empty poll response while dispatch is paused.
+pub const DISPATCH_PAUSED_HEADER: &str = "X-MCP-Dispatch-Paused";
+/// Names the task protocol version a plugin speaks on every `/mcp` request.
+pub const PLUGIN_VERSION_HEADER: &str = "X-MCP-Plugin-Version";
+/// The version the bundled plugin sends. Bump it, with `PLUGIN_PROTOCOL_VERSION` in
+/// Main.server.luau, whenever task chunks or result bodies change in a way older plugins misread.
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+// Oldest plugin version this server still dispatches to.
+const MIN_PLUGIN_PROTOCOL_VERSION: u32 = 1;
+// Plugins from before the header existed are still served, with one warning per server run.
+static LEGACY_PLUGIN_WARNING: Once = Once::new();
+/// Frames `GET /ws` sends in place of a task when dispatch is paused or resumed.
+const WS_PAUSED_FRAME: &str = "paused";
+const WS_RESUMED_FRAME: &str = "resumed";
+/// How long a WebSocket connection waits before asking again while dispatch is paused,
+/// matching the plugin's own back-off when long-polling.
+const WS_PAUSED_RETRY: Duration = Duration::from_secs(5);
+/// Streamed output kept per task; anything past this is dropped with a note.
+const MAX_STREAMED_OUTPUT_BYTES: usize = 1 << 20;
 
-// --- DiscoveredTool and discover_luau_tools (UNCHANGED) ---
+// --- DiscoveredTool and discover_luau_tools ---
 #[derive(Clone, Debug)]
-pub struct DiscoveredTool { pub file_path: PathBuf, }
+pub struct DiscoveredTool {
+    pub file_path: PathBuf,
+    /// Declared with a `--- @readonly` header line; the only discovered tools `--read-only` allows.
+    pub readonly: bool,
+    /// Tools named by `--- @preamble <tool>` lines, run before this tool's arguments chunk.
+    pub preambles: Vec<String>,
+    /// From `@description` header tags.
+    pub description: Option<String>,
+    /// JSON schema built from `@arg` header tags; `None` when the tool declares no arguments.
+    pub arg_schema: Option<serde_json::Value>,
+    /// FNV-1a hash of the file, computed on first use by `GET /tools/manifest`.
+    content_hash: OnceLock<Option<String>>,
+}
+impl DiscoveredTool {
+    fn new(file_path: PathBuf, header: ToolHeader) -> Self {
+        let arg_schema = (!header.args.is_empty()).then(|| tool_arg_schema(&header.args));
+        Self {
+            file_path,
+            readonly: header.readonly,
+            preambles: header.preambles,
+            description: header.description,
+            arg_schema,
+            content_hash: OnceLock::new(),
+        }
+    }
+    /// `None` when the file can no longer be read.
+    pub fn content_hash(&self) -> Option<&str> {
+        self.content_hash
+            .get_or_init(|| {
+                fs::read(&self.file_path)
+                    .ok()
+                    .map(|bytes| format!("fnv1a64:{:016x}", fnv1a_64(&bytes)))
+            })
+            .as_deref()
+    }
+}
+// Stable across runs and platforms, unlike `DefaultHasher`; this only detects drift, it isn't a security check.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+/// Names of the tools `RBXStudioServer` registers itself. Keep in sync with the `#[tool]` methods.
+pub const NATIVE_TOOL_NAMES: &[&str] = &[
+    "run_command",
+    "insert_model",
+    "execute_discovered_luau_tool",
+    "create_remote_event",
+    "create_bindable_event",
+    "clone_instance",
+    "find_instances",
+    "get_attributes",
+    "set_attributes",
+    "create_instance",
+    "wait_for",
+    "group_instances",
+    "set_service_enabled",
+    "get_place_stats",
+    "get_place_info",
+    "with_selection",
+    "export_tree",
+    "set_dispatch_enabled",
+    "list_plugin_sessions",
+    "set_target_session",
+    "list_pending_tasks",
+    "cancel_task",
+    "check_luau",
+    "get_players",
+    "set_property_bulk",
+    "get_run_logs",
+    "create_script",
+    "resolve_path",
+    "diff_trees",
+    "get_properties_bulk",
+    "search_models",
+    "set_anchored",
+    "weld_instances",
+    "set_collision_group",
+    "set_physical_properties",
+    "delete_tagged",
+    "get_workspace_property",
+    "set_workspace_property",
+    "snapshot_view",
+    "restore_view",
+    "get_instance_properties",
+    "batch",
+    "list_luau_tools",
+    "set_instance_properties",
+    "undo",
+    "redo",
+    "set_waypoint",
+    "delete_instance",
+    "execute_luau_file",
+];
+
+/// Plugin tool modules that run the typed tasks (`ToolArgumentValues` variants, with `RunCode`
+/// for `RunCommand`). Keep in sync with the variants, `resolveTask` and `TYPED_TASK_MODULES` in
+/// Main.server.luau.
+const TYPED_TASK_MODULES: &[&str] = &[
+    "RunCode",
+    "InsertModel",
+    "CreateEvent",
+    "CloneInstance",
+    "FindInstances",
+    "GetAttributes",
+    "SetAttributes",
+    "GetPlaceInfo",
+    "CreateInstance",
+    "WaitFor",
+    "GroupInstances",
+    "SetServiceEnabled",
+    "GetPlaceStats",
+    "WithSelection",
+    "ExportTree",
+    "CheckLuau",
+    "GetPlayers",
+    "SetPropertyBulk",
+    "GetRunLogs",
+    "CreateScript",
+    "ResolvePath",
+    "GetPropertiesBulk",
+    "SearchModels",
+    "SetAnchored",
+    "WeldInstances",
+    "SetCollisionGroup",
+    "SetPhysicalProperties",
+    "DeleteTagged",
+    "GetWorkspaceProperty",
+    "SetWorkspaceProperty",
+    "SnapshotView",
+    "RestoreView",
+    "GetInstanceProperties",
+    "SetInstanceProperties",
+    "SetWaypoint",
+    "Undo",
+    "Redo",
+    "DeleteInstance",
+];
+
+// A discovered `run_command.luau` (or `RunCommand.luau`) would be indistinguishable from the
+// native tool to a caller, so native names always win and the file is skipped.
+fn shadows_native_tool(tool_name: &str) -> Option<&'static str> {
+    let normalized = normalize_tool_name(tool_name);
+    NATIVE_TOOL_NAMES
+        .iter()
+        .copied()
+        .find(|native| normalize_tool_name(native) == normalized)
+}
+fn normalize_tool_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Finds every `.luau` file under `tools_dir_path`. Files in subfolders get namespaced names, so
+/// `Lighting/SetAmbient.luau` is the tool `Lighting.SetAmbient`. Hidden files and folders are
+/// skipped, and entries are visited in sorted order so a name collision always keeps the same file.
 pub fn discover_luau_tools(tools_dir_path: &Path) -> HashMap<String, DiscoveredTool> {
     let mut tools = HashMap::new();
-    if !tools_dir_path.exists() { return tools; }
-    if let Ok(entries) = fs::read_dir(tools_dir_path) {
-        for entry in entries.filter_map(Result::ok) {
-            let path = entry.path();
-            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("luau") {
-                if let Some(tool_name) = path.file_stem().and_then(|s| s.to_str()).map(String::from) {
-                    tools.insert(tool_name, DiscoveredTool { file_path: path });
+    if !tools_dir_path.exists() {
+        return tools;
+    }
+    discover_in(tools_dir_path, "", &mut tools);
+    info!("Discovered {} Luau tools", tools.len());
+    tools
+}
+fn discover_in(dir: &Path, namespace: &str, tools: &mut HashMap<String, DiscoveredTool>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        // `file_type` doesn't follow symlinks, so a linked folder can't make discovery loop.
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            discover_in(&path, &format!("{}{}.", namespace, name), tools);
+            continue;
+        }
+        if !path.is_file() || path.extension().and_then(|s| s.to_str()) != Some("luau") {
+            continue;
+        }
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        // Through execute_discovered_luau_tool these would skip their typed tool's checks
+        // (--allow-dangerous, --read-only, allowlists, argument validation).
+        if namespace.is_empty() && TYPED_TASK_MODULES.contains(&stem) {
+            debug!(
+                "Not exposing {} as a Luau tool: it runs a typed tool's tasks.",
+                path.display()
+            );
+            continue;
+        }
+        let tool_name = format!("{}{}", namespace, stem);
+        if let Some(native) = shadows_native_tool(&tool_name) {
+            warn!("Skipping discovered Luau tool {}: its name shadows the native tool '{}'. Rename the file to make it callable.", path.display(), native);
+            continue;
+        }
+        if let Some(existing) = tools.get(&tool_name) {
+            warn!(
+                "Skipping discovered Luau tool {}: '{}' is already provided by {}.",
+                path.display(),
+                tool_name,
+                existing.file_path.display()
+            );
+            continue;
+        }
+        let header = fs::read_to_string(&path)
+            .map(|source| parse_tool_header(&source))
+            .unwrap_or_default();
+        for warning in &header.warnings {
+            warn!("Luau tool {}: {}", path.display(), warning);
+        }
+        tools.insert(tool_name, DiscoveredTool::new(path, header));
+    }
+}
+/// The discovered tools, replaced as a whole when `watch_tools_dir` sees the folder change.
+/// Callers take a `snapshot` so one tool call sees one consistent set.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Arc<RwLock<Arc<HashMap<String, DiscoveredTool>>>>,
+}
+impl ToolRegistry {
+    pub fn new(tools: HashMap<String, DiscoveredTool>) -> Self {
+        Self {
+            tools: Arc::new(RwLock::new(Arc::new(tools))),
+        }
+    }
+    pub fn snapshot(&self) -> Arc<HashMap<String, DiscoveredTool>> {
+        match self.tools.read() {
+            Ok(tools) => tools.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+    fn replace(&self, tools: HashMap<String, DiscoveredTool>) {
+        let mut current = match self.tools.write() {
+            Ok(current) => current,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *current = Arc::new(tools);
+    }
+}
+
+// How often the tools folder is checked. A change is only picked up once the folder has looked
+// the same for one more check, so the burst of writes from a single editor save reloads once.
+const TOOLS_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// Modification time and size of every file discovery would consider, keyed by path.
+fn tools_fingerprint(dir: &Path, fingerprint: &mut HashMap<PathBuf, (Option<SystemTime>, u64)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            tools_fingerprint(&path, fingerprint);
+        } else if path.extension().and_then(|s| s.to_str()) == Some("luau") {
+            if let Ok(metadata) = fs::metadata(&path) {
+                fingerprint.insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+}
+
+/// Polls `tools_dir` and re-runs discovery into `registry` whenever a tool file is added,
+/// removed or edited, logging which tools changed. Runs until the process exits.
+pub async fn watch_tools_dir(registry: ToolRegistry, tools_dir: PathBuf) {
+    let mut applied = HashMap::new();
+    tools_fingerprint(&tools_dir, &mut applied);
+    let mut seen = applied.clone();
+    let mut interval = tokio::time::interval(TOOLS_WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut current = HashMap::new();
+        tools_fingerprint(&tools_dir, &mut current);
+        if current != seen {
+            seen = current;
+            continue;
+        }
+        if current == applied {
+            continue;
+        }
+        let previous = registry.snapshot();
+        let tools = discover_luau_tools(&tools_dir);
+        let mut added: Vec<&str> = tools
+            .keys()
+            .filter(|name| !previous.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        let mut removed: Vec<&str> = previous
+            .keys()
+            .filter(|name| !tools.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        let mut changed: Vec<&str> = tools
+            .iter()
+            .filter(|(name, tool)| {
+                previous.get(*name).is_some_and(|old| {
+                    old.file_path != tool.file_path
+                        || applied.get(&tool.file_path) != current.get(&tool.file_path)
+                })
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        added.sort();
+        removed.sort();
+        changed.sort();
+        if !added.is_empty() || !removed.is_empty() || !changed.is_empty() {
+            info!(added = ?added, removed = ?removed, changed = ?changed, "Reloaded discovered Luau tools.");
+        }
+        registry.replace(tools);
+        applied = current;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ToolHeader {
+    readonly: bool,
+    preambles: Vec<String>,
+    description: Option<String>,
+    args: Vec<ToolArg>,
+    /// Tags that couldn't be understood; logged by discovery and otherwise ignored.
+    warnings: Vec<String>,
+}
+#[derive(Debug)]
+struct ToolArg {
+    name: String,
+    /// JSON schema type, mapped from the Luau-ish type in the tag.
+    json_type: &'static str,
+    optional: bool,
+    description: Option<String>,
+}
+// `@arg` types and the JSON schema type each one maps to.
+const TOOL_ARG_TYPES: &[(&str, &str)] = &[
+    ("string", "string"),
+    ("number", "number"),
+    ("integer", "integer"),
+    ("boolean", "boolean"),
+    ("table", "object"),
+    ("array", "array"),
+];
+// Lines of the header, before any code: `---` comment lines and the inside of a leading
+// `--[[ ... ]]` block. Plain `--` comments are skipped.
+fn header_lines(source: &str, warnings: &mut Vec<String>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut block: Option<Vec<String>> = None;
+    for line in source.lines().map(str::trim) {
+        if let Some(block_lines) = block.as_mut() {
+            match line.split_once("]]") {
+                Some((inside, _)) => {
+                    block_lines.push(inside.trim().to_string());
+                    lines.append(block_lines);
+                    block = None;
                 }
+                None => block_lines.push(line.to_string()),
             }
+        } else if let Some(rest) = line.strip_prefix("--[[") {
+            match rest.split_once("]]") {
+                Some((inside, _)) => lines.push(inside.trim().to_string()),
+                None => block = Some(vec![rest.trim().to_string()]),
+            }
+        } else if let Some(tag) = line.strip_prefix("---") {
+            lines.push(tag.trim().to_string());
+        } else if !line.is_empty() && !line.starts_with("--") {
+            break;
         }
     }
-    info!("Discovered {} Luau tools", tools.len());
-    tools
+    if block.is_some() {
+        warnings.push("the leading --[[ metadata block is never closed; ignoring it".to_string());
+    }
+    lines
+}
+// `name:type`, or `name?:type` for an optional argument, then an optional description.
+fn parse_tool_arg(spec: &str) -> Option<ToolArg> {
+    let (declaration, description) = spec.split_once(char::is_whitespace).unwrap_or((spec, ""));
+    let (name, luau_type) = declaration.split_once(':')?;
+    let (name, optional) = match name.strip_suffix('?') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let json_type = TOOL_ARG_TYPES
+        .iter()
+        .find(|(tag_type, _)| tag_type.eq_ignore_ascii_case(luau_type))?
+        .1;
+    let description = description.trim().trim_start_matches('-').trim();
+    Some(ToolArg {
+        name: name.to_string(),
+        json_type,
+        optional,
+        description: (!description.is_empty()).then(|| description.to_string()),
+    })
+}
+// Header tags live in the leading block of `---` comment lines (or a `--[[ ]]` block), before any
+// code. A line that isn't a tag continues the description of the `@description` or `@arg` before it.
+fn parse_tool_header(source: &str) -> ToolHeader {
+    let mut header = ToolHeader::default();
+    let lines = header_lines(source, &mut header.warnings);
+    // What a plain continuation line extends: the description, the last `@arg`, or nothing.
+    let mut continues: Option<Option<usize>> = None;
+    for line in lines {
+        let line = line.as_str();
+        if line.is_empty() {
+            continues = None;
+        } else if line == "@readonly" {
+            header.readonly = true;
+            continues = None;
+        } else if let Some(name) = line.strip_prefix("@preamble ") {
+            header.preambles.push(name.trim().to_string());
+            continues = None;
+        } else if let Some(text) = line.strip_prefix("@description") {
+            append_description(&mut header.description, text);
+            continues = Some(None);
+        } else if let Some(spec) = line.strip_prefix("@arg ") {
+            match parse_tool_arg(spec.trim()) {
+                Some(arg) => {
+                    header.args.push(arg);
+                    continues = Some(Some(header.args.len() - 1));
+                }
+                None => {
+                    let types: Vec<&str> = TOOL_ARG_TYPES
+                        .iter()
+                        .map(|(tag_type, _)| *tag_type)
+                        .collect();
+                    header.warnings.push(format!("ignoring malformed '@arg {}'; expected '@arg name:type' with type one of {}", spec.trim(), types.join(", ")));
+                    continues = None;
+                }
+            }
+        } else if line.starts_with('@') {
+            header
+                .warnings
+                .push(format!("ignoring unknown header tag '{}'", line));
+            continues = None;
+        } else {
+            match continues {
+                Some(Some(index)) => append_description(&mut header.args[index].description, line),
+                Some(None) => append_description(&mut header.description, line),
+                None => {}
+            }
+        }
+    }
+    header
+}
+fn append_description(description: &mut Option<String>, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+    match description {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(text);
+        }
+        None => *description = Some(text.to_string()),
+    }
+}
+/// `{ "type": "object", "properties", "required" }` for the tool's `@arg` tags, describing the
+/// table `tool_arguments_luau` should return.
+fn tool_arg_schema(args: &[ToolArg]) -> serde_json::Value {
+    let mut properties = serde_json::Map::new();
+    for arg in args {
+        let mut property = serde_json::json!({ "type": arg.json_type });
+        if let Some(description) = &arg.description {
+            property["description"] = serde_json::Value::String(description.clone());
+        }
+        properties.insert(arg.name.clone(), property);
+    }
+    let required: Vec<&str> = args
+        .iter()
+        .filter(|arg| !arg.optional)
+        .map(|arg| arg.name.as_str())
+        .collect();
+    serde_json::json!({ "type": "object", "properties": properties, "required": required })
+}
+
+// --- StateManager and related enums/structs ---
+/// What the StateManager hands back to a dispatcher once its task resolves.
+#[derive(Debug)]
+pub struct TaskCompletion {
+    pub result: CallToolResult,
+    /// Time between the task being queued and a plugin poll picking it up.
+    pub queue_wait: Option<Duration>,
+    /// Size of the result body the plugin posted.
+    pub response_bytes: usize,
+}
+pub type TaskResponder = oneshot::Sender<Result<TaskCompletion, McpError>>;
+
+/// Answer to a plugin poll. A poll nobody answers before `LONG_POLL_DURATION` is simply empty.
+#[derive(Debug)]
+pub enum PollOutcome {
+    Task(ToolArguments),
+    /// Dispatch is paused; the plugin should tell the user and poll less often.
+    Paused,
 }
 
-// --- StateManager and related enums/structs (UNCHANGED) ---
 #[derive(Debug)]
 pub enum StateManagerCommand {
-    DispatchTask { args: ToolArguments, response_tx: oneshot::Sender<Result<CallToolResult, McpError>>, },
-    PollForTask { response_tx: oneshot::Sender<Option<ToolArguments>>, },
-    SubmitTaskResult { task_id: Uuid, result: CallToolResult, },
+    /// `started_tx` fires when a plugin poll picks the task up.
+    /// `session` restricts delivery to one plugin session; otherwise waiting sessions take turns.
+    DispatchTask {
+        args: ToolArguments,
+        session: Option<String>,
+        response_tx: TaskResponder,
+        started_tx: oneshot::Sender<()>,
+    },
+    /// `session` is the poller's `X-MCP-Plugin-Session` id, absent for plugins that predate it.
+    PollForTask {
+        session: Option<String>,
+        response_tx: oneshot::Sender<PollOutcome>,
+    },
+    /// `accepted_tx` answers false when no caller is waiting for the task any more.
+    SubmitTaskResult {
+        task_id: Uuid,
+        result: CallToolResult,
+        response_bytes: usize,
+        accepted_tx: oneshot::Sender<bool>,
+    },
+    /// Fails every outstanding task with a shutdown error, answers with how many there were, and
+    /// stops the `StateManager`; commands sent afterwards find it gone.
+    Shutdown { response_tx: oneshot::Sender<usize> },
+    /// A chunk of output the plugin streamed while running the task; buffered until its result arrives.
+    AppendTaskOutput {
+        task_id: Uuid,
+        chunk: String,
+        accepted_tx: oneshot::Sender<bool>,
+    },
+    /// The plugin answered but the result was unusable; fail the caller now instead of letting it time out.
+    FailTask {
+        task_id: Uuid,
+        message: String,
+        accepted_tx: oneshot::Sender<bool>,
+    },
+    /// The dispatcher stopped waiting; forget the task and count it against the circuit breaker.
+    /// `output_tx`, when given, receives what the plugin streamed for the task before it timed out.
+    TaskTimedOut {
+        task_id: Uuid,
+        reason: CancelReason,
+        output_tx: Option<oneshot::Sender<String>>,
+    },
+    /// Where a task is, for `GET /tasks/{id}/status`.
+    GetTaskStatus {
+        task_id: Uuid,
+        response_tx: oneshot::Sender<TaskStatus>,
+    },
+    ListSessions {
+        response_tx: oneshot::Sender<Vec<SessionInfo>>,
+    },
+    /// Queue and connection counters for `GET /healthz`.
+    GetStats {
+        response_tx: oneshot::Sender<ServerStats>,
+    },
+    /// Running totals for `GET /metrics`.
+    GetMetrics {
+        response_tx: oneshot::Sender<MetricsSnapshot>,
+    },
+    /// How long a plugin long-poll waited before it was answered, for the poll wait histogram.
+    RecordPollWait { waited: Duration },
+    /// Fails a queued or running task on behalf of the client. Replies whether the task was found.
+    CancelTask {
+        task_id: Uuid,
+        response_tx: oneshot::Sender<bool>,
+    },
+    ListPendingTasks {
+        response_tx: oneshot::Sender<Vec<PendingTaskInfo>>,
+    },
+    /// Pause or resume handing tasks to the plugin. Replies with the resulting state.
+    SetDispatchEnabled {
+        enabled: bool,
+        response_tx: oneshot::Sender<DispatchState>,
+    },
+}
+
+/// Why the server gave up on a task.
+#[derive(rmcp::serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    /// No plugin picked the task up within `QUEUE_WAIT_TIMEOUT`.
+    QueueWaitTimeout,
+    /// The plugin took the task but returned nothing within its execution timeout.
+    ExecutionTimeout,
+    /// The task was addressed to a plugin session that stopped polling before taking it.
+    SessionExpired,
+    /// The client called `cancel_task`.
+    CancelledByClient,
+    /// The task was still pending after the `StateManager`'s task TTL.
+    TtlExpired,
+}
+
+/// A task's state as reported to the plugin. Completed and never-seen tasks are both `unknown`.
+#[derive(rmcp::serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Cancelled { reason: CancelReason },
+    Unknown,
+}
+
+// Cancellation reasons are kept for this many of the most recent cancelled tasks.
+const MAX_REMEMBERED_CANCELLATIONS: usize = 256;
+
+/// Snapshot served by `GET /healthz`.
+#[derive(rmcp::serde::Serialize, Clone, Copy, Debug)]
+pub struct ServerStats {
+    /// Tasks waiting for a plugin to pick them up.
+    pub queue_len: usize,
+    /// Tasks queued or running whose result hasn't arrived.
+    pub pending_tasks: usize,
+    /// Whether any plugin session has a long-poll open right now.
+    pub client_waiting: bool,
+    pub sessions: usize,
+    pub dispatch_enabled: bool,
+    /// Circuit breaker around the plugin connection; `open` means dispatches are failing fast.
+    pub breaker: BreakerState,
+}
+
+/// Whether tasks are being delivered, and how many are waiting for delivery.
+#[derive(rmcp::serde::Serialize, Clone, Copy, Debug)]
+pub struct DispatchState {
+    pub dispatch_enabled: bool,
+    pub queued: usize,
+}
+/// A task the server is still waiting on, as listed by `list_pending_tasks`.
+#[derive(rmcp::serde::Serialize, Clone, Debug)]
+pub struct PendingTaskInfo {
+    pub task_id: Uuid,
+    pub tool: &'static str,
+    #[serde(flatten)]
+    pub status: TaskStatus,
+    pub age_secs: u64,
+    /// Bytes of output the plugin has streamed for the task so far.
+    pub streamed_bytes: usize,
+}
+struct PendingTask {
+    response_tx: TaskResponder,
+    tool: &'static str,
+    queued_at: Instant,
+    picked_up_at: Option<Instant>,
+    /// Plugin session that took the task.
+    picked_up_by: Option<String>,
+    started_tx: Option<oneshot::Sender<()>>,
+    /// Output the plugin streamed while running the task, appended to its result.
+    streamed_output: String,
+    idempotency_key: Option<String>,
+}
+// Successful results kept for repeated dispatches under the same idempotency key.
+const MAX_IDEMPOTENCY_KEYS: usize = 256;
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(600);
+
+// A result remembered under an idempotency key; `tool` guards against a key reused for another tool.
+struct IdempotentResult {
+    key: String,
+    tool: &'static str,
+    result: CallToolResult,
+    response_bytes: usize,
+    completed_at: Instant,
+}
+// Consecutive timeouts that open the circuit, and how long it stays open before a probe.
+const BREAKER_FAILURE_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(rmcp::serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    /// Dispatches fail fast until the cooldown has passed.
+    Open,
+    /// One probe task is let through; its outcome closes or re-opens the circuit.
+    HalfOpen,
+}
+
+// Stops queueing work for a plugin that keeps timing out. Tool-level errors the plugin
+// reports (`isError`) are answers, so only timeouts count as failures.
+struct CircuitBreaker {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe: Option<Uuid>,
+}
+impl CircuitBreaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            probe: None,
+        }
+    }
+    // Returns the remaining cooldown when the task must be rejected.
+    fn admit(&mut self, task_id: Uuid) -> Option<Duration> {
+        match self.state {
+            BreakerState::Closed => None,
+            BreakerState::Open => {
+                let elapsed = self
+                    .opened_at
+                    .map_or(BREAKER_COOLDOWN, |opened_at| opened_at.elapsed());
+                if elapsed < BREAKER_COOLDOWN {
+                    return Some(BREAKER_COOLDOWN - elapsed);
+                }
+                info!(target: "state_manager", task_id=%task_id, "Circuit half-open; sending probe task.");
+                self.state = BreakerState::HalfOpen;
+                self.probe = Some(task_id);
+                None
+            }
+            BreakerState::HalfOpen => Some(Duration::ZERO),
+        }
+    }
+    fn record_success(&mut self, task_id: Uuid) {
+        if self.state == BreakerState::HalfOpen && self.probe != Some(task_id) {
+            return;
+        }
+        if self.state != BreakerState::Closed {
+            info!(target: "state_manager", task_id=%task_id, "Probe succeeded; circuit closed.");
+        }
+        *self = Self::new();
+    }
+    fn record_failure(&mut self, task_id: Uuid) {
+        match self.state {
+            BreakerState::HalfOpen if self.probe == Some(task_id) => self.open(),
+            BreakerState::HalfOpen | BreakerState::Open => {}
+            BreakerState::Closed => {
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= BREAKER_FAILURE_THRESHOLD {
+                    self.open();
+                }
+            }
+        }
+    }
+    fn open(&mut self) {
+        warn!(target: "state_manager", failures = self.consecutive_failures, "Circuit opened; failing dispatches fast for {}s.", BREAKER_COOLDOWN.as_secs());
+        self.state = BreakerState::Open;
+        self.opened_at = Some(Instant::now());
+        self.probe = None;
+    }
+}
+
+// Task counts since the last health summary.
+#[derive(Default)]
+struct IntervalStats {
+    dispatched: u64,
+    completed: u64,
+    timed_out: u64,
+}
+
+// Session key for polls from plugins that predate `X-MCP-Plugin-Session`.
+const DEFAULT_SESSION: &str = "default";
+// A session that hasn't polled for this long is forgotten, along with the tasks addressed to it.
+const SESSION_IDLE_EXPIRY: Duration = Duration::from_secs(60);
+
+// One connected plugin, i.e. one Studio window (a reloaded plugin is a new session).
+struct PluginSession {
+    waiter: Option<oneshot::Sender<PollOutcome>>,
+    /// Tasks dispatched to this session alone; untargeted tasks wait in `StateManager::task_queue`.
+    queue: VecDeque<ToolArguments>,
+    last_poll_at: Instant,
 }
+impl PluginSession {
+    // The long-poll's request ends after `LONG_POLL_DURATION`, leaving a closed waiter behind.
+    fn is_waiting(&self) -> bool {
+        self.waiter
+            .as_ref()
+            .is_some_and(|waiter| !waiter.is_closed())
+    }
+}
+
+/// A connected plugin session as listed by `list_plugin_sessions`.
+#[derive(rmcp::serde::Serialize, Clone, Debug)]
+pub struct SessionInfo {
+    pub session_id: String,
+    /// Whether the plugin has a poll open, i.e. is idle and ready for a task.
+    pub waiting: bool,
+    /// Tasks queued for this session alone.
+    pub queued: usize,
+    pub in_flight: usize,
+    pub last_poll_secs_ago: u64,
+}
+
 pub struct StateManager {
+    /// Tasks for whichever session polls first.
     task_queue: VecDeque<ToolArguments>,
-    pending_tasks: HashMap<Uuid, oneshot::Sender<Result<CallToolResult, McpError>>>,
-    client_waiter: Option<oneshot::Sender<Option<ToolArguments>>>,
+    pending_tasks: HashMap<Uuid, PendingTask>,
+    sessions: HashMap<String, PluginSession>,
+    /// Session that received the last untargeted task handed straight to a waiter, for round-robin.
+    last_served: Option<String>,
+    breaker: CircuitBreaker,
+    /// Cleared by `set_dispatch_enabled(false)`: tasks still queue but are not delivered.
+    dispatch_enabled: bool,
+    /// How often `run` logs a health summary; `None` disables it.
+    summary_interval: Option<Duration>,
+    stats: IntervalStats,
+    /// Recently cancelled tasks, oldest first, so the plugin can learn why a task was dropped.
+    cancelled: VecDeque<(Uuid, CancelReason)>,
+    journal: Option<QueueJournal>,
+    /// Pending tasks older than this are dropped by the reaper in `run`.
+    task_ttl: Duration,
+    /// Recent successful results by idempotency key, least recently used first.
+    idempotent_results: VecDeque<IdempotentResult>,
+    /// Totals since start for `GET /metrics`; unlike `stats`, never reset.
+    totals: TaskMetrics,
+    /// Dispatches are refused while this many tasks wait for delivery.
+    max_queued_tasks: usize,
 }
 impl StateManager {
-    pub fn new() -> Self { Self { task_queue: VecDeque::new(), pending_tasks: HashMap::new(), client_waiter: None, } }
+    pub fn new() -> Self {
+        Self {
+            task_queue: VecDeque::new(),
+            pending_tasks: HashMap::new(),
+            sessions: HashMap::new(),
+            last_served: None,
+            breaker: CircuitBreaker::new(),
+            dispatch_enabled: true,
+            summary_interval: None,
+            stats: IntervalStats::default(),
+            cancelled: VecDeque::new(),
+            journal: None,
+            task_ttl: PENDING_TASK_TTL,
+            idempotent_results: VecDeque::new(),
+            totals: TaskMetrics::default(),
+            max_queued_tasks: DEFAULT_MAX_QUEUED_TASKS,
+        }
+    }
+    /// A limit of at least 1; tasks recovered from the queue journal are queued regardless.
+    pub fn with_max_queued_tasks(mut self, max: usize) -> Self {
+        self.max_queued_tasks = max.max(1);
+        self
+    }
+    /// Clamped to `MIN_PENDING_TASK_TTL` so a task is never reaped while its caller may still be waiting.
+    pub fn with_task_ttl(mut self, ttl: Duration) -> Self {
+        self.task_ttl = ttl.max(MIN_PENDING_TASK_TTL);
+        self
+    }
+    // Fails every pending task's caller and empties the queues, returning how many were failed.
+    // Queued tasks stay in the journal, if there is one, so the next run still re-queues them.
+    fn fail_all_for_shutdown(&mut self) -> usize {
+        let message = if self.journal.is_some() {
+            "The server is shutting down. Undelivered tasks stay in the queue journal and run when it restarts."
+        } else {
+            "The server is shutting down."
+        };
+        self.task_queue.clear();
+        for session in self.sessions.values_mut() {
+            session.queue.clear();
+            session.waiter = None;
+        }
+        let failed = self.pending_tasks.len();
+        for (task_id, pending) in self.pending_tasks.drain() {
+            info!(target: "state_manager", task_id=%task_id, tool=pending.tool, "Failing task at shutdown.");
+            let _ = pending
+                .response_tx
+                .send(Err(McpError::internal_error(message, None)));
+        }
+        failed
+    }
+    // Drops tasks whose caller should long since have given up, e.g. because its future was
+    // dropped before the timeout could report it.
+    fn reap_expired_tasks(&mut self) {
+        let ttl = self.task_ttl;
+        let expired: Vec<Uuid> = self
+            .pending_tasks
+            .iter()
+            .filter(|(_, pending)| pending.queued_at.elapsed() >= ttl)
+            .map(|(task_id, _)| *task_id)
+            .collect();
+        for task_id in expired {
+            let Some(pending) = self.pending_tasks.remove(&task_id) else {
+                continue;
+            };
+            warn!(target: "state_manager", task_id=%task_id, tool=pending.tool, "Reaping task left pending for over {}s.", ttl.as_secs());
+            self.journal_completed(task_id);
+            self.remember_cancellation(task_id, CancelReason::TtlExpired);
+            self.totals.cancelled += 1;
+            self.task_queue.retain(|task| task.id != Some(task_id));
+            for session in self.sessions.values_mut() {
+                session.queue.retain(|task| task.id != Some(task_id));
+            }
+            let message = format!(
+                "The task was dropped after {}s without a result.",
+                ttl.as_secs()
+            );
+            let _ = pending
+                .response_tx
+                .send(Err(McpError::internal_error(message, None)));
+        }
+    }
+    /// Journals queued tasks to `path`, first re-queueing the ones a previous run accepted but
+    /// never delivered. Their original callers are gone, so their results are only logged.
+    pub fn with_queue_journal(mut self, path: &Path) -> color_eyre::Result<Self> {
+        let (journal, recovered) = QueueJournal::open(path)?;
+        if !recovered.is_empty() {
+            info!(target: "state_manager", count = recovered.len(), "Re-queueing tasks left undelivered by the previous run.");
+        }
+        for task in recovered {
+            let Some(task_id) = task.id() else { continue };
+            // Nobody listens on these channels; sends to them just fail.
+            let (response_tx, _) = oneshot::channel();
+            let (started_tx, _) = oneshot::channel();
+            self.pending_tasks.insert(
+                task_id,
+                PendingTask {
+                    response_tx,
+                    tool: task.args.variant_name(),
+                    queued_at: Instant::now(),
+                    picked_up_at: None,
+                    picked_up_by: None,
+                    started_tx: Some(started_tx),
+                    streamed_output: String::new(),
+                    idempotency_key: task.idempotency_key.clone(),
+                },
+            );
+            enqueue(&mut self.task_queue, task);
+        }
+        self.journal = Some(journal);
+        Ok(self)
+    }
+    // Call wherever a task leaves `pending_tasks`.
+    fn journal_completed(&mut self, task_id: Uuid) {
+        if let Some(journal) = &mut self.journal {
+            journal.record_completed(task_id);
+        }
+    }
+    // Looks up a result for `key`, refreshing its place in the LRU order. Entries past
+    // `IDEMPOTENCY_KEY_TTL` are dropped first.
+    fn idempotent_result(&mut self, key: &str) -> Option<&IdempotentResult> {
+        self.idempotent_results
+            .retain(|entry| entry.completed_at.elapsed() < IDEMPOTENCY_KEY_TTL);
+        let index = self
+            .idempotent_results
+            .iter()
+            .position(|entry| entry.key == key)?;
+        let entry = self.idempotent_results.remove(index)?;
+        self.idempotent_results.push_back(entry);
+        self.idempotent_results.back()
+    }
+    // Error results aren't remembered, so a retry after a failure runs the task again.
+    fn remember_idempotent_result(
+        &mut self,
+        key: String,
+        tool: &'static str,
+        result: &CallToolResult,
+        response_bytes: usize,
+    ) {
+        if result.is_error == Some(true) {
+            return;
+        }
+        self.idempotent_results.retain(|entry| entry.key != key);
+        if self.idempotent_results.len() == MAX_IDEMPOTENCY_KEYS {
+            self.idempotent_results.pop_front();
+        }
+        self.idempotent_results.push_back(IdempotentResult {
+            key,
+            tool,
+            result: result.clone(),
+            response_bytes,
+            completed_at: Instant::now(),
+        });
+    }
+    fn remember_cancellation(&mut self, task_id: Uuid, reason: CancelReason) {
+        if self.cancelled.len() == MAX_REMEMBERED_CANCELLATIONS {
+            self.cancelled.pop_front();
+        }
+        self.cancelled.push_back((task_id, reason));
+    }
+    // The dispatcher gets a cancellation error right away. A plugin already running the task
+    // finishes it, but sees the cancellation via `GET /tasks/{id}/status` and drops the result.
+    fn cancel_task(&mut self, task_id: Uuid) -> bool {
+        self.task_queue.retain(|task| task.id != Some(task_id));
+        for session in self.sessions.values_mut() {
+            session.queue.retain(|task| task.id != Some(task_id));
+        }
+        let Some(pending) = self.pending_tasks.remove(&task_id) else {
+            return false;
+        };
+        self.journal_completed(task_id);
+        info!(target: "state_manager", task_id=%task_id, running = pending.picked_up_at.is_some(), "Task cancelled by the client.");
+        self.remember_cancellation(task_id, CancelReason::CancelledByClient);
+        self.totals.cancelled += 1;
+        let _ = pending.response_tx.send(Err(McpError::new(
+            rmcp::model::ErrorCode::INTERNAL_ERROR,
+            "The task was cancelled with cancel_task.",
+            Some(serde_json::json!({ "task_id": task_id, "cancelled": true })),
+        )));
+        true
+    }
+    fn task_status(&self, task_id: Uuid) -> TaskStatus {
+        if let Some(pending) = self.pending_tasks.get(&task_id) {
+            return if pending.picked_up_at.is_some() {
+                TaskStatus::Running
+            } else {
+                TaskStatus::Queued
+            };
+        }
+        self.cancelled
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == task_id)
+            .map_or(TaskStatus::Unknown, |(_, reason)| TaskStatus::Cancelled {
+                reason: *reason,
+            })
+    }
+    /// A zero interval disables the summary.
+    pub fn with_summary_interval(mut self, interval: Duration) -> Self {
+        self.summary_interval = Some(interval).filter(|interval| !interval.is_zero());
+        self
+    }
+    // A plugin counts as connected while it holds a long-poll or polled within the last two poll windows.
+    fn plugin_connected(&self) -> bool {
+        self.sessions.values().any(|session| {
+            session.is_waiting() || session.last_poll_at.elapsed() < LONG_POLL_DURATION * 2
+        })
+    }
+    fn queued_len(&self) -> usize {
+        self.task_queue.len()
+            + self
+                .sessions
+                .values()
+                .map(|session| session.queue.len())
+                .sum::<usize>()
+    }
+    fn log_summary(&mut self) {
+        let stats = std::mem::take(&mut self.stats);
+        info!(
+            target: "state_manager",
+            dispatched = stats.dispatched,
+            completed = stats.completed,
+            timed_out = stats.timed_out,
+            queued = self.queued_len(),
+            in_flight = self.pending_tasks.len(),
+            sessions = self.sessions.len(),
+            plugin_connected = self.plugin_connected(),
+            "Health summary for the last {}s.",
+            self.summary_interval.unwrap_or_default().as_secs()
+        );
+    }
+    fn session_info(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .iter()
+            .map(|(session_id, session)| SessionInfo {
+                session_id: session_id.clone(),
+                waiting: session.is_waiting(),
+                queued: session.queue.len(),
+                in_flight: self
+                    .pending_tasks
+                    .values()
+                    .filter(|pending| pending.picked_up_by.as_deref() == Some(session_id.as_str()))
+                    .count(),
+                last_poll_secs_ago: session.last_poll_at.elapsed().as_secs(),
+            })
+            .collect();
+        sessions.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+        sessions
+    }
+    fn mark_picked_up(&mut self, task_id: Uuid, session: &str) {
+        if let Some(journal) = &mut self.journal {
+            journal.record_delivered(task_id);
+        }
+        if let Some(pending) = self.pending_tasks.get_mut(&task_id) {
+            pending.picked_up_at = Some(Instant::now());
+            pending.picked_up_by = Some(session.to_string());
+            if let Some(started_tx) = pending.started_tx.take() {
+                let _ = started_tx.send(());
+            }
+        }
+    }
+    // Hands `task` to the open poll of `session_id`. Gives the task back if the session has no
+    // poll open or the poll's request has already gone away.
+    fn deliver(&mut self, session_id: &str, task: ToolArguments) -> Result<(), ToolArguments> {
+        let Some(waiter) = self
+            .sessions
+            .get_mut(session_id)
+            .and_then(|session| session.waiter.take())
+        else {
+            return Err(task);
+        };
+        let task_id = task.id.expect("Task must have ID");
+        match waiter.send(PollOutcome::Task(task)) {
+            Ok(()) => {
+                self.mark_picked_up(task_id, session_id);
+                Ok(())
+            }
+            Err(PollOutcome::Task(task)) => Err(task),
+            Err(PollOutcome::Paused) => unreachable!("a task was sent"),
+        }
+    }
+    // Answers a poll with a task taken from the front of a queue, putting it back if the poll is gone.
+    fn send_queued(
+        &mut self,
+        session_id: &str,
+        response_tx: oneshot::Sender<PollOutcome>,
+        task: ToolArguments,
+        targeted: bool,
+    ) {
+        let task_id = task.id.expect("Task must have ID");
+        info!(target: "state_manager", task_id=%task_id, session=%session_id, "Dispatching queued task to new poller.");
+        match response_tx.send(PollOutcome::Task(task)) {
+            Ok(()) => self.mark_picked_up(task_id, session_id),
+            Err(PollOutcome::Task(task)) => {
+                match self.sessions.get_mut(session_id).filter(|_| targeted) {
+                    Some(session) => session.queue.push_front(task),
+                    None => self.task_queue.push_front(task),
+                }
+            }
+            Err(PollOutcome::Paused) => unreachable!("a task was sent"),
+        }
+    }
+    // Untargeted tasks go to waiting sessions in turn, starting after the one served last.
+    fn deliver_round_robin(&mut self, mut task: ToolArguments) -> Result<(), ToolArguments> {
+        let mut waiting: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.is_waiting())
+            .map(|(id, _)| id.clone())
+            .collect();
+        waiting.sort();
+        let start = self.last_served.as_ref().map_or(0, |last| {
+            waiting.iter().position(|id| id > last).unwrap_or(0)
+        });
+        waiting.rotate_left(start);
+        for session_id in waiting {
+            match self.deliver(&session_id, task) {
+                Ok(()) => {
+                    self.last_served = Some(session_id);
+                    return Ok(());
+                }
+                Err(returned) => task = returned,
+            }
+        }
+        Err(task)
+    }
+    // Forgets sessions that stopped polling, failing the tasks that were addressed to them.
+    // Tasks a session already took are left to their execution timeout.
+    fn expire_idle_sessions(&mut self) {
+        let expired: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| {
+                !session.is_waiting() && session.last_poll_at.elapsed() >= SESSION_IDLE_EXPIRY
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for session_id in expired {
+            let Some(session) = self.sessions.remove(&session_id) else {
+                continue;
+            };
+            info!(target: "state_manager", session=%session_id, "Plugin session stopped polling; forgetting it.");
+            for task in session.queue {
+                let Some(task_id) = task.id else { continue };
+                if let Some(pending) = self.pending_tasks.remove(&task_id) {
+                    self.journal_completed(task_id);
+                    self.remember_cancellation(task_id, CancelReason::SessionExpired);
+                    self.totals.cancelled += 1;
+                    warn!(target: "state_manager", task_id=%task_id, session=%session_id, "Failing task addressed to a plugin session that is gone.");
+                    let message = format!(
+                        "Plugin session '{}' stopped polling before it picked this task up.",
+                        session_id
+                    );
+                    let _ = pending
+                        .response_tx
+                        .send(Err(McpError::internal_error(message, None)));
+                }
+            }
+        }
+    }
     pub async fn run(mut self, mut command_rx: mpsc::Receiver<StateManagerCommand>) {
         info!("State Manager started.");
-        while let Some(command) = command_rx.recv().await {
+        let mut summary_timer = self
+            .summary_interval
+            .map(|period| tokio::time::interval_at(Instant::now() + period, period));
+        let mut reap_timer = tokio::time::interval_at(
+            Instant::now() + PENDING_TASK_REAP_INTERVAL,
+            PENDING_TASK_REAP_INTERVAL,
+        );
+        loop {
+            let summary_tick = async {
+                match summary_timer.as_mut() {
+                    Some(timer) => {
+                        timer.tick().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            let command = tokio::select! {
+                command = command_rx.recv() => command,
+                _ = summary_tick => {
+                    self.log_summary();
+                    continue;
+                }
+                _ = reap_timer.tick() => {
+                    self.reap_expired_tasks();
+                    continue;
+                }
+            };
+            let Some(command) = command else { break };
+            self.expire_idle_sessions();
             match command {
-                StateManagerCommand::DispatchTask { args, response_tx } => {
+                StateManagerCommand::DispatchTask {
+                    args,
+                    session,
+                    response_tx,
+                    started_tx,
+                } => {
                     let task_id = args.id.expect("Task must have ID");
-                    info!(target: "state_manager", task_id=%task_id, "Queueing task for dispatch.");
-                    self.pending_tasks.insert(task_id, response_tx);
-                    if let Some(waiter) = self.client_waiter.take() {
-                        info!(target: "state_manager", task_id=%task_id, "Fulfilling waiting client.");
-                        let _ = waiter.send(Some(args));
-                    } else {
-                        info!(target: "state_manager", task_id=%task_id, "No client waiting, adding to queue.");
-                        self.task_queue.push_back(args);
+                    if let Some(session_id) = &session {
+                        if !self.sessions.contains_key(session_id) {
+                            let message = format!("No plugin session '{}' is connected. Use list_plugin_sessions to see the connected sessions.", session_id);
+                            let _ = response_tx.send(Err(McpError::internal_error(message, None)));
+                            continue;
+                        }
+                    }
+                    if let Some(key) = args.idempotency_key.clone() {
+                        let tool = args.args.variant_name();
+                        if let Some(entry) = self.idempotent_result(&key) {
+                            let outcome = if entry.tool == tool {
+                                info!(target: "state_manager", task_id=%task_id, "Answering repeated idempotency key from the cached result.");
+                                Ok(TaskCompletion {
+                                    result: entry.result.clone(),
+                                    queue_wait: None,
+                                    response_bytes: entry.response_bytes,
+                                })
+                            } else {
+                                let message = format!(
+                                    "Idempotency key '{}' was already used for {}, not {}.",
+                                    key, entry.tool, tool
+                                );
+                                Err(McpError::invalid_params(message, None))
+                            };
+                            let _ = response_tx.send(outcome);
+                            continue;
+                        }
+                        if let Some((running_id, _)) =
+                            self.pending_tasks.iter().find(|(_, pending)| {
+                                pending.idempotency_key.as_deref() == Some(key.as_str())
+                            })
+                        {
+                            let message = format!("Task {} with idempotency key '{}' hasn't finished yet; call again once it has to get its result.", running_id, key);
+                            let _ = response_tx.send(Err(McpError::internal_error(message, None)));
+                            continue;
+                        }
+                    }
+                    if self.queued_len() >= self.max_queued_tasks {
+                        warn!(target: "state_manager", task_id=%task_id, queued = self.queued_len(), "Task queue full; rejecting task.");
+                        let message = format!("The server is busy: {} tasks are already waiting for the Studio plugin. Retry once some have finished.", self.max_queued_tasks);
+                        let data = serde_json::json!({ "queue_full": true, "max_queued_tasks": self.max_queued_tasks });
+                        let _ = response_tx.send(Err(McpError::new(
+                            rmcp::model::ErrorCode::INTERNAL_ERROR,
+                            message,
+                            Some(data),
+                        )));
+                        continue;
+                    }
+                    if let Some(retry_in) = self.breaker.admit(task_id) {
+                        warn!(target: "state_manager", task_id=%task_id, "Circuit open; rejecting task.");
+                        let message = format!("The Studio plugin has repeatedly failed to respond; not dispatching for another {}s. Check that Studio is open and the MCP plugin is running.", retry_in.as_secs().max(1));
+                        let _ = response_tx.send(Err(McpError::internal_error(message, None)));
+                        continue;
+                    }
+                    info!(target: "state_manager", task_id=%task_id, session = ?session, "Queueing task for dispatch.");
+                    self.stats.dispatched += 1;
+                    self.totals.dispatched += 1;
+                    if let Some(journal) = &mut self.journal {
+                        journal.record_queued(&args);
+                    }
+                    self.pending_tasks.insert(
+                        task_id,
+                        PendingTask {
+                            response_tx,
+                            tool: args.args.variant_name(),
+                            queued_at: Instant::now(),
+                            picked_up_at: None,
+                            picked_up_by: None,
+                            started_tx: Some(started_tx),
+                            streamed_output: String::new(),
+                            idempotency_key: args.idempotency_key.clone(),
+                        },
+                    );
+                    if !self.dispatch_enabled {
+                        info!(target: "state_manager", task_id=%task_id, "Dispatch paused, adding to queue.");
+                    }
+                    let delivered = match (&session, self.dispatch_enabled) {
+                        (_, false) => Err(args),
+                        (Some(session_id), true) => self.deliver(&session_id.clone(), args),
+                        (None, true) => self.deliver_round_robin(args),
+                    };
+                    match delivered {
+                        Ok(()) => {
+                            info!(target: "state_manager", task_id=%task_id, "Fulfilling waiting client.")
+                        }
+                        Err(args) => {
+                            if self.dispatch_enabled {
+                                info!(target: "state_manager", task_id=%task_id, "No client waiting, adding to queue.");
+                            }
+                            match session.and_then(|session_id| self.sessions.get_mut(&session_id))
+                            {
+                                Some(target) => enqueue(&mut target.queue, args),
+                                None => enqueue(&mut self.task_queue, args),
+                            }
+                        }
                     }
                 }
-                StateManagerCommand::PollForTask { response_tx } => {
-                    if let Some(task) = self.task_queue.pop_front() {
-                        info!(target: "state_manager", task_id=%task.id.unwrap(), "Dispatching queued task to new poller.");
-                        let _ = response_tx.send(Some(task));
+                StateManagerCommand::PollForTask {
+                    session,
+                    response_tx,
+                } => {
+                    let session_id = session.unwrap_or_else(|| DEFAULT_SESSION.to_string());
+                    let plugin_session = self.sessions.entry(session_id.clone()).or_insert_with(|| {
+                        info!(target: "state_manager", session=%session_id, "Plugin session connected.");
+                        PluginSession { waiter: None, queue: VecDeque::new(), last_poll_at: Instant::now() }
+                    });
+                    plugin_session.last_poll_at = Instant::now();
+                    if !self.dispatch_enabled {
+                        let _ = response_tx.send(PollOutcome::Paused);
+                    } else if !plugin_session.queue.is_empty() || !self.task_queue.is_empty() {
+                        // Tasks for this session win ties with untargeted ones.
+                        let targeted_priority =
+                            plugin_session.queue.front().map(|task| task.priority);
+                        let untargeted_priority = self.task_queue.front().map(|task| task.priority);
+                        if targeted_priority >= untargeted_priority {
+                            let task = plugin_session.queue.pop_front().expect("checked non-empty");
+                            self.send_queued(&session_id, response_tx, task, true);
+                        } else {
+                            let task = self.task_queue.pop_front().expect("checked non-empty");
+                            self.send_queued(&session_id, response_tx, task, false);
+                        }
                     } else {
-                        info!(target: "state_manager", "No tasks in queue, client is now waiting.");
-                        self.client_waiter = Some(response_tx);
+                        info!(target: "state_manager", session=%session_id, "No tasks in queue, client is now waiting.");
+                        plugin_session.waiter = Some(response_tx);
                     }
                 }
-                StateManagerCommand::SubmitTaskResult { task_id, result } => {
+                StateManagerCommand::SubmitTaskResult {
+                    task_id,
+                    result,
+                    response_bytes,
+                    accepted_tx,
+                } => {
                     info!(target: "state_manager", task_id=%task_id, "Received task result from client.");
-                    if let Some(response_tx) = self.pending_tasks.remove(&task_id) {
-                        let _ = response_tx.send(Ok(result));
-                    } else { warn!(target: "state_manager", task_id=%task_id, "Received result for unknown or timed-out task."); }
+                    if let Some(pending) = self.pending_tasks.remove(&task_id) {
+                        self.journal_completed(task_id);
+                        self.breaker.record_success(task_id);
+                        self.stats.completed += 1;
+                        self.totals.completed += 1;
+                        let mut result = result;
+                        if !pending.streamed_output.is_empty() {
+                            result.content.push(Content::text(format!(
+                                "Streamed output:\n{}",
+                                pending.streamed_output
+                            )));
+                        }
+                        if let Some(key) = pending.idempotency_key {
+                            self.remember_idempotent_result(
+                                key,
+                                pending.tool,
+                                &result,
+                                response_bytes,
+                            );
+                        }
+                        let queue_wait = pending
+                            .picked_up_at
+                            .map(|picked_up_at| picked_up_at - pending.queued_at);
+                        let _ = pending.response_tx.send(Ok(TaskCompletion {
+                            result,
+                            queue_wait,
+                            response_bytes,
+                        }));
+                        let _ = accepted_tx.send(true);
+                    } else {
+                        warn!(target: "state_manager", task_id=%task_id, "Received result for unknown or timed-out task.");
+                        let _ = accepted_tx.send(false);
+                    }
+                }
+                StateManagerCommand::Shutdown { response_tx } => {
+                    let failed = self.fail_all_for_shutdown();
+                    let _ = response_tx.send(failed);
+                    break;
+                }
+                StateManagerCommand::AppendTaskOutput {
+                    task_id,
+                    chunk,
+                    accepted_tx,
+                } => {
+                    let Some(pending) = self.pending_tasks.get_mut(&task_id) else {
+                        let _ = accepted_tx.send(false);
+                        continue;
+                    };
+                    let output = &mut pending.streamed_output;
+                    if output.len() < MAX_STREAMED_OUTPUT_BYTES {
+                        let room = MAX_STREAMED_OUTPUT_BYTES - output.len();
+                        if chunk.len() <= room {
+                            output.push_str(&chunk);
+                        } else {
+                            let mut end = room;
+                            while !chunk.is_char_boundary(end) {
+                                end -= 1;
+                            }
+                            output.push_str(&chunk[..end]);
+                            output.push_str("\n[streamed output truncated]\n");
+                        }
+                    }
+                    let _ = accepted_tx.send(true);
+                }
+                StateManagerCommand::FailTask {
+                    task_id,
+                    message,
+                    accepted_tx,
+                } => {
+                    if let Some(pending) = self.pending_tasks.remove(&task_id) {
+                        warn!(target: "state_manager", task_id=%task_id, "Failing task after an unusable plugin response.");
+                        self.journal_completed(task_id);
+                        // The plugin is alive and answering, so this isn't a breaker failure.
+                        self.breaker.record_success(task_id);
+                        self.stats.completed += 1;
+                        self.totals.completed += 1;
+                        let _ = pending
+                            .response_tx
+                            .send(Err(McpError::internal_error(message, None)));
+                        let _ = accepted_tx.send(true);
+                    } else {
+                        let _ = accepted_tx.send(false);
+                    }
+                }
+                StateManagerCommand::TaskTimedOut {
+                    task_id,
+                    reason,
+                    output_tx,
+                } => {
+                    if let Some(pending) = self.pending_tasks.remove(&task_id) {
+                        if let Some(output_tx) = output_tx {
+                            let _ = output_tx.send(pending.streamed_output);
+                        }
+                        self.journal_completed(task_id);
+                        self.stats.timed_out += 1;
+                        self.totals.timed_out += 1;
+                        self.remember_cancellation(task_id, reason);
+                    }
+                    self.task_queue.retain(|task| task.id != Some(task_id));
+                    for session in self.sessions.values_mut() {
+                        session.queue.retain(|task| task.id != Some(task_id));
+                    }
+                    // A task that expired while dispatch was paused says nothing about the plugin.
+                    if self.dispatch_enabled {
+                        self.breaker.record_failure(task_id);
+                    }
+                }
+                StateManagerCommand::GetTaskStatus {
+                    task_id,
+                    response_tx,
+                } => {
+                    let _ = response_tx.send(self.task_status(task_id));
+                }
+                StateManagerCommand::ListSessions { response_tx } => {
+                    let _ = response_tx.send(self.session_info());
+                }
+                StateManagerCommand::GetStats { response_tx } => {
+                    let _ = response_tx.send(ServerStats {
+                        queue_len: self.queued_len(),
+                        pending_tasks: self.pending_tasks.len(),
+                        client_waiting: self.sessions.values().any(PluginSession::is_waiting),
+                        sessions: self.sessions.len(),
+                        dispatch_enabled: self.dispatch_enabled,
+                        breaker: self.breaker.state,
+                    });
+                }
+                StateManagerCommand::GetMetrics { response_tx } => {
+                    let _ = response_tx.send(MetricsSnapshot {
+                        totals: self.totals.clone(),
+                        queue_depth: self.queued_len(),
+                        pending_tasks: self.pending_tasks.len(),
+                    });
+                }
+                StateManagerCommand::RecordPollWait { waited } => {
+                    self.totals.poll_wait.observe(waited)
+                }
+                StateManagerCommand::CancelTask {
+                    task_id,
+                    response_tx,
+                } => {
+                    let _ = response_tx.send(self.cancel_task(task_id));
+                }
+                StateManagerCommand::ListPendingTasks { response_tx } => {
+                    let mut tasks: Vec<PendingTaskInfo> = self
+                        .pending_tasks
+                        .iter()
+                        .map(|(task_id, pending)| PendingTaskInfo {
+                            task_id: *task_id,
+                            tool: pending.tool,
+                            status: self.task_status(*task_id),
+                            age_secs: pending.queued_at.elapsed().as_secs(),
+                            streamed_bytes: pending.streamed_output.len(),
+                        })
+                        .collect();
+                    tasks.sort_by_key(|task| std::cmp::Reverse(task.age_secs));
+                    let _ = response_tx.send(tasks);
+                }
+                StateManagerCommand::SetDispatchEnabled {
+                    enabled,
+                    response_tx,
+                } => {
+                    if self.dispatch_enabled != enabled {
+                        info!(target: "state_manager", enabled, queued = self.queued_len(), "Task dispatch {}.", if enabled { "resumed" } else { "paused" });
+                    }
+                    self.dispatch_enabled = enabled;
+                    if !enabled {
+                        // Release the plugins' open long-polls; their next polls return empty until resumed.
+                        for session in self.sessions.values_mut() {
+                            if let Some(waiter) = session.waiter.take() {
+                                let _ = waiter.send(PollOutcome::Paused);
+                            }
+                        }
+                    }
+                    let _ = response_tx.send(DispatchState {
+                        dispatch_enabled: enabled,
+                        queued: self.queued_len(),
+                    });
                 }
             }
         }
     }
 }
 
-// --- Axum and Tool Argument Structs (UNCHANGED) ---
+// --- Axum and Tool Argument Structs ---
 #[derive(Clone)]
-pub struct AxumSharedState { pub sm_command_tx: mpsc::Sender<StateManagerCommand>, }
-#[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize, Clone, Debug)]
-pub enum ToolArgumentValues { RunCommand { command: String }, InsertModel { query: String }, ExecuteLuauByName { tool_name: String, arguments_luau: String, } }
-fn format_tool_argument_values_to_luau_string(args: &ToolArgumentValues) -> String {
-    match args {
-        ToolArgumentValues::ExecuteLuauByName { tool_name, arguments_luau } => { format!("ExecuteLuauByName = {{ tool_name = \"{}\", arguments_luau = [[{}]] }}", tool_name, arguments_luau) }
-        ToolArgumentValues::RunCommand { command } => format!("RunCommand = {{ command = [[{}]] }}", command),
-        ToolArgumentValues::InsertModel { query } => format!("InsertModel = {{ query = [[{}]] }}", query),
-    }
+pub struct AxumSharedState {
+    pub sm_command_tx: mpsc::Sender<StateManagerCommand>,
+    /// Retry unparseable result bodies after `json_repair::repair_json`.
+    pub lenient_json: bool,
+    pub discovered_luau_tools: ToolRegistry,
+    /// When set, plugin requests must carry `Authorization: Bearer <token>`.
+    pub auth_token: Option<Arc<str>>,
 }
 #[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize, Clone, Debug)]
-pub struct ToolArguments { args: ToolArgumentValues, id: Option<Uuid>, }
-impl ToolArguments {
-    pub fn to_luau_string(&self) -> String {
-        let args_str = format_tool_argument_values_to_luau_string(&self.args);
-        let id_str = self.id.map_or_else(|| "nil".to_string(), |uuid| format!("\"{}\"", uuid.to_string()));
-        format!("return {{ id = {}, args = {{ {} }} }}", id_str, args_str)
+pub enum ToolArgumentValues {
+    RunCommand {
+        command: String,
+        context: Option<String>,
+    },
+    InsertModel {
+        query: String,
+        parent_path: Option<String>,
+        position: Option<[f64; 3]>,
+    },
+    /// `source` is the tool's file as read by the server (`--ship-tool-source`); the plugin runs
+    /// it instead of its bundled copy. Only the server attaches it, so it is never read from JSON.
+    ExecuteLuauByName {
+        tool_name: String,
+        arguments_luau: String,
+        #[serde(default, skip_deserializing)]
+        source: Option<String>,
+    },
+    CreateEvent {
+        class_name: String,
+        name: String,
+        parent_path: String,
+    },
+    CloneInstance {
+        instance_path: String,
+        new_parent_path: Option<String>,
+        new_name: Option<String>,
+    },
+    FindInstances {
+        root_path: String,
+        class_name: Option<String>,
+        name_pattern: Option<String>,
+        limit: u32,
+    },
+    GetAttributes {
+        instance_path: String,
+    },
+    /// `attributes` holds pre-encoded Luau expressions; `clear` lists attributes to remove
+    /// (a `nil` value would simply vanish from a Luau table constructor).
+    SetAttributes {
+        instance_path: String,
+        attributes: Vec<(String, String)>,
+        clear: Vec<String>,
+    },
+    GetPlaceInfo {},
+    /// `properties` holds pre-encoded Luau expressions, as for `SetAttributes`.
+    CreateInstance {
+        class_name: String,
+        parent_path: Option<String>,
+        properties: Vec<(String, String)>,
+        tag: Option<String>,
+    },
+    WaitFor {
+        condition_luau: String,
+        timeout_ms: u32,
+    },
+    GroupInstances {
+        instance_paths: Vec<String>,
+        container_class: String,
+        container_name: String,
+    },
+    SetServiceEnabled {
+        service: String,
+        property: String,
+        enabled: bool,
+    },
+    GetPlaceStats {
+        max_instances: u32,
+    },
+    WithSelection {
+        instance_paths: Vec<String>,
+        command: String,
+    },
+    ExportTree {
+        root_path: String,
+        max_depth: u32,
+        max_nodes: u32,
+    },
+    CheckLuau {
+        code: String,
+    },
+    GetPlayers {},
+    /// `value` is a pre-encoded Luau expression applied to every path.
+    SetPropertyBulk {
+        instance_paths: Vec<String>,
+        property: String,
+        value: String,
+    },
+    GetRunLogs {
+        since_ms: Option<u64>,
+        max_lines: u32,
+    },
+    CreateScript {
+        script_class: String,
+        parent_path: String,
+        name: String,
+        source: String,
+        disabled: bool,
+    },
+    ResolvePath {
+        path: String,
+        limit: u32,
+    },
+    /// `(path, property names)` per entry.
+    GetPropertiesBulk {
+        requests: Vec<(String, Vec<String>)>,
+    },
+    SearchModels {
+        query: String,
+        limit: u32,
+    },
+    SetAnchored {
+        instance_paths: Vec<String>,
+        anchored: bool,
+    },
+    WeldInstances {
+        instance_paths: Vec<String>,
+    },
+    SetCollisionGroup {
+        instance_paths: Vec<String>,
+        group: String,
+        create_if_missing: bool,
+    },
+    /// `properties` is a pre-encoded `PhysicalProperties.new(..)` call, or `nil` to restore the material default.
+    SetPhysicalProperties {
+        instance_paths: Vec<String>,
+        properties: String,
+    },
+    DeleteTagged {
+        tag: String,
+    },
+    GetWorkspaceProperty {
+        property_name: String,
+    },
+    /// `value` is a pre-encoded Luau expression.
+    SetWorkspaceProperty {
+        property_name: String,
+        value: String,
+    },
+    SnapshotView {},
+    RestoreView {
+        selection: Vec<String>,
+        camera: Option<CameraState>,
+    },
+    GetInstanceProperties {
+        instance_path: String,
+        property_names: Vec<String>,
+    },
+    /// Runs `steps` in order within one task. With `stop_on_error`, the steps after the first
+    /// failing one are skipped.
+    Batch {
+        steps: Vec<ToolArgumentValues>,
+        stop_on_error: bool,
+    },
+    /// `properties` is the JSON object from the caller, encoded per value by `luau::property_value`
+    /// when the task is formatted; the tool validates every value first.
+    SetInstanceProperties {
+        instance_path: String,
+        properties: serde_json::Value,
+    },
+    /// History operations. The plugin runs these outside the undo recording it wraps every other
+    /// task in, so they can't sit inside a `Batch`.
+    Undo {
+        steps: u32,
+    },
+    Redo {
+        steps: u32,
+    },
+    SetWaypoint {
+        name: String,
+    },
+    /// Without `recursive` the plugin refuses an instance that has children.
+    DeleteInstance {
+        instance_path: String,
+        recursive: bool,
+    },
+}
+impl ToolArgumentValues {
+    /// Name of the variant, used when reporting on a task outside of the MCP call that created it.
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            ToolArgumentValues::RunCommand { .. } => "RunCommand",
+            ToolArgumentValues::InsertModel { .. } => "InsertModel",
+            ToolArgumentValues::ExecuteLuauByName { .. } => "ExecuteLuauByName",
+            ToolArgumentValues::CreateEvent { .. } => "CreateEvent",
+            ToolArgumentValues::CloneInstance { .. } => "CloneInstance",
+            ToolArgumentValues::FindInstances { .. } => "FindInstances",
+            ToolArgumentValues::GetAttributes { .. } => "GetAttributes",
+            ToolArgumentValues::SetAttributes { .. } => "SetAttributes",
+            ToolArgumentValues::GetPlaceInfo {} => "GetPlaceInfo",
+            ToolArgumentValues::CreateInstance { .. } => "CreateInstance",
+            ToolArgumentValues::WaitFor { .. } => "WaitFor",
+            ToolArgumentValues::GroupInstances { .. } => "GroupInstances",
+            ToolArgumentValues::SetServiceEnabled { .. } => "SetServiceEnabled",
+            ToolArgumentValues::GetPlaceStats { .. } => "GetPlaceStats",
+            ToolArgumentValues::WithSelection { .. } => "WithSelection",
+            ToolArgumentValues::ExportTree { .. } => "ExportTree",
+            ToolArgumentValues::CheckLuau { .. } => "CheckLuau",
+            ToolArgumentValues::GetPlayers {} => "GetPlayers",
+            ToolArgumentValues::SetPropertyBulk { .. } => "SetPropertyBulk",
+            ToolArgumentValues::GetRunLogs { .. } => "GetRunLogs",
+            ToolArgumentValues::CreateScript { .. } => "CreateScript",
+            ToolArgumentValues::ResolvePath { .. } => "ResolvePath",
+            ToolArgumentValues::GetPropertiesBulk { .. } => "GetPropertiesBulk",
+            ToolArgumentValues::SearchModels { .. } => "SearchModels",
+            ToolArgumentValues::SetAnchored { .. } => "SetAnchored",
+            ToolArgumentValues::WeldInstances { .. } => "WeldInstances",
+            ToolArgumentValues::SetCollisionGroup { .. } => "SetCollisionGroup",
+            ToolArgumentValues::SetPhysicalProperties { .. } => "SetPhysicalProperties",
+            ToolArgumentValues::DeleteTagged { .. } => "DeleteTagged",
+            ToolArgumentValues::GetWorkspaceProperty { .. } => "GetWorkspaceProperty",
+            ToolArgumentValues::SetWorkspaceProperty { .. } => "SetWorkspaceProperty",
+            ToolArgumentValues::SnapshotView {} => "SnapshotView",
+            ToolArgumentValues::RestoreView { .. } => "RestoreView",
+            ToolArgumentValues::GetInstanceProperties { .. } => "GetInstanceProperties",
+            ToolArgumentValues::Batch { .. } => "Batch",
+            ToolArgumentValues::SetInstanceProperties { .. } => "SetInstanceProperties",
+            ToolArgumentValues::SetWaypoint { .. } => "SetWaypoint",
+            ToolArgumentValues::Undo { .. } => "Undo",
+            ToolArgumentValues::Redo { .. } => "Redo",
+            ToolArgumentValues::DeleteInstance { .. } => "DeleteInstance",
+        }
+    }
+    /// Whether the task only reads the place. Anything that can run arbitrary Luau
+    /// counts as mutating, since the server can't see what the code does.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            ToolArgumentValues::Batch { steps, .. } => steps.iter().all(ToolArgumentValues::is_read_only),
+            ToolArgumentValues::FindInstances { .. }
+            | ToolArgumentValues::GetAttributes { .. }
+            | ToolArgumentValues::GetPlaceInfo {}
+            | ToolArgumentValues::GetPlaceStats { .. }
+            | ToolArgumentValues::ExportTree { .. }
+            | ToolArgumentValues::CheckLuau { .. }
+            | ToolArgumentValues::GetPlayers {}
+            | ToolArgumentValues::GetRunLogs { .. }
+            | ToolArgumentValues::ResolvePath { .. }
+            | ToolArgumentValues::GetPropertiesBulk { .. }
+            | ToolArgumentValues::SearchModels { .. }
+            | ToolArgumentValues::GetWorkspaceProperty { .. }
+            | ToolArgumentValues::SnapshotView {}
+            | ToolArgumentValues::GetInstanceProperties { .. }
+            // Only ends the current undo step; the place itself is unchanged.
+            | ToolArgumentValues::SetWaypoint { .. } => true,
+            ToolArgumentValues::RunCommand { .. }
+            | ToolArgumentValues::InsertModel { .. }
+            | ToolArgumentValues::ExecuteLuauByName { .. }
+            | ToolArgumentValues::CreateEvent { .. }
+            | ToolArgumentValues::CloneInstance { .. }
+            | ToolArgumentValues::SetAttributes { .. }
+            | ToolArgumentValues::CreateInstance { .. }
+            | ToolArgumentValues::WaitFor { .. }
+            | ToolArgumentValues::GroupInstances { .. }
+            | ToolArgumentValues::SetServiceEnabled { .. }
+            | ToolArgumentValues::WithSelection { .. }
+            | ToolArgumentValues::SetPropertyBulk { .. }
+            | ToolArgumentValues::CreateScript { .. }
+            | ToolArgumentValues::SetAnchored { .. }
+            | ToolArgumentValues::WeldInstances { .. }
+            | ToolArgumentValues::SetCollisionGroup { .. }
+            | ToolArgumentValues::SetPhysicalProperties { .. }
+            | ToolArgumentValues::DeleteTagged { .. }
+            | ToolArgumentValues::SetWorkspaceProperty { .. }
+            | ToolArgumentValues::RestoreView { .. }
+            | ToolArgumentValues::SetInstanceProperties { .. }
+            | ToolArgumentValues::Undo { .. }
+            | ToolArgumentValues::Redo { .. }
+            | ToolArgumentValues::DeleteInstance { .. } => false,
+        }
     }
-     fn new_with_id(args_values: ToolArgumentValues) -> (Self, Uuid) {
-         let id = Uuid::new_v4();
-         (Self { args: args_values, id: Some(id) }, id)
-     }
 }
+// Appended to a wrapped `run_command` chunk: turns the packed return values into a JSON array.
+// Instances become their full path, non-finite numbers and other datatypes their `tostring`,
+// and a table seen again further down (a cycle) the string "<cycle>".
+const RUN_COMMAND_RETURN_ENCODER: &str = r#"local __mcp_http = game:GetService("HttpService")
+local function __mcp_encode(value, seen)
+	local kind = typeof(value)
+	if kind == "nil" or kind == "string" or kind == "boolean" then
+		return value
+	elseif kind == "number" then
+		return if value == value and math.abs(value) ~= math.huge then value else tostring(value)
+	elseif kind == "Instance" then
+		return value:GetFullName()
+	elseif kind == "table" then
+		if seen[value] then
+			return "<cycle>"
+		end
+		seen[value] = true
+		local isArray = next(value) == nil or (#value > 0 and next(value, #value) == nil)
+		local encoded = {}
+		for key, item in pairs(value) do
+			encoded[if isArray then key else tostring(key)] = __mcp_encode(item, seen)
+		end
+		seen[value] = nil
+		return encoded
+	end
+	return tostring(value)
+end
+local __mcp_parts = {}
+for index = 1, __mcp_results.n do
+	local value = __mcp_encode(__mcp_results[index], {})
+	__mcp_parts[index] = if value == nil then "null" else __mcp_http:JSONEncode(value)
+end
+return "[" .. table.concat(__mcp_parts, ",") .. "]""#;
 
-// --- RBXStudioServer struct and impls (UNCHANGED) ---
-#[derive(Clone)]
-pub struct RBXStudioServer { sm_command_tx: mpsc::Sender<StateManagerCommand>, discovered_luau_tools: Arc<HashMap<String, DiscoveredTool>>, }
-impl RBXStudioServer {
-    pub fn new(sm_command_tx: mpsc::Sender<StateManagerCommand>, discovered_luau_tools: Arc<HashMap<String, DiscoveredTool>>) -> Self { Self { sm_command_tx, discovered_luau_tools } }
-    async fn generic_tool_run(&self, args_values: ToolArgumentValues) -> Result<CallToolResult, McpError> {
-        let (tool_arguments_with_id, request_id) = ToolArguments::new_with_id(args_values.clone());
-        let (response_tx, response_rx) = oneshot::channel();
-        let command = StateManagerCommand::DispatchTask { args: tool_arguments_with_id, response_tx, };
-        if self.sm_command_tx.send(command).await.is_err() { return Err(McpError::internal_error("StateManager unavailable.", None)); }
-        match tokio::time::timeout(TOOL_EXECUTION_TIMEOUT, response_rx).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(_)) => Err(McpError::internal_error("Oneshot channel dropped.", None)),
-            Err(_) => {
-                warn!(target: "mcp_server", request_id = %request_id, "Tool execution timed out.");
-                Err(McpError::new(rmcp::model::ErrorCode::INTERNAL_ERROR, format!("Tool execution timed out after {}s.", TOOL_EXECUTION_TIMEOUT.as_secs()), None))
+// Runs `command` as the body of a vararg function so every value it returns is captured, and
+// makes the chunk return them as a JSON array string; the plugin's RunCode puts that in
+// `return_values` when the task has `returns_json`. The command starts on the first line so
+// error line numbers still match what the caller sent.
+fn wrap_run_command(command: &str) -> String {
+    format!(
+        "local __mcp_results = table.pack((function(...) {}\nend)(...))\n{}",
+        command, RUN_COMMAND_RETURN_ENCODER
+    )
+}
+fn format_tool_argument_values_to_luau_string(args: &ToolArgumentValues) -> String {
+    match args {
+        ToolArgumentValues::ExecuteLuauByName {
+            tool_name,
+            arguments_luau,
+            source,
+        } => format!(
+            "ExecuteLuauByName = {}",
+            luau::table(&[
+                ("tool_name", luau::string(tool_name)),
+                ("arguments_luau", luau::long_string(arguments_luau)),
+                (
+                    "source",
+                    source
+                        .as_deref()
+                        .map_or_else(|| "nil".to_string(), luau::long_string)
+                ),
+            ])
+        ),
+        ToolArgumentValues::RunCommand { command, context } => format!(
+            "RunCommand = {}",
+            luau::table(&[
+                ("command", luau::long_string(&wrap_run_command(command))),
+                ("context", luau::optional_string(context.as_deref())),
+                ("returns_json", "true".to_string()),
+            ])
+        ),
+        ToolArgumentValues::InsertModel {
+            query,
+            parent_path,
+            position,
+        } => format!(
+            "InsertModel = {}",
+            luau::table(&[
+                ("query", luau::string(query)),
+                ("parent_path", luau::optional_string(parent_path.as_deref())),
+                (
+                    "position",
+                    position.map_or_else(
+                        || "nil".to_string(),
+                        |[x, y, z]| format!("Vector3.new({}, {}, {})", x, y, z)
+                    )
+                ),
+            ])
+        ),
+        // Typed variants are keyed by the plugin tool module that implements them.
+        ToolArgumentValues::CreateEvent {
+            class_name,
+            name,
+            parent_path,
+        } => format!(
+            "CreateEvent = {}",
+            luau::table(&[
+                ("class_name", luau::string(class_name)),
+                ("name", luau::string(name)),
+                ("parent_path", luau::string(parent_path)),
+            ])
+        ),
+        ToolArgumentValues::CloneInstance {
+            instance_path,
+            new_parent_path,
+            new_name,
+        } => format!(
+            "CloneInstance = {}",
+            luau::table(&[
+                ("instance_path", luau::string(instance_path)),
+                (
+                    "new_parent_path",
+                    luau::optional_string(new_parent_path.as_deref())
+                ),
+                ("new_name", luau::optional_string(new_name.as_deref())),
+            ])
+        ),
+        ToolArgumentValues::FindInstances {
+            root_path,
+            class_name,
+            name_pattern,
+            limit,
+        } => format!(
+            "FindInstances = {}",
+            luau::table(&[
+                (
+                    "root_path",
+                    luau::string(&normalize_instance_path(root_path))
+                ),
+                ("class_name", luau::optional_string(class_name.as_deref())),
+                (
+                    "name_pattern",
+                    luau::optional_string(name_pattern.as_deref())
+                ),
+                ("limit", limit.to_string()),
+            ])
+        ),
+        ToolArgumentValues::GetAttributes { instance_path } => format!(
+            "GetAttributes = {}",
+            luau::table(&[("instance_path", luau::string(instance_path))])
+        ),
+        ToolArgumentValues::SetAttributes {
+            instance_path,
+            attributes,
+            clear,
+        } => format!(
+            "SetAttributes = {}",
+            luau::table(&[
+                ("instance_path", luau::string(instance_path)),
+                ("attributes", luau::string_keyed_table(attributes)),
+                ("clear", luau::string_list(clear)),
+            ])
+        ),
+        ToolArgumentValues::CreateInstance {
+            class_name,
+            parent_path,
+            properties,
+            tag,
+        } => format!(
+            "CreateInstance = {}",
+            luau::table(&[
+                ("class_name", luau::string(class_name)),
+                ("parent_path", luau::optional_string(parent_path.as_deref())),
+                ("properties", luau::string_keyed_table(properties)),
+                ("tag_attribute", luau::string(CREATED_TAG_ATTRIBUTE)),
+                ("tag", luau::optional_string(tag.as_deref())),
+            ])
+        ),
+        ToolArgumentValues::WaitFor {
+            condition_luau,
+            timeout_ms,
+        } => format!(
+            "WaitFor = {}",
+            luau::table(&[
+                ("condition_luau", luau::string(condition_luau)),
+                ("timeout_ms", timeout_ms.to_string()),
+            ])
+        ),
+        ToolArgumentValues::GroupInstances {
+            instance_paths,
+            container_class,
+            container_name,
+        } => format!(
+            "GroupInstances = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("container_class", luau::string(container_class)),
+                ("container_name", luau::string(container_name)),
+            ])
+        ),
+        ToolArgumentValues::SetServiceEnabled {
+            service,
+            property,
+            enabled,
+        } => format!(
+            "SetServiceEnabled = {}",
+            luau::table(&[
+                ("service", luau::string(service)),
+                ("property", luau::string(property)),
+                ("enabled", enabled.to_string()),
+            ])
+        ),
+        ToolArgumentValues::GetPlaceStats { max_instances } => format!(
+            "GetPlaceStats = {}",
+            luau::table(&[("max_instances", max_instances.to_string())])
+        ),
+        ToolArgumentValues::WithSelection {
+            instance_paths,
+            command,
+        } => format!(
+            "WithSelection = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("command", luau::string(command)),
+            ])
+        ),
+        ToolArgumentValues::ExportTree {
+            root_path,
+            max_depth,
+            max_nodes,
+        } => format!(
+            "ExportTree = {}",
+            luau::table(&[
+                ("root_path", luau::string(root_path)),
+                ("max_depth", max_depth.to_string()),
+                ("max_nodes", max_nodes.to_string()),
+            ])
+        ),
+        ToolArgumentValues::CheckLuau { code } => format!(
+            "CheckLuau = {}",
+            luau::table(&[("code", luau::string(code))])
+        ),
+        ToolArgumentValues::GetPlayers {} => format!("GetPlayers = {}", luau::table(&[])),
+        ToolArgumentValues::SetPropertyBulk {
+            instance_paths,
+            property,
+            value,
+        } => format!(
+            "SetPropertyBulk = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("property", luau::string(property)),
+                ("value", value.clone()),
+            ])
+        ),
+        ToolArgumentValues::GetRunLogs {
+            since_ms,
+            max_lines,
+        } => format!(
+            "GetRunLogs = {}",
+            luau::table(&[
+                (
+                    "since_ms",
+                    since_ms.map_or_else(|| "nil".to_string(), |ms| ms.to_string())
+                ),
+                ("max_lines", max_lines.to_string()),
+            ])
+        ),
+        ToolArgumentValues::CreateScript {
+            script_class,
+            parent_path,
+            name,
+            source,
+            disabled,
+        } => format!(
+            "CreateScript = {}",
+            luau::table(&[
+                ("script_class", luau::string(script_class)),
+                ("parent_path", luau::string(parent_path)),
+                ("name", luau::string(name)),
+                ("source", luau::string(source)),
+                ("disabled", disabled.to_string()),
+            ])
+        ),
+        ToolArgumentValues::ResolvePath { path, limit } => format!(
+            "ResolvePath = {}",
+            luau::table(&[("path", luau::string(path)), ("limit", limit.to_string())])
+        ),
+        ToolArgumentValues::GetPropertiesBulk { requests } => {
+            let requests = requests
+                .iter()
+                .map(|(path, properties)| {
+                    luau::table(&[
+                        ("path", luau::string(path)),
+                        ("properties", luau::string_list(properties)),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "GetPropertiesBulk = {}",
+                luau::table(&[("requests", luau::list(&requests))])
+            )
+        }
+        ToolArgumentValues::SearchModels { query, limit } => format!(
+            "SearchModels = {}",
+            luau::table(&[("query", luau::string(query)), ("limit", limit.to_string())])
+        ),
+        ToolArgumentValues::SetAnchored {
+            instance_paths,
+            anchored,
+        } => format!(
+            "SetAnchored = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("anchored", anchored.to_string()),
+            ])
+        ),
+        ToolArgumentValues::WeldInstances { instance_paths } => format!(
+            "WeldInstances = {}",
+            luau::table(&[("instance_paths", luau::string_list(instance_paths))])
+        ),
+        ToolArgumentValues::SetCollisionGroup {
+            instance_paths,
+            group,
+            create_if_missing,
+        } => format!(
+            "SetCollisionGroup = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("group", luau::string(group)),
+                ("create_if_missing", create_if_missing.to_string()),
+            ])
+        ),
+        ToolArgumentValues::SetPhysicalProperties {
+            instance_paths,
+            properties,
+        } => format!(
+            "SetPhysicalProperties = {}",
+            luau::table(&[
+                ("instance_paths", luau::string_list(instance_paths)),
+                ("properties", properties.clone()),
+            ])
+        ),
+        ToolArgumentValues::DeleteTagged { tag } => format!(
+            "DeleteTagged = {}",
+            luau::table(&[
+                ("attribute", luau::string(CREATED_TAG_ATTRIBUTE)),
+                ("tag", luau::string(tag))
+            ])
+        ),
+        ToolArgumentValues::GetWorkspaceProperty { property_name } => format!(
+            "GetWorkspaceProperty = {}",
+            luau::table(&[("property_name", luau::string(property_name))])
+        ),
+        ToolArgumentValues::SetWorkspaceProperty {
+            property_name,
+            value,
+        } => format!(
+            "SetWorkspaceProperty = {}",
+            luau::table(&[
+                ("property_name", luau::string(property_name)),
+                ("value", value.clone())
+            ])
+        ),
+        ToolArgumentValues::SnapshotView {} => format!("SnapshotView = {}", luau::table(&[])),
+        ToolArgumentValues::RestoreView { selection, camera } => {
+            let camera = camera.as_ref().map_or_else(
+                || "nil".to_string(),
+                |camera| {
+                    luau::table(&[
+                        ("cframe", luau_cframe(&camera.cframe)),
+                        ("focus", luau_cframe(&camera.focus)),
+                        ("field_of_view", camera.field_of_view.to_string()),
+                    ])
+                },
+            );
+            format!(
+                "RestoreView = {}",
+                luau::table(&[
+                    ("selection", luau::string_list(selection)),
+                    ("camera", camera)
+                ])
+            )
+        }
+        ToolArgumentValues::GetInstanceProperties {
+            instance_path,
+            property_names,
+        } => format!(
+            "GetInstanceProperties = {}",
+            luau::table(&[
+                ("instance_path", luau::string(instance_path)),
+                ("property_names", luau::string_list(property_names))
+            ])
+        ),
+        ToolArgumentValues::Batch {
+            steps,
+            stop_on_error,
+        } => {
+            let steps: Vec<String> = steps
+                .iter()
+                .map(|step| format!("{{ {} }}", format_tool_argument_values_to_luau_string(step)))
+                .collect();
+            format!(
+                "Batch = {}",
+                luau::table(&[
+                    ("steps", luau::list(&steps)),
+                    ("stop_on_error", stop_on_error.to_string())
+                ])
+            )
+        }
+        ToolArgumentValues::SetInstanceProperties {
+            instance_path,
+            properties,
+        } => {
+            // A value that can't be encoded is reported back as that property's failure rather
+            // than being sent as nil, which would clear the property instead.
+            let empty = serde_json::Map::new();
+            let mut encoded = Vec::new();
+            let mut rejected = Vec::new();
+            for (name, value) in properties.as_object().unwrap_or(&empty) {
+                match luau::property_value(value) {
+                    Ok(value) => encoded.push((name.clone(), value)),
+                    Err(message) => rejected.push((name.clone(), luau::string(&message))),
+                }
+            }
+            let mut fields = vec![
+                ("instance_path", luau::string(instance_path)),
+                ("properties", luau::string_keyed_table(&encoded)),
+            ];
+            if !rejected.is_empty() {
+                fields.push(("rejected_properties", luau::string_keyed_table(&rejected)));
             }
+            format!("SetInstanceProperties = {}", luau::table(&fields))
         }
+        ToolArgumentValues::Undo { steps } => {
+            format!("Undo = {}", luau::table(&[("steps", steps.to_string())]))
+        }
+        ToolArgumentValues::Redo { steps } => {
+            format!("Redo = {}", luau::table(&[("steps", steps.to_string())]))
+        }
+        ToolArgumentValues::SetWaypoint { name } => format!(
+            "SetWaypoint = {}",
+            luau::table(&[("name", luau::string(name))])
+        ),
+        ToolArgumentValues::DeleteInstance {
+            instance_path,
+            recursive,
+        } => format!(
+            "DeleteInstance = {}",
+            luau::table(&[
+                ("instance_path", luau::string(instance_path)),
+                ("recursive", recursive.to_string())
+            ])
+        ),
+        ToolArgumentValues::GetPlaceInfo {} => format!("GetPlaceInfo = {}", luau::table(&[])),
     }
 }
-#[tool(tool_box)]
-impl ServerHandler for RBXStudioServer {
-    fn get_info(&self) -> ServerInfo {
-        // This function is correct. For brevity, I'm omitting the large block of schema definition.
-        ServerInfo { protocol_version: ProtocolVersion::V_2025_03_26, server_info: Implementation::from_build_env(), instructions: Some("...".into()), capabilities: ServerCapabilities::default(), }
+
+// Environments `run_command` can execute in; the plugin's RunCode tool interprets them.
+const RUN_CONTEXTS: &[&str] = &["plugin", "server"];
+
+const SCRIPT_CLASSES: &[&str] = &["Script", "LocalScript", "ModuleScript"];
+
+// Service switches `set_service_enabled` may flip. Kept to boolean properties that Studio
+// lets a plugin write and that are routinely needed while setting a place up.
+const TOGGLABLE_SERVICE_PROPERTIES: &[(&str, &str)] = &[
+    ("HttpService", "HttpEnabled"),
+    ("Players", "CharacterAutoLoads"),
+    ("Workspace", "StreamingEnabled"),
+    ("StarterGui", "ShowDevelopmentGui"),
+    ("Lighting", "GlobalShadows"),
+    ("SoundService", "RespectFilteringEnabled"),
+];
+
+// `wait_for` must give up in the plugin at least this long before the server stops waiting for the result.
+const WAIT_FOR_TIMEOUT_MARGIN: Duration = Duration::from_secs(5);
+
+// Steps one `batch` call may run.
+const MAX_BATCH_STEPS: usize = 50;
+
+// Undo history steps one `undo` or `redo` call may walk.
+const MAX_HISTORY_STEPS: u32 = 50;
+
+// Instances `get_place_stats` visits before it stops and reports approximate counts.
+const MAX_STATS_TRAVERSAL: u32 = 200_000;
+
+// Bounds on `export_tree` so one call can't serialize an entire place.
+const DEFAULT_EXPORT_DEPTH: u32 = 5;
+const MAX_EXPORT_DEPTH: u32 = 20;
+const MAX_EXPORT_NODES: u32 = 2_000;
+
+// Upper bound on paths returned by a single `find_instances` call.
+const MAX_FIND_RESULTS: u32 = 200;
+
+// Upper bound on candidates returned by a single `search_models` call.
+const MAX_SEARCH_RESULTS: u32 = 50;
+
+// Upper bound on entries reported by a single `diff_trees` call.
+const MAX_DIFF_CHANGES: usize = 500;
+
+// Upper bound on candidates returned by a single `resolve_path` call.
+const MAX_RESOLVE_MATCHES: u32 = 100;
+
+// Upper bound on Output lines returned by a single `get_run_logs` call.
+const MAX_RUN_LOG_LINES: u32 = 500;
+
+// Upper bound on instances touched by a single bulk edit.
+const MAX_BULK_PATHS: usize = 500;
+
+// Checks a bulk tool's path list: at least `min` entries, at most `MAX_BULK_PATHS`, each well formed.
+fn validate_bulk_paths(paths: &[String], min: usize) -> Result<(), String> {
+    if paths.len() < min {
+        return Err(format!(
+            "'instance_paths' must list at least {} instance(s).",
+            min
+        ));
     }
+    if paths.len() > MAX_BULK_PATHS {
+        return Err(format!(
+            "'instance_paths' may list at most {} instances.",
+            MAX_BULK_PATHS
+        ));
+    }
+    paths
+        .iter()
+        .try_for_each(|path| validate_instance_path(path))
 }
-#[tool(tool_box)]
-impl RBXStudioServer {
-    // These tool impls are correct and just call generic_tool_run
-    #[tool(description = "Runs a raw Luau command string...")] async fn run_command(&self, #[tool(param)] command: String,) -> Result<CallToolResult, McpError> { self.generic_tool_run(ToolArgumentValues::RunCommand { command }).await }
-    #[tool(description = "Inserts a model...")] async fn insert_model(&self, #[tool(param)] query: String,) -> Result<CallToolResult, McpError> { self.generic_tool_run(ToolArgumentValues::InsertModel { query }).await }
-    #[tool(description = "Executes a specific Luau tool...")] async fn execute_discovered_luau_tool(&self, #[tool(param)] tool_name: String, #[tool(param)] tool_arguments_luau: String,) -> Result<CallToolResult, McpError> {
-        if !self.discovered_luau_tools.contains_key(&tool_name) { return Ok(CallToolResult::error(vec![Content::text(format!("Luau tool '{}' not found.", tool_name))])); }
-        self.generic_tool_run(ToolArgumentValues::ExecuteLuauByName { tool_name, arguments_luau: tool_arguments_luau }).await
+
+// `PhysicalProperties.new` arguments in order: JSON field, allowed range, default when omitted
+// (`None` means required).
+const PHYSICAL_PROPERTY_FIELDS: &[(&str, f64, f64, Option<f64>)] = &[
+    ("density", 0.0001, 100.0, None),
+    ("friction", 0.0, 2.0, None),
+    ("elasticity", 0.0, 1.0, None),
+    ("friction_weight", 0.0, 100.0, Some(1.0)),
+    ("elasticity_weight", 0.0, 100.0, Some(1.0)),
+];
+
+// Encodes `set_physical_properties`' `props` as a `PhysicalProperties.new(..)` call; null becomes `nil`.
+fn encode_physical_properties(props: &serde_json::Value) -> Result<String, String> {
+    if props.is_null() {
+        return Ok("nil".to_string());
+    }
+    let object = props
+        .as_object()
+        .ok_or("'props' must be an object or null.")?;
+    if let Some(unknown) = object.keys().find(|key| {
+        !PHYSICAL_PROPERTY_FIELDS
+            .iter()
+            .any(|(name, ..)| *name == key.as_str())
+    }) {
+        return Err(format!("Unknown field '{}' in 'props'.", unknown));
+    }
+    let mut arguments = Vec::new();
+    for (name, min, max, default) in PHYSICAL_PROPERTY_FIELDS {
+        let value = match object.get(*name) {
+            Some(value) => value
+                .as_f64()
+                .ok_or_else(|| format!("'{}' must be a number.", name))?,
+            None => default.ok_or_else(|| format!("'props' is missing '{}'.", name))?,
+        };
+        if !(*min..=*max).contains(&value) {
+            return Err(format!(
+                "'{}' must be between {} and {}, got {}.",
+                name, min, max, value
+            ));
+        }
+        arguments.push(value.to_string());
     }
+    Ok(format!("PhysicalProperties.new({})", arguments.join(", ")))
 }
 
+// Attribute `create_instance`'s `tag` is written to and `delete_tagged` matches on.
+const CREATED_TAG_ATTRIBUTE: &str = "MCPTag";
 
-// --- UNIFIED HANDLER WITH THE FINAL FIX ---
-pub async fn unified_handler(
-    State(axum_state): State<AxumSharedState>,
-    headers: HeaderMap,
-    body: String,
-) -> impl IntoResponse {
-    if let Some(task_id_header) = headers.get("X-MCP-Task-ID") {
-        let task_id_str = task_id_header.to_str().unwrap_or_default();
-        if let Ok(task_id) = Uuid::parse_str(task_id_str) {
-            match rmcp::serde_json::from_str::<CallToolResult>(&body) {
-                Ok(result) => {
-                    let cmd = StateManagerCommand::SubmitTaskResult { task_id, result };
-                    if axum_state.sm_command_tx.send(cmd).await.is_err() {
-                        return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
-                    }
-                    // ===================================================================
-                    // THE FIX IS HERE: Respond with NO CONTENT instead of a string.
-                    // This prevents the "Invalid Luau" error on the client.
-                    // ===================================================================
-                    return (StatusCode::NO_CONTENT, "").into_response();
+fn validate_created_tag(tag: &str) -> Result<(), String> {
+    if tag.trim().is_empty() {
+        return Err("Tag must not be empty.".to_string());
+    }
+    if tag.chars().any(char::is_control) {
+        return Err("Tag must not contain control characters.".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum WorkspacePropertyKind {
+    Number,
+    Bool,
+    Vector3,
+}
+impl WorkspacePropertyKind {
+    fn check(self, property_name: &str, value: &serde_json::Value) -> Result<(), String> {
+        let ok = match self {
+            WorkspacePropertyKind::Number => value.as_f64().is_some_and(f64::is_finite),
+            WorkspacePropertyKind::Bool => value.is_boolean(),
+            WorkspacePropertyKind::Vector3 => {
+                value.get("$type").and_then(serde_json::Value::as_str) == Some("Vector3")
+            }
+        };
+        if ok {
+            return Ok(());
+        }
+        let expected = match self {
+            WorkspacePropertyKind::Number => "a finite number",
+            WorkspacePropertyKind::Bool => "a boolean",
+            WorkspacePropertyKind::Vector3 => "a tagged Vector3",
+        };
+        Err(format!(
+            "'{}' must be set to {}, got {}.",
+            property_name, expected, value
+        ))
+    }
+}
+
+// Workspace properties `get_workspace_property`/`set_workspace_property` may touch: world
+// physics settings, not the place's contents.
+const WORKSPACE_PROPERTIES: &[(&str, WorkspacePropertyKind)] = &[
+    ("Gravity", WorkspacePropertyKind::Number),
+    ("FallenPartsDestroyHeight", WorkspacePropertyKind::Number),
+    ("AirDensity", WorkspacePropertyKind::Number),
+    ("GlobalWind", WorkspacePropertyKind::Vector3),
+    ("StreamingEnabled", WorkspacePropertyKind::Bool),
+    ("TouchesUseCollisionGroups", WorkspacePropertyKind::Bool),
+];
+
+fn workspace_property_kind(property_name: &str) -> Result<WorkspacePropertyKind, String> {
+    WORKSPACE_PROPERTIES
+        .iter()
+        .find(|(name, _)| *name == property_name)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| {
+            let allowed = WORKSPACE_PROPERTIES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "'{}' is not an allowed Workspace property; use one of {}.",
+                property_name, allowed
+            )
+        })
+}
+
+const MAX_INSTANCE_NAME_LENGTH: usize = 100;
+
+// Names are later addressed through dot-separated paths by `ToolHelpers.FindInstanceByPath`,
+// so a name containing '.' could never be found again even though Roblox accepts it.
+fn validate_instance_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Instance name must not be empty.".to_string());
+    }
+    if name.chars().count() > MAX_INSTANCE_NAME_LENGTH {
+        return Err(format!(
+            "Instance name must be at most {} characters.",
+            MAX_INSTANCE_NAME_LENGTH
+        ));
+    }
+    if name.contains('.') {
+        return Err(format!(
+            "Instance name '{}' must not contain '.', which separates path segments.",
+            name
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Instance name must not contain control characters.".to_string());
+    }
+    Ok(())
+}
+
+// Resolves `path` under `root`, following symlinks and `..`, and refuses anything that ends up
+// outside it, so execute_luau_file can't be used to read arbitrary files.
+fn resolve_luau_file(root: &Path, path: &str) -> Result<PathBuf, String> {
+    if path.trim().is_empty() {
+        return Err("'path' must not be empty.".to_string());
+    }
+    let root = fs::canonicalize(root).map_err(|e| {
+        format!(
+            "The --luau-files-dir folder {} is unavailable: {}",
+            root.display(),
+            e
+        )
+    })?;
+    let outside = || {
+        format!(
+            "'{}' is outside {}; execute_luau_file only reads files in that folder.",
+            path,
+            root.display()
+        )
+    };
+    // Checked before touching the disk so a path that climbs out is refused whether or not its
+    // target exists; symlinks that lead out are caught once the path is canonicalized.
+    let mut depth = 0usize;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return Err(outside()),
+        }
+    }
+    let file = match fs::canonicalize(root.join(path)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("No file '{}' in {}.", path, root.display()))
+        }
+        Err(e) => return Err(format!("Could not resolve '{}': {}", path, e)),
+    };
+    if !file.starts_with(&root) {
+        return Err(outside());
+    }
+    if !file.is_file() {
+        return Err(format!("'{}' is not a file.", path));
+    }
+    Ok(file)
+}
+
+// Longest `idempotency_key` a tool call may pass.
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+fn validate_idempotency_key(key: &str) -> Result<(), String> {
+    if key.trim().is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(format!(
+            "'idempotency_key' must be between 1 and {} characters.",
+            MAX_IDEMPOTENCY_KEY_LEN
+        ));
+    }
+    Ok(())
+}
+
+// In read-only mode a discovered tool's arguments must be data: they arrive as a JSON object and
+// are encoded here, since Luau arguments would run in Studio beside the read-only tool unchecked.
+fn read_only_tool_arguments(arguments: &str) -> Result<String, String> {
+    match serde_json::from_str::<serde_json::Value>(arguments) {
+        Ok(object @ serde_json::Value::Object(_)) => Ok(format!("return {}", luau::from_json(&object)?)),
+        _ => Err("The server is read-only: 'tool_arguments_luau' must be a JSON object of the tool's arguments, not Luau code.".to_string()),
+    }
+}
+
+fn history_steps(steps: Option<u32>) -> Result<u32, String> {
+    match steps.unwrap_or(1) {
+        steps @ 1..=MAX_HISTORY_STEPS => Ok(steps),
+        _ => Err(format!(
+            "'steps' must be between 1 and {}.",
+            MAX_HISTORY_STEPS
+        )),
+    }
+}
+
+// Rewrites the path spellings clients commonly send into the dotted form the plugin resolves
+// from the DataModel: `game.Workspace.Part`, `Workspace/Part` and `Workspace\Part` all become
+// `Workspace.Part`. Anything else is returned trimmed but unchanged.
+fn normalize_instance_path(path: &str) -> String {
+    let path = path.trim().replace(['/', '\\'], ".");
+    match path.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("game.") => path[5..].to_string(),
+        _ => path,
+    }
+}
+
+// Checks the shape of a dot-separated instance path such as `Workspace.Map.Spawn`;
+// whether it resolves is up to the plugin.
+fn validate_instance_path(path: &str) -> Result<(), String> {
+    if path.trim().is_empty() {
+        return Err("Instance path must not be empty.".to_string());
+    }
+    if path.chars().any(char::is_control) {
+        return Err("Instance path must not contain control characters.".to_string());
+    }
+    if path.split('.').any(|segment| segment.trim().is_empty()) {
+        return Err(format!("Instance path '{}' has an empty segment; expected names separated by single dots, e.g. Workspace.Model.Part.", path));
+    }
+    Ok(())
+}
+
+// The paths `delete_instance` can tell are protected without a round trip: the DataModel, a
+// service (a direct child of it) or Terrain. The plugin still checks what a path resolves to.
+fn names_protected_instance(path: &str) -> bool {
+    let path = normalize_instance_path(path);
+    path.eq_ignore_ascii_case("game")
+        || !path.contains('.')
+        || path.eq_ignore_ascii_case("Workspace.Terrain")
+}
+
+const MAX_ATTRIBUTE_NAME_LENGTH: usize = 100;
+
+// Mirrors the rules `Instance:SetAttribute` enforces, so a bad name fails before a round trip.
+fn validate_attribute_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Attribute name must not be empty.".to_string());
+    }
+    if name.len() > MAX_ATTRIBUTE_NAME_LENGTH {
+        return Err(format!(
+            "Attribute name '{}' must be at most {} characters.",
+            name, MAX_ATTRIBUTE_NAME_LENGTH
+        ));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!(
+            "Attribute name '{}' may only contain letters, digits and underscores.",
+            name
+        ));
+    }
+    if name.starts_with("RBX") {
+        return Err(format!(
+            "Attribute name '{}' must not start with the reserved prefix 'RBX'.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// One rule a tool's arguments broke, reported back as `{"validation_errors": [...]}`.
+#[derive(rmcp::serde::Serialize, Debug)]
+pub struct ValidationError {
+    pub field: String,
+    pub message: String,
+}
+impl ValidationError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+fn validation_error_result(errors: &[ValidationError]) -> CallToolResult {
+    let body = serde_json::json!({ "validation_errors": errors });
+    CallToolResult::error(vec![Content::text(body.to_string())])
+}
+
+/// Editor camera state inside a `snapshot_view` token. CFrames are the 12 numbers of
+/// `CFrame:GetComponents()`.
+#[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CameraState {
+    pub cframe: [f64; 12],
+    pub focus: [f64; 12],
+    pub field_of_view: f64,
+}
+
+/// The token `snapshot_view` returns and `restore_view` accepts.
+#[derive(rmcp::serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ViewSnapshot {
+    pub selection: Vec<String>,
+    #[serde(default)]
+    pub camera: Option<CameraState>,
+}
+impl ViewSnapshot {
+    fn validate(&self) -> Result<(), String> {
+        if self.selection.len() > MAX_BULK_PATHS {
+            return Err(format!(
+                "The token selects more than {} instances.",
+                MAX_BULK_PATHS
+            ));
+        }
+        self.selection
+            .iter()
+            .try_for_each(|path| validate_instance_path(path))?;
+        if let Some(camera) = &self.camera {
+            if camera
+                .cframe
+                .iter()
+                .chain(&camera.focus)
+                .any(|c| !c.is_finite())
+            {
+                return Err("Camera CFrame components must be finite numbers.".to_string());
+            }
+            if !(1.0..=120.0).contains(&camera.field_of_view) {
+                return Err(format!(
+                    "Camera field_of_view must be between 1 and 120, got {}.",
+                    camera.field_of_view
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn luau_cframe(components: &[f64; 12]) -> String {
+    let components = components.iter().map(f64::to_string).collect::<Vec<_>>();
+    format!("CFrame.new({})", components.join(", "))
+}
+
+/// One entry of `get_properties_bulk`.
+#[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize, rmcp::schemars::JsonSchema, Debug)]
+#[schemars(crate = "rmcp::schemars")]
+pub struct PropertyRequest {
+    /// Dot-separated instance path, e.g. "Workspace.Baseplate".
+    pub path: String,
+    /// Property names to read, e.g. ["Size", "Anchored"].
+    pub properties: Vec<String>,
+}
+
+/// Arguments of `create_instance`. The derived schema is what MCP clients see;
+/// `validate` covers the rules a JSON schema can't express.
+#[derive(rmcp::serde::Deserialize, rmcp::schemars::JsonSchema, Debug)]
+#[schemars(crate = "rmcp::schemars")]
+pub struct CreateInstanceParams {
+    /// Roblox class to create, e.g. "Part" or "Folder".
+    pub class_name: String,
+    /// Dot-separated path of the parent, e.g. "Workspace.Map". Left unparented when omitted.
+    #[serde(default)]
+    pub parent_path: Option<String>,
+    /// Property name -> value. Values may be strings, numbers, booleans, component tables
+    /// such as {"x": 1, "y": 2, "z": 3}, or tagged datatypes such as {"$type": "Vector3", ...}.
+    #[serde(default)]
+    pub properties: HashMap<String, serde_json::Value>,
+    /// Stored in the instance's MCPTag attribute so `delete_tagged` can remove everything
+    /// created under the same tag.
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+impl CreateInstanceParams {
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        if self.class_name.is_empty() || !self.class_name.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            errors.push(ValidationError::new(
+                "class_name",
+                "must be a non-empty Roblox class name containing only letters and digits.",
+            ));
+        }
+        if let Some(Err(message)) = self.parent_path.as_deref().map(validate_instance_path) {
+            errors.push(ValidationError::new("parent_path", message));
+        }
+        if let Some(Err(message)) = self.tag.as_deref().map(validate_created_tag) {
+            errors.push(ValidationError::new("tag", message));
+        }
+        for (name, value) in &self.properties {
+            let field = format!("properties.{}", name);
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                errors.push(ValidationError::new(
+                    field,
+                    "property names may only contain letters, digits and underscores.",
+                ));
+            } else if name.eq_ignore_ascii_case("Parent") {
+                errors.push(ValidationError::new(
+                    field,
+                    "set the parent with 'parent_path' instead.",
+                ));
+            } else if name == "Name" {
+                if let Some(Err(message)) = value.as_str().map(validate_instance_name) {
+                    errors.push(ValidationError::new(field, message));
                 }
-                Err(e) => {
-                    warn!("Failed to parse result body: {}", e);
-                    return (StatusCode::BAD_REQUEST, "Invalid result JSON").into_response();
+            }
+            if value.is_null() {
+                errors.push(ValidationError::new(
+                    format!("properties.{}", name),
+                    "null is not a valid property value.",
+                ));
+            }
+        }
+        errors
+    }
+}
+
+#[derive(rmcp::serde::Deserialize, rmcp::serde::Serialize, Clone, Debug)]
+pub struct ToolArguments {
+    // Boxed: the largest variants would otherwise make every queued task and command hundreds of bytes.
+    args: Box<ToolArgumentValues>,
+    id: Option<Uuid>,
+    #[serde(default)]
+    priority: TaskPriority,
+    #[serde(default)]
+    idempotency_key: Option<String>,
+}
+/// Queued tasks are handed out highest priority first, and in arrival order within a priority.
+#[derive(
+    rmcp::serde::Deserialize,
+    rmcp::serde::Serialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+impl TaskPriority {
+    /// Parses a tool's optional `priority` argument; absent means normal.
+    fn from_param(priority: Option<&str>) -> std::result::Result<Self, String> {
+        match priority {
+            None | Some("normal") => Ok(TaskPriority::Normal),
+            Some("low") => Ok(TaskPriority::Low),
+            Some("high") => Ok(TaskPriority::High),
+            Some(other) => Err(format!(
+                "'priority' must be 'low', 'normal' or 'high', got '{}'.",
+                other
+            )),
+        }
+    }
+    /// The priority of the running tool call: its `priority` argument when given, else the
+    /// `priority` in the request's `_meta` (over HTTP, from the `X-MCP-Priority` header), so a client
+    /// can set it without a tool parameter.
+    fn for_call(priority: Option<&str>) -> std::result::Result<Self, String> {
+        if priority.is_some() {
+            return Self::from_param(priority);
+        }
+        let meta_priority = CALL_CONTEXT
+            .try_with(|call| call.meta.get("priority").cloned())
+            .ok()
+            .flatten();
+        match meta_priority {
+            None => Ok(TaskPriority::Normal),
+            Some(serde_json::Value::String(priority)) => Self::from_param(Some(&priority)),
+            Some(other) => Err(format!(
+                "'_meta.priority' must be 'low', 'normal' or 'high', got {}.",
+                other
+            )),
+        }
+    }
+}
+// Inserts after every task of the same or higher priority.
+fn enqueue(queue: &mut VecDeque<ToolArguments>, task: ToolArguments) {
+    let index = queue
+        .iter()
+        .position(|queued| queued.priority < task.priority)
+        .unwrap_or(queue.len());
+    queue.insert(index, task);
+}
+impl ToolArguments {
+    pub fn to_luau_string(&self) -> String {
+        let args_str = format_tool_argument_values_to_luau_string(&self.args);
+        let id_str = self
+            .id
+            .map_or_else(|| "nil".to_string(), |uuid| format!("\"{}\"", uuid));
+        format!("return {{ id = {}, args = {{ {} }} }}", id_str, args_str)
+    }
+    pub fn id(&self) -> Option<Uuid> {
+        self.id
+    }
+    pub(crate) fn with_priority(mut self, priority: TaskPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+    pub(crate) fn with_idempotency_key(mut self, key: Option<String>) -> Self {
+        self.idempotency_key = key;
+        self
+    }
+    pub(crate) fn new_with_id(args_values: ToolArgumentValues) -> (Self, Uuid) {
+        let id = Uuid::new_v4();
+        (
+            Self {
+                args: Box::new(args_values),
+                id: Some(id),
+                priority: TaskPriority::Normal,
+                idempotency_key: None,
+            },
+            id,
+        )
+    }
+}
+
+/// Stops the `StateManager` after failing its outstanding tasks, so callers get an error
+/// instead of waiting out their timeouts. Returns how many tasks were failed.
+pub async fn shutdown_state_manager(sm_command_tx: &mpsc::Sender<StateManagerCommand>) -> usize {
+    let (response_tx, response_rx) = oneshot::channel();
+    if sm_command_tx
+        .send(StateManagerCommand::Shutdown { response_tx })
+        .await
+        .is_err()
+    {
+        return 0;
+    }
+    response_rx.await.unwrap_or(0)
+}
+
+/// Pauses or resumes task delivery; shared by the `set_dispatch_enabled` tool and `POST /admin/dispatch`.
+pub async fn set_dispatch_enabled(
+    sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+    enabled: bool,
+) -> Result<DispatchState, McpError> {
+    let (response_tx, response_rx) = oneshot::channel();
+    if sm_command_tx
+        .send(StateManagerCommand::SetDispatchEnabled {
+            enabled,
+            response_tx,
+        })
+        .await
+        .is_err()
+    {
+        return Err(McpError::internal_error("StateManager unavailable.", None));
+    }
+    response_rx
+        .await
+        .map_err(|_| McpError::internal_error("Oneshot channel dropped.", None))
+}
+
+// What a tool call's client sent beside the arguments, available to the tool while it runs.
+struct CallContext {
+    peer: Peer<RoleServer>,
+    meta: JsonObject,
+}
+tokio::task_local! {
+    static CALL_CONTEXT: CallContext;
+    // Set while `batch` builds a step by calling the step's tool, which leaves its task here
+    // instead of dispatching it.
+    static BATCH_STEP: RefCell<Option<ToolArgumentValues>>;
+}
+
+// How often a waiting tool call tells a client that asked for progress that it's still going.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sends MCP progress notifications for one tool call whose client passed a `progressToken`.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+    sent: Arc<AtomicU32>,
+}
+impl std::fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressReporter")
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+impl ProgressReporter {
+    /// `None` outside a tool call, or unless the call's `_meta` carries a `progressToken`.
+    pub fn for_current_call() -> Option<Self> {
+        CALL_CONTEXT
+            .try_with(|call| {
+                let token = match call.meta.get("progressToken")? {
+                    serde_json::Value::String(token) => {
+                        ProgressToken::String(token.as_str().into())
+                    }
+                    serde_json::Value::Number(token) => {
+                        ProgressToken::Number(u32::try_from(token.as_u64()?).ok()?)
+                    }
+                    _ => return None,
+                };
+                Some(Self {
+                    peer: call.peer.clone(),
+                    token,
+                    sent: Arc::new(AtomicU32::new(0)),
+                })
+            })
+            .ok()
+            .flatten()
+    }
+
+    // A task has no known amount of work, so `progress` just counts notifications, which keeps it
+    // increasing as the spec requires. This rmcp's notification has no message field, so the
+    // phase only goes to the log.
+    async fn report(&self, phase: String) {
+        let progress = self.sent.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!(target: "mcp_server", token = %self.token, progress, "{}", phase);
+        let params = ProgressNotificationParam {
+            progress_token: self.token.clone(),
+            progress,
+            total: None,
+        };
+        if let Err(e) = self.peer.notify_progress(params).await {
+            warn!(target: "mcp_server", token = ?self.token, "Could not send a progress notification: {}", e);
+        }
+    }
+}
+
+/// Where a task goes and how long it may run once started.
+#[derive(Clone, Debug)]
+pub struct DispatchOptions {
+    /// Pins the task to one plugin session.
+    pub session: Option<String>,
+    pub execution_timeout: Duration,
+    /// Told when the task is picked up, and every `PROGRESS_INTERVAL` while it waits or runs.
+    pub progress: Option<ProgressReporter>,
+}
+impl Default for DispatchOptions {
+    fn default() -> Self {
+        Self {
+            session: None,
+            execution_timeout: TOOL_EXECUTION_TIMEOUT,
+            progress: None,
+        }
+    }
+}
+
+// `tokio::time::timeout(limit, rx)`, reporting "<phase> (<elapsed>s)" to `progress` every
+// `PROGRESS_INTERVAL` until it resolves.
+async fn wait_reporting_progress<T>(
+    rx: oneshot::Receiver<T>,
+    limit: Duration,
+    progress: Option<&ProgressReporter>,
+    phase: &str,
+) -> Result<Result<T, oneshot::error::RecvError>, tokio::time::error::Elapsed> {
+    let Some(progress) = progress else {
+        return tokio::time::timeout(limit, rx).await;
+    };
+    let started = Instant::now();
+    let wait = tokio::time::timeout(limit, rx);
+    tokio::pin!(wait);
+    let mut ticker = tokio::time::interval_at(started + PROGRESS_INTERVAL, PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            outcome = &mut wait => return outcome,
+            _ = ticker.tick() => progress.report(format!("{} ({}s)", phase, started.elapsed().as_secs())).await,
+        }
+    }
+}
+
+// The end of a timed-out task's streamed output, which shows how far it got.
+fn output_tail(output: &str) -> &str {
+    const TAIL_BYTES: usize = 4096;
+    let mut start = output.len().saturating_sub(TAIL_BYTES);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+/// Queues `args` with the StateManager and waits for the plugin's result. Waiting for a plugin
+/// poll to pick the task up (`QUEUE_WAIT_TIMEOUT`) and running it (`options.execution_timeout`)
+/// have separate deadlines, and a timeout says which one was missed.
+pub async fn dispatch_and_wait(
+    sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+    args: ToolArguments,
+    options: DispatchOptions,
+) -> Result<TaskCompletion, McpError> {
+    let DispatchOptions {
+        session,
+        execution_timeout,
+        progress,
+    } = options;
+    let request_id = args.id;
+    let (response_tx, response_rx) = oneshot::channel();
+    let (started_tx, started_rx) = oneshot::channel();
+    let command = StateManagerCommand::DispatchTask {
+        args,
+        session,
+        response_tx,
+        started_tx,
+    };
+    if sm_command_tx.send(command).await.is_err() {
+        return Err(McpError::internal_error("StateManager unavailable.", None));
+    }
+    if let Some(progress) = &progress {
+        progress
+            .report("Waiting for the Studio plugin".to_string())
+            .await;
+    }
+
+    // `started_tx` is dropped unsent when the task resolves without a pickup (e.g. the circuit
+    // breaker rejected it); the result is then already waiting on `response_rx`.
+    let pickup = wait_reporting_progress(
+        started_rx,
+        QUEUE_WAIT_TIMEOUT,
+        progress.as_ref(),
+        "Waiting for the Studio plugin",
+    )
+    .await;
+    if pickup.is_err() {
+        warn!(target: "mcp_server", request_id = ?request_id, phase = "queue_wait", "Tool execution timed out.");
+        if let Some(task_id) = request_id {
+            let _ = sm_command_tx
+                .send(StateManagerCommand::TaskTimedOut {
+                    task_id,
+                    reason: CancelReason::QueueWaitTimeout,
+                    output_tx: None,
+                })
+                .await;
+        }
+        let message = format!("Timed out after {}s waiting for the Studio plugin to pick up the task; it never started. Check that Studio is open and the MCP plugin is connected.", QUEUE_WAIT_TIMEOUT.as_secs());
+        return Err(McpError::new(
+            rmcp::model::ErrorCode::INTERNAL_ERROR,
+            message,
+            Some(serde_json::json!({ "timeout_phase": "queue_wait" })),
+        ));
+    }
+    if let (Some(progress), Ok(Ok(()))) = (&progress, &pickup) {
+        progress
+            .report("Task picked up by the Studio plugin".to_string())
+            .await;
+    }
+    match wait_reporting_progress(
+        response_rx,
+        execution_timeout,
+        progress.as_ref(),
+        "Executing in Studio",
+    )
+    .await
+    {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err(McpError::internal_error("Oneshot channel dropped.", None)),
+        Err(_) => {
+            warn!(target: "mcp_server", request_id = ?request_id, phase = "execution", "Tool execution timed out.");
+            let mut partial_output = String::new();
+            if let Some(task_id) = request_id {
+                let (output_tx, output_rx) = oneshot::channel();
+                if sm_command_tx
+                    .send(StateManagerCommand::TaskTimedOut {
+                        task_id,
+                        reason: CancelReason::ExecutionTimeout,
+                        output_tx: Some(output_tx),
+                    })
+                    .await
+                    .is_ok()
+                {
+                    partial_output = output_rx.await.unwrap_or_default();
                 }
             }
-        } else {
-            return (StatusCode::BAD_REQUEST, "Invalid X-MCP-Task-ID header").into_response();
+            let mut message = format!("Tool execution timed out after {}s; the plugin started the task but did not return a result. Pass a larger `timeout_seconds` (run_command, execute_discovered_luau_tool) or start the server with a larger --tool-timeout for slow operations.", execution_timeout.as_secs());
+            let mut data = serde_json::json!({ "timeout_phase": "execution" });
+            if !partial_output.is_empty() {
+                let tail = output_tail(&partial_output);
+                message.push_str(&format!("\nOutput streamed before the timeout:\n{}", tail));
+                data["partial_output"] = tail.into();
+            }
+            Err(McpError::new(
+                rmcp::model::ErrorCode::INTERNAL_ERROR,
+                message,
+                Some(data),
+            ))
         }
-    } else {
-        // This is a poll for a new task.
+    }
+}
+
+// --- RBXStudioServer struct and impls ---
+/// What `insert_model` reports when the marketplace search matches nothing.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoMatchPolicy {
+    /// Return an error result echoing the query.
+    #[default]
+    Error,
+    /// Return a successful result saying nothing was inserted.
+    Noop,
+}
+
+// Prefix the plugin's InsertModel puts on a no-match error so it can be told apart from
+// load or parenting failures.
+const INSERT_NO_MATCH_SENTINEL: &str = "INSERT_NO_MATCH: ";
+
+/// Behaviour switches for `RBXStudioServer`, set from the command line.
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    /// Append a server-side timing and payload-size block to every tool result.
+    pub result_timing: bool,
+    /// Append a JSON line per dispatched task to this log.
+    pub audit_log: Option<Arc<AuditLog>>,
+    /// Reject every tool that may modify the place.
+    pub read_only: bool,
+    pub insert_on_no_match: NoMatchPolicy,
+    /// Enable destructive tools such as `delete_tagged`.
+    pub allow_dangerous: bool,
+    /// `clientInfo.name`s (case-insensitive) treated as text-only whatever capabilities they declare.
+    pub text_only_clients: Vec<String>,
+    /// How long a started task may run unless the call passes `timeout_seconds`.
+    pub execution_timeout: Duration,
+    /// Send each discovered tool's source from disk with the task, for the plugin to run.
+    pub ship_tool_source: bool,
+    /// Times a task whose error result matches `retry_patterns` is dispatched again; 0 disables retries.
+    pub max_retries: u32,
+    /// Case-insensitive substrings of plugin error text that mark a failure as transient.
+    pub retry_patterns: Vec<String>,
+    /// Folder `execute_luau_file` may read scripts from; the tool is disabled without one.
+    pub luau_files_dir: Option<PathBuf>,
+}
+/// Plugin errors worth retrying by default: Studio was busy, and the task never got going.
+pub const DEFAULT_RETRY_PATTERNS: &[&str] = &[
+    "Studio is compiling",
+    "attempt to yield across",
+    "Studio is busy",
+];
+// Wait before the first retry, doubled for each one after, up to the cap.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            result_timing: false,
+            audit_log: None,
+            read_only: false,
+            insert_on_no_match: NoMatchPolicy::default(),
+            allow_dangerous: false,
+            text_only_clients: Vec::new(),
+            execution_timeout: TOOL_EXECUTION_TIMEOUT,
+            ship_tool_source: false,
+            max_retries: 0,
+            retry_patterns: DEFAULT_RETRY_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            luau_files_dir: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RBXStudioServer {
+    sm_command_tx: mpsc::Sender<StateManagerCommand>,
+    discovered_luau_tools: ToolRegistry,
+    options: ServerOptions,
+    /// Set from this connection's `initialize` request: whether results must be flattened to text.
+    text_only_client: Arc<AtomicBool>,
+    /// Plugin session chosen with `set_target_session`; `None` lets waiting sessions take turns.
+    target_session: Arc<Mutex<Option<String>>>,
+}
+impl RBXStudioServer {
+    pub fn new(
+        sm_command_tx: mpsc::Sender<StateManagerCommand>,
+        discovered_luau_tools: ToolRegistry,
+        options: ServerOptions,
+    ) -> Self {
+        Self {
+            sm_command_tx,
+            discovered_luau_tools,
+            options,
+            text_only_client: Arc::new(AtomicBool::new(false)),
+            target_session: Arc::new(Mutex::new(None)),
+        }
+    }
+    // Decides from the client's `initialize` request whether this connection's results are flattened
+    // to text; `text_only_clients` overrides what the client declares.
+    fn negotiate_content(&self, request: &InitializeRequestParam) {
+        let client = &request.client_info.name;
+        let listed = self
+            .options
+            .text_only_clients
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(client));
+        let text_only = listed || declares_text_only(&request.capabilities);
+        if text_only {
+            info!(target: "mcp_server", client = %client, listed, "Client is text-only; non-text result content will be flattened.");
+        }
+        self.text_only_client.store(text_only, Ordering::Relaxed);
+    }
+    async fn list_sessions(&self) -> Result<Vec<SessionInfo>, McpError> {
         let (response_tx, response_rx) = oneshot::channel();
-        let cmd = StateManagerCommand::PollForTask { response_tx };
+        if self
+            .sm_command_tx
+            .send(StateManagerCommand::ListSessions { response_tx })
+            .await
+            .is_err()
+        {
+            return Err(McpError::internal_error("StateManager unavailable.", None));
+        }
+        response_rx
+            .await
+            .map_err(|_| McpError::internal_error("Oneshot channel dropped.", None))
+    }
+    // Native variants classify themselves; discovered tools opt in with `--- @readonly`.
+    fn is_read_only_task(&self, args_values: &ToolArgumentValues) -> bool {
+        match args_values {
+            ToolArgumentValues::ExecuteLuauByName { tool_name, .. } => self
+                .discovered_luau_tools
+                .snapshot()
+                .get(tool_name)
+                .is_some_and(|tool| tool.readonly),
+            ToolArgumentValues::Batch { steps, .. } => {
+                steps.iter().all(|step| self.is_read_only_task(step))
+            }
+            other => other.is_read_only(),
+        }
+    }
+    async fn generic_tool_run(
+        &self,
+        args_values: ToolArgumentValues,
+    ) -> Result<CallToolResult, McpError> {
+        let priority = match TaskPriority::for_call(None) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        self.generic_tool_run_with_timeout(args_values, None, priority, None)
+            .await
+    }
+    // `timeout_seconds` comes from the tool call; it replaces the server-wide execution timeout,
+    // up to `MAX_TOOL_EXECUTION_TIMEOUT`. Retries reuse `idempotency_key`; only successful
+    // results are remembered under it, so they still run the task again.
+    async fn generic_tool_run_with_timeout(
+        &self,
+        args_values: ToolArgumentValues,
+        timeout_seconds: Option<u32>,
+        priority: TaskPriority,
+        idempotency_key: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if self.options.read_only && !self.is_read_only_task(&args_values) {
+            let tool = match &args_values {
+                ToolArgumentValues::ExecuteLuauByName { tool_name, .. } => tool_name.as_str(),
+                other => other.variant_name(),
+            };
+            warn!(target: "mcp_server", tool = tool, "Rejected mutating tool in read-only mode.");
+            return Ok(CallToolResult::error(vec![Content::text(format!("The server is read-only: '{}' may modify the place and is disabled by --read-only.", tool))]));
+        }
+        if BATCH_STEP.try_with(|_| ()).is_ok() {
+            BATCH_STEP.with(|step| {
+                step.borrow_mut().get_or_insert(args_values);
+            });
+            return Ok(CallToolResult::success(Vec::new()));
+        }
+        let progress = ProgressReporter::for_current_call();
+        let dispatched_at = SystemTime::now();
+        let mut attempt = 0;
+        let (completion, request_bytes) = loop {
+            // Every attempt is a new task, so a late result from an earlier one can't be taken for it.
+            let (tool_arguments_with_id, task_id) = ToolArguments::new_with_id(args_values.clone());
+            let tool_arguments_with_id = tool_arguments_with_id
+                .with_priority(priority)
+                .with_idempotency_key(idempotency_key.clone());
+            let request_bytes = tool_arguments_with_id.to_luau_string().len();
+            let started = Instant::now();
+            let dispatch_options = DispatchOptions {
+                session: self.target_session.lock().unwrap().clone(),
+                execution_timeout: timeout_seconds
+                    .map_or(self.options.execution_timeout, |seconds| {
+                        Duration::from_secs(u64::from(seconds))
+                    })
+                    .min(MAX_TOOL_EXECUTION_TIMEOUT),
+                progress: progress.clone(),
+            };
+            let outcome = dispatch_and_wait(
+                &self.sm_command_tx,
+                tool_arguments_with_id,
+                dispatch_options,
+            )
+            .await;
+            if let Some(audit_log) = &self.options.audit_log {
+                audit_log.record(task_id, &args_values, &outcome, started.elapsed());
+            }
+            let completion = outcome?;
+            if attempt >= self.options.max_retries || !self.is_retryable(&completion.result) {
+                break (completion, request_bytes);
+            }
+            let delay = RETRY_BASE_DELAY
+                .saturating_mul(1 << attempt.min(16))
+                .min(RETRY_MAX_DELAY);
+            attempt += 1;
+            info!(target: "mcp_server", task_id=%task_id, tool = args_values.variant_name(), attempt, "Transient plugin error; retrying in {}ms.", delay.as_millis());
+            tokio::time::sleep(delay).await;
+        };
+        let mut result = completion.result;
+        if self.options.result_timing {
+            let timing = ResultTiming {
+                dispatched_at,
+                completed_at: SystemTime::now(),
+                queue_wait: completion.queue_wait,
+                request_bytes,
+                response_bytes: completion.response_bytes,
+            };
+            result
+                .content
+                .push(Content::text(timing.to_json().to_string()));
+        }
+        if self.text_only_client.load(Ordering::Relaxed) {
+            result.content = flatten_to_text(result.content);
+        }
+        Ok(result)
+    }
 
-        if axum_state.sm_command_tx.send(cmd).await.is_err() {
-            return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+    // Only error results can be retried, and only when their text names a transient condition;
+    // timeouts aren't, since the plugin may have run the task.
+    fn is_retryable(&self, result: &CallToolResult) -> bool {
+        if result.is_error != Some(true) || self.options.retry_patterns.is_empty() {
+            return false;
         }
-        
-        match tokio::time::timeout(LONG_POLL_DURATION, response_rx).await {
-            Ok(Ok(Some(task))) => {
-                let luau_string = task.to_luau_string();
-                (StatusCode::OK, [("Content-Type", "application/luau")], luau_string).into_response()
+        let patterns: Vec<String> = self
+            .options
+            .retry_patterns
+            .iter()
+            .map(|pattern| pattern.to_lowercase())
+            .collect();
+        result.content.iter().any(|content| match &content.raw {
+            RawContent::Text(text) => {
+                let text = text.text.to_lowercase();
+                patterns
+                    .iter()
+                    .any(|pattern| text.contains(pattern.as_str()))
             }
-            _ => (StatusCode::NO_CONTENT, "").into_response(),
+            _ => false,
+        })
+    }
+
+    // Prepends the sources of `tool_name`'s `@preamble` tools (and theirs, depth-first) to the
+    // arguments chunk, each at most once. Every preamble runs in its own function so a
+    // trailing `return` in it can't cut the chunk short.
+    fn with_preambles(&self, tool_name: &str, arguments_luau: String) -> Result<String, String> {
+        fn visit<'a>(
+            tools: &'a HashMap<String, DiscoveredTool>,
+            name: &'a str,
+            chain: &mut Vec<&'a str>,
+            order: &mut Vec<&'a str>,
+        ) -> Result<(), String> {
+            let tool = tools.get(name).ok_or_else(|| {
+                format!(
+                    "Preamble '{}' (required by '{}') is not a discovered Luau tool.",
+                    name,
+                    chain.last().copied().unwrap_or(name)
+                )
+            })?;
+            for preamble in &tool.preambles {
+                if chain.contains(&preamble.as_str()) || preamble == name {
+                    return Err(format!(
+                        "Preamble cycle: '{}' requires '{}'.",
+                        name, preamble
+                    ));
+                }
+                if order.contains(&preamble.as_str()) {
+                    continue;
+                }
+                chain.push(name);
+                visit(tools, preamble, chain, order)?;
+                chain.pop();
+                order.push(preamble);
+            }
+            Ok(())
+        }
+        let tools = self.discovered_luau_tools.snapshot();
+        let mut order = Vec::new();
+        visit(&tools, tool_name, &mut Vec::new(), &mut order)?;
+        if order.is_empty() {
+            return Ok(arguments_luau);
+        }
+        let mut chunk = String::new();
+        for name in order {
+            let path = &tools[name].file_path;
+            let source = fs::read_to_string(path).map_err(|e| {
+                format!(
+                    "Could not read preamble '{}' at {}: {}",
+                    name,
+                    path.display(),
+                    e
+                )
+            })?;
+            chunk.push_str(&format!(
+                "-- preamble: {}\n(function()\n{}\nend)()\n",
+                name, source
+            ));
+        }
+        chunk.push_str(&arguments_luau);
+        Ok(chunk)
+    }
+
+    // Builds one `batch` step by calling its tool as a client would, so the step gets exactly the
+    // tool's checks and encoding; the task the tool would dispatch is taken instead.
+    async fn batch_step(&self, step: CallToolRequestParam) -> Result<ToolArgumentValues, String> {
+        let name = step.name.to_string();
+        let (peer, _) = Peer::new(
+            Arc::new(AtomicU32RequestIdProvider::default()),
+            ClientInfo::default(),
+        );
+        let context = RequestContext {
+            ct: CancellationToken::new(),
+            id: RequestId::Number(0),
+            peer,
+        };
+        let call = ToolCallContext::new(self, step, context);
+        let (result, task) = BATCH_STEP
+            .scope(RefCell::new(None), async {
+                let result = Self::tool_box().call(call).await;
+                (result, BATCH_STEP.with(|step| step.take()))
+            })
+            .await;
+        match (task, result) {
+            (Some(task), _) => Ok(task),
+            (None, Err(e)) => Err(e.message.to_string()),
+            (None, Ok(result)) if result.is_error == Some(true) => Err(result
+                .content
+                .iter()
+                .filter_map(|content| match &content.raw {
+                    RawContent::Text(text) => Some(text.text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            (None, Ok(_)) => Err(format!(
+                "'{}' is answered by the server without a task in Studio, so it can't be batched.",
+                name
+            )),
+        }
+    }
+
+    async fn create_event(
+        &self,
+        class_name: &str,
+        name: String,
+        parent_path: String,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_instance_name(&name) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::CreateEvent {
+            class_name: class_name.to_string(),
+            name,
+            parent_path,
+        })
+        .await
+    }
+}
+// Server-side view of one task, appended to results when `--result-timing` is set
+// so latency can be attributed to queueing, plugin execution, or transport.
+struct ResultTiming {
+    dispatched_at: SystemTime,
+    completed_at: SystemTime,
+    queue_wait: Option<Duration>,
+    request_bytes: usize,
+    response_bytes: usize,
+}
+impl ResultTiming {
+    fn to_json(&self) -> serde_json::Value {
+        let unix_ms = |t: SystemTime| {
+            t.duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0)
+        };
+        let total = self
+            .completed_at
+            .duration_since(self.dispatched_at)
+            .unwrap_or_default();
+        serde_json::json!({
+            "server_timing": {
+                "dispatched_at_ms": unix_ms(self.dispatched_at),
+                "completed_at_ms": unix_ms(self.completed_at),
+                "queue_wait_ms": self.queue_wait.map(|d| d.as_millis() as u64),
+                "total_ms": total.as_millis() as u64,
+                "request_bytes": self.request_bytes,
+                "response_bytes": self.response_bytes,
+            }
+        })
+    }
+}
+
+// The first text item of a plugin result that parses as JSON; plugin tools put their data first.
+fn first_json_content(result: &CallToolResult) -> Option<serde_json::Value> {
+    result
+        .content
+        .iter()
+        .find_map(|content| match &content.raw {
+            RawContent::Text(text) => serde_json::from_str(&text.text).ok(),
+            _ => None,
+        })
+}
+
+// Whether a client's declared capabilities leave out images: it lists the content types it
+// renders under `capabilities.experimental.content.types` and "image" isn't one of them.
+// Clients that declare nothing are assumed to render everything.
+fn declares_text_only(capabilities: &ClientCapabilities) -> bool {
+    let Some(types) = capabilities
+        .experimental
+        .as_ref()
+        .and_then(|experimental| experimental.get("content"))
+        .and_then(|content| content.get("types"))
+        .and_then(serde_json::Value::as_array)
+    else {
+        return false;
+    };
+    !types.iter().any(|kind| kind.as_str() == Some("image"))
+}
+
+// Replaces every non-text content item with a short text placeholder, for clients that
+// can't render images or embedded resources.
+fn flatten_to_text(content: Vec<Content>) -> Vec<Content> {
+    content
+        .into_iter()
+        .map(|item| match &item.raw {
+            RawContent::Text(_) => item,
+            RawContent::Image(image) => Content::text(format!(
+                "[image omitted: {}, {} bytes of base64]",
+                image.mime_type,
+                image.data.len()
+            )),
+            _ => Content::text("[non-text content omitted]"),
+        })
+        .collect()
+}
+
+impl ServerHandler for RBXStudioServer {
+    async fn list_tools(
+        &self,
+        _: PaginatedRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            next_cursor: None,
+            tools: Self::tool_box().list(),
+        })
+    }
+    // As `#[tool(tool_box)]` would, but the tool runs with its call's peer and `_meta` at hand.
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let call = CallContext {
+            peer: context.peer.clone(),
+            meta: request.meta.take().unwrap_or_default(),
+        };
+        let context = ToolCallContext::new(self, request, context);
+        CALL_CONTEXT
+            .scope(call, Self::tool_box().call(context))
+            .await
+    }
+    async fn initialize(
+        &self,
+        request: InitializeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, McpError> {
+        self.negotiate_content(&request);
+        Ok(self.get_info())
+    }
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo { protocol_version: ProtocolVersion::V_2025_03_26, server_info: Implementation::from_build_env(), instructions: Some(if self.options.read_only { "... This server is read-only: tools that may modify the place are rejected; discovered Luau tools are only available when marked `--- @readonly`, and take their arguments as a JSON object rather than Luau." } else { "..." }.into()), capabilities: ServerCapabilities::default(), }
+    }
+}
+#[tool(tool_box)]
+impl RBXStudioServer {
+    #[tool(
+        description = "Runs a raw Luau command string in Studio. `context` is \"plugin\" (default; full plugin environment) or \"server\" (plugin-only globals such as `plugin` are hidden, as in a server Script). `timeout_seconds` (max 300) lets a slow command run longer than the server's default tool timeout. `priority` (\"low\", \"normal\" (default) or \"high\") orders it among queued tasks. `idempotency_key` makes a repeat of the call with the same key (within 10 minutes) return the first successful result instead of running again. Returns JSON {\"output\": printed lines, \"return_values\": [every value the command returns]}; Instances are given as their full path, other Roblox datatypes via tostring."
+    )]
+    async fn run_command(
+        &self,
+        #[tool(param)] command: String,
+        #[tool(param)] context: Option<String>,
+        #[tool(param)] timeout_seconds: Option<u32>,
+        #[tool(param)] priority: Option<String>,
+        #[tool(param)] idempotency_key: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let priority = match TaskPriority::for_call(priority.as_deref()) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        if let Some(context) = context.as_deref().filter(|c| !RUN_CONTEXTS.contains(c)) {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'context' must be one of {}, got \"{}\".",
+                RUN_CONTEXTS.join(", "),
+                context
+            ))]));
+        }
+        if timeout_seconds == Some(0) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'timeout_seconds' must be at least 1.",
+            )]));
+        }
+        if let Some(Err(message)) = idempotency_key.as_deref().map(validate_idempotency_key) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run_with_timeout(
+            ToolArgumentValues::RunCommand { command, context },
+            timeout_seconds,
+            priority,
+            idempotency_key,
+        )
+        .await
+    }
+    #[tool(
+        description = "Runs a Luau file from the server's --luau-files-dir as run_command would, so a script can be edited on disk and re-run without pasting it. `path` is relative to that folder and must stay inside it. `context` and `timeout_seconds` are as for run_command."
+    )]
+    async fn execute_luau_file(
+        &self,
+        #[tool(param)] path: String,
+        #[tool(param)] context: Option<String>,
+        #[tool(param)] timeout_seconds: Option<u32>,
+    ) -> Result<CallToolResult, McpError> {
+        let Some(root) = &self.options.luau_files_dir else {
+            return Ok(CallToolResult::error(vec![Content::text("execute_luau_file is disabled; start the server with --luau-files-dir to choose the folder it may read from.")]));
+        };
+        let file = match resolve_luau_file(root, &path) {
+            Ok(file) => file,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        let command = match fs::read_to_string(&file) {
+            Ok(command) => command,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Could not read '{}': {}",
+                    path, e
+                ))]))
+            }
+        };
+        self.run_command(command, context, timeout_seconds, None, None)
+            .await
+    }
+    #[tool(
+        description = "Inserts a model from the Creator Marketplace by asset ID or search query. `parent_path` (default Workspace) sets where it lands and `position` ([x, y, z]) where it is pivoted; without a position it is placed in front of the camera. `idempotency_key` makes a repeat of the call with the same key (within 10 minutes) return the first successful result instead of running again."
+    )]
+    async fn insert_model(
+        &self,
+        #[tool(param)] query: String,
+        #[tool(param)] parent_path: Option<String>,
+        #[tool(param)] position: Option<[f64; 3]>,
+        #[tool(param)] idempotency_key: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(Err(message)) = parent_path.as_deref().map(validate_instance_path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if position.is_some_and(|p| p.iter().any(|c| !c.is_finite())) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'position' components must be finite numbers.",
+            )]));
+        }
+        if let Some(Err(message)) = idempotency_key.as_deref().map(validate_idempotency_key) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let args = ToolArgumentValues::InsertModel {
+            query: query.clone(),
+            parent_path,
+            position,
+        };
+        let priority = match TaskPriority::for_call(None) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        let result = self
+            .generic_tool_run_with_timeout(args, None, priority, idempotency_key)
+            .await?;
+        let no_match = result.is_error.unwrap_or(false)
+            && result.content.first().is_some_and(|content| matches!(&content.raw, RawContent::Text(text) if text.text.starts_with(INSERT_NO_MATCH_SENTINEL)));
+        if !no_match {
+            return Ok(result);
+        }
+        Ok(match self.options.insert_on_no_match {
+            NoMatchPolicy::Error => CallToolResult::error(vec![Content::text(format!(
+                "No assets matched query '{}'.",
+                query
+            ))]),
+            NoMatchPolicy::Noop => CallToolResult::success(vec![Content::text(format!(
+                "No assets matched query '{}'; nothing was inserted.",
+                query
+            ))]),
+        })
+    }
+    #[tool(
+        description = "Executes a specific Luau tool... Tools in subfolders are namespaced by folder, e.g. `Lighting.SetAmbient`. `timeout_seconds` (max 300) lets a slow tool run longer than the server's default tool timeout. `priority` (\"low\", \"normal\" (default) or \"high\") orders it among queued tasks. `idempotency_key` makes a repeat of the call with the same key (within 10 minutes) return the first successful result instead of running again. With --read-only, `tool_arguments_luau` must be a JSON object of the arguments instead of Luau; the server encodes it."
+    )]
+    async fn execute_discovered_luau_tool(
+        &self,
+        #[tool(param)] tool_name: String,
+        #[tool(param)] tool_arguments_luau: String,
+        #[tool(param)] timeout_seconds: Option<u32>,
+        #[tool(param)] priority: Option<String>,
+        #[tool(param)] idempotency_key: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let priority = match TaskPriority::for_call(priority.as_deref()) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        if !self
+            .discovered_luau_tools
+            .snapshot()
+            .contains_key(&tool_name)
+        {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Luau tool '{}' not found.",
+                tool_name
+            ))]));
+        }
+        if timeout_seconds == Some(0) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'timeout_seconds' must be at least 1.",
+            )]));
+        }
+        if let Some(Err(message)) = idempotency_key.as_deref().map(validate_idempotency_key) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let tool_arguments_luau = if self.options.read_only {
+            match read_only_tool_arguments(&tool_arguments_luau) {
+                Ok(arguments_luau) => arguments_luau,
+                Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+            }
+        } else {
+            tool_arguments_luau
+        };
+        let arguments_luau = match self.with_preambles(&tool_name, tool_arguments_luau) {
+            Ok(arguments_luau) => arguments_luau,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        let source = if self.options.ship_tool_source {
+            let tools = self.discovered_luau_tools.snapshot();
+            let path = &tools[&tool_name].file_path;
+            match fs::read_to_string(path) {
+                Ok(source) => Some(source),
+                Err(e) => {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Could not read Luau tool '{}' at {}: {}",
+                        tool_name,
+                        path.display(),
+                        e
+                    ))]))
+                }
+            }
+        } else {
+            None
+        };
+        self.generic_tool_run_with_timeout(
+            ToolArgumentValues::ExecuteLuauByName {
+                tool_name,
+                arguments_luau,
+                source,
+            },
+            timeout_seconds,
+            priority,
+            idempotency_key,
+        )
+        .await
+    }
+    #[tool(
+        description = "Lists the Luau tools execute_discovered_luau_tool can run, sorted by name, as JSON: `name`, `file_path`, the author's `description`, an `arg_schema` (JSON schema of the table `tool_arguments_luau` should return, or null when undeclared), whether it is `readonly` (the only kind --read-only allows), and its `preambles`. Answered by the server without contacting Studio."
+    )]
+    async fn list_luau_tools(&self) -> Result<CallToolResult, McpError> {
+        let discovered_luau_tools = self.discovered_luau_tools.snapshot();
+        let mut names: Vec<&String> = discovered_luau_tools.keys().collect();
+        names.sort();
+        let tools: Vec<serde_json::Value> = names
+            .into_iter()
+            .map(|name| {
+                let tool = &discovered_luau_tools[name];
+                serde_json::json!({
+                    "name": name,
+                    "file_path": tool.file_path,
+                    "description": tool.description,
+                    "arg_schema": tool.arg_schema,
+                    "readonly": tool.readonly,
+                    "preambles": tool.preambles,
+                })
+            })
+            .collect();
+        let body = serde_json::json!({ "tools": tools, "read_only_mode": self.options.read_only });
+        Ok(CallToolResult::success(vec![Content::text(
+            body.to_string(),
+        )]))
+    }
+    #[tool(
+        description = "Creates a RemoteEvent named `name` under the instance at `parent_path` and returns the new event's path."
+    )]
+    async fn create_remote_event(
+        &self,
+        #[tool(param)] name: String,
+        #[tool(param)] parent_path: String,
+    ) -> Result<CallToolResult, McpError> {
+        self.create_event("RemoteEvent", name, parent_path).await
+    }
+    #[tool(
+        description = "Creates a BindableEvent named `name` under the instance at `parent_path` and returns the new event's path."
+    )]
+    async fn create_bindable_event(
+        &self,
+        #[tool(param)] name: String,
+        #[tool(param)] parent_path: String,
+    ) -> Result<CallToolResult, McpError> {
+        self.create_event("BindableEvent", name, parent_path).await
+    }
+    #[tool(
+        description = "Deep-clones the instance at `instance_path`. The clone is parented to `new_parent_path` (defaults to the source's parent) and optionally renamed to `new_name`. Returns the clone's path."
+    )]
+    async fn clone_instance(
+        &self,
+        #[tool(param)] instance_path: String,
+        #[tool(param)] new_parent_path: Option<String>,
+        #[tool(param)] new_name: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(Err(message)) = new_name.as_deref().map(validate_instance_name) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::CloneInstance {
+            instance_path,
+            new_parent_path,
+            new_name,
+        })
+        .await
+    }
+    #[tool(
+        description = "Searches descendants of `root_path` (default Workspace) for instances whose Name matches the Lua pattern `name_pattern` and/or that are of `class_name` (IsA). At least one filter is required. `root_path` may be given as `game.Workspace.Map` or with `/` separators. Returns a JSON array of matching paths (empty when nothing matches), capped at 200."
+    )]
+    async fn find_instances(
+        &self,
+        #[tool(param)] name_pattern: Option<String>,
+        #[tool(param)] class_name: Option<String>,
+        #[tool(param)] root_path: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if name_pattern.is_none() && class_name.is_none() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "find_instances requires at least one of 'name_pattern' or 'class_name'.",
+            )]));
+        }
+        let root_path = root_path.map_or_else(
+            || "Workspace".to_string(),
+            |root_path| normalize_instance_path(&root_path),
+        );
+        if let Err(message) = validate_instance_path(&root_path) {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'root_path': {}",
+                message
+            ))]));
         }
+        self.generic_tool_run(ToolArgumentValues::FindInstances {
+            root_path,
+            class_name,
+            name_pattern,
+            limit: MAX_FIND_RESULTS,
+        })
+        .await
     }
-}
\ No newline at end of file
+    #[tool(
+        description = "Returns all Attributes on the instance at `instance_path` as a JSON object. Roblox datatypes are tagged, e.g. {\"$type\":\"Vector3\",\"x\":1,\"y\":2,\"z\":3}."
+    )]
+    async fn get_attributes(
+        &self,
+        #[tool(param)] instance_path: String,
+    ) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::GetAttributes { instance_path })
+            .await
+    }
+    #[tool(
+        description = "Sets Attributes on the instance at `instance_path` from a JSON object of name -> value. Values may be strings, numbers, booleans, null (removes the attribute), or tagged datatypes: {\"$type\":\"Vector3\",\"x\":..,\"y\":..,\"z\":..}, Vector2 (x,y), Color3 (r,g,b in 0-1), UDim (scale,offset), UDim2 (x_scale,x_offset,y_scale,y_offset), NumberRange (min,max), CFrame (`components`: the 12 numbers of CFrame:GetComponents(), or x,y,z for a position only), BrickColor (name), EnumItem (value, e.g. \"Enum.Material.Plastic\"). Values read with get_attributes can be written back as they are."
+    )]
+    async fn set_attributes(
+        &self,
+        #[tool(param)] instance_path: String,
+        #[tool(param)] attributes: HashMap<String, serde_json::Value>,
+    ) -> Result<CallToolResult, McpError> {
+        if attributes.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "set_attributes requires at least one attribute.",
+            )]));
+        }
+        let mut encoded = Vec::new();
+        let mut clear = Vec::new();
+        for (name, value) in attributes {
+            if let Err(message) = validate_attribute_name(&name) {
+                return Ok(CallToolResult::error(vec![Content::text(message)]));
+            }
+            if value.is_null() {
+                clear.push(name);
+                continue;
+            }
+            match luau::from_json(&value) {
+                Ok(luau_value) => encoded.push((name, luau_value)),
+                Err(message) => {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Attribute '{}': {}",
+                        name, message
+                    ))]))
+                }
+            }
+        }
+        self.generic_tool_run(ToolArgumentValues::SetAttributes {
+            instance_path,
+            attributes: encoded,
+            clear,
+        })
+        .await
+    }
+    #[tool(
+        description = "Creates an instance of `class_name`, applies `properties`, and parents it to `parent_path`. Arguments are validated before anything reaches Studio; violations come back as {\"validation_errors\": [{\"field\", \"message\"}]}."
+    )]
+    async fn create_instance(
+        &self,
+        #[tool(aggr)] params: CreateInstanceParams,
+    ) -> Result<CallToolResult, McpError> {
+        let mut errors = params.validate();
+        let mut properties = Vec::new();
+        for (name, value) in &params.properties {
+            match luau::from_json(value) {
+                Ok(encoded) => properties.push((name.clone(), encoded)),
+                Err(message) => errors.push(ValidationError::new(
+                    format!("properties.{}", name),
+                    message,
+                )),
+            }
+        }
+        if !errors.is_empty() {
+            return Ok(validation_error_result(&errors));
+        }
+        let CreateInstanceParams {
+            class_name,
+            parent_path,
+            tag,
+            ..
+        } = params;
+        self.generic_tool_run(ToolArgumentValues::CreateInstance {
+            class_name,
+            parent_path,
+            properties,
+            tag,
+        })
+        .await
+    }
+    #[tool(
+        description = "Repeatedly evaluates `condition_luau` (an expression, or a chunk that returns a value) in Studio until it is truthy or `timeout_ms` elapses (max 25000 with the default --tool-timeout). Returns JSON with `satisfied`, `elapsed_ms` and the last error raised by the condition, if any."
+    )]
+    async fn wait_for(
+        &self,
+        #[tool(param)] condition_luau: String,
+        #[tool(param)] timeout_ms: u32,
+    ) -> Result<CallToolResult, McpError> {
+        if condition_luau.trim().is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'condition_luau' must not be empty.",
+            )]));
+        }
+        let max_timeout_ms = self
+            .options
+            .execution_timeout
+            .saturating_sub(WAIT_FOR_TIMEOUT_MARGIN)
+            .as_millis() as u32;
+        if timeout_ms == 0 || timeout_ms > max_timeout_ms {
+            return Ok(CallToolResult::error(vec![Content::text(format!("'timeout_ms' must be between 1 and {} so the wait ends before the tool call times out.", max_timeout_ms))]));
+        }
+        self.generic_tool_run(ToolArgumentValues::WaitFor {
+            condition_luau,
+            timeout_ms,
+        })
+        .await
+    }
+    #[tool(
+        description = "Moves the instances at `instance_paths` into a new `container_class` (\"Model\" by default, or \"Folder\") named `container_name`, created under the first instance's parent. Returns the container's path."
+    )]
+    async fn group_instances(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] container_class: Option<String>,
+        #[tool(param)] container_name: String,
+    ) -> Result<CallToolResult, McpError> {
+        if instance_paths.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'instance_paths' must list at least one instance.",
+            )]));
+        }
+        if let Some(message) = instance_paths
+            .iter()
+            .find_map(|path| validate_instance_path(path).err())
+        {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let container_class = container_class.unwrap_or_else(|| "Model".to_string());
+        if container_class != "Model" && container_class != "Folder" {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'container_class' must be \"Model\" or \"Folder\", got \"{}\".",
+                container_class
+            ))]));
+        }
+        if let Err(message) = validate_instance_name(&container_name) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::GroupInstances {
+            instance_paths,
+            container_class,
+            container_name,
+        })
+        .await
+    }
+    #[tool(
+        description = "Turns a service switch on or off. Allowed `service`.`property` pairs: HttpService.HttpEnabled, Players.CharacterAutoLoads, Workspace.StreamingEnabled, StarterGui.ShowDevelopmentGui, Lighting.GlobalShadows, SoundService.RespectFilteringEnabled. Returns the previous and new values."
+    )]
+    async fn set_service_enabled(
+        &self,
+        #[tool(param)] service: String,
+        #[tool(param)] property: String,
+        #[tool(param)] enabled: bool,
+    ) -> Result<CallToolResult, McpError> {
+        if !TOGGLABLE_SERVICE_PROPERTIES
+            .iter()
+            .any(|(s, p)| *s == service && *p == property)
+        {
+            let allowed = TOGGLABLE_SERVICE_PROPERTIES
+                .iter()
+                .map(|(s, p)| format!("{}.{}", s, p))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'{}.{}' is not a togglable service property. Allowed: {}.",
+                service, property, allowed
+            ))]));
+        }
+        self.generic_tool_run(ToolArgumentValues::SetServiceEnabled {
+            service,
+            property,
+            enabled,
+        })
+        .await
+    }
+    #[tool(
+        description = "Returns the place's instance, part and script counts plus Studio memory use (MB) as JSON. Traversal stops after 200000 instances, in which case `approximate` is true."
+    )]
+    async fn get_place_stats(&self) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::GetPlaceStats {
+            max_instances: MAX_STATS_TRAVERSAL,
+        })
+        .await
+    }
+    #[tool(
+        description = "Selects the instances at `instance_paths` and runs the Luau `command` in the same task, so no other task can change the selection in between. The command can read it with game:GetService(\"Selection\"):Get()."
+    )]
+    async fn with_selection(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] command: String,
+    ) -> Result<CallToolResult, McpError> {
+        if instance_paths.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'instance_paths' must list at least one instance.",
+            )]));
+        }
+        if let Some(message) = instance_paths
+            .iter()
+            .find_map(|path| validate_instance_path(path).err())
+        {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::WithSelection {
+            instance_paths,
+            command,
+        })
+        .await
+    }
+    #[tool(
+        description = "Exports the subtree at `root_path` as JSON nodes of the form {\"className\", \"properties\", \"children\"}, with datatypes tagged by \"$type\". `max_depth` defaults to 5 (max 20); at most 2000 nodes are exported and `truncated` reports whether anything was cut."
+    )]
+    async fn export_tree(
+        &self,
+        #[tool(param)] root_path: String,
+        #[tool(param)] max_depth: Option<u32>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_instance_path(&root_path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let max_depth = max_depth
+            .unwrap_or(DEFAULT_EXPORT_DEPTH)
+            .min(MAX_EXPORT_DEPTH);
+        self.generic_tool_run(ToolArgumentValues::ExportTree {
+            root_path,
+            max_depth,
+            max_nodes: MAX_EXPORT_NODES,
+        })
+        .await
+    }
+    #[tool(
+        description = "Pauses (false) or resumes (true) delivery of tasks to Studio, e.g. while a person edits the place by hand. While paused, tool calls queue up (and time out if not resumed in time); resuming delivers the backlog in order."
+    )]
+    async fn set_dispatch_enabled(
+        &self,
+        #[tool(param)] enabled: bool,
+    ) -> Result<CallToolResult, McpError> {
+        let state = set_dispatch_enabled(&self.sm_command_tx, enabled).await?;
+        let body = serde_json::to_string(&state)
+            .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+        Ok(CallToolResult::success(vec![Content::text(body)]))
+    }
+    #[tool(
+        description = "Lists the connected Studio plugin sessions (one per Studio window) as JSON: `session_id`, whether it is `waiting` for work, tasks `queued` for it and `in_flight`, and seconds since its last poll. `target_session` is the session chosen with set_target_session, if any."
+    )]
+    async fn list_plugin_sessions(&self) -> Result<CallToolResult, McpError> {
+        let sessions = self.list_sessions().await?;
+        let target_session = self.target_session.lock().unwrap().clone();
+        let body = serde_json::json!({ "sessions": sessions, "target_session": target_session });
+        Ok(CallToolResult::success(vec![Content::text(
+            body.to_string(),
+        )]))
+    }
+    #[tool(
+        description = "Sends this client's later tool calls to one Studio window only, by a `session_id` from list_plugin_sessions. Omit `session_id` to go back to handing each task to whichever connected session is free, in turn."
+    )]
+    async fn set_target_session(
+        &self,
+        #[tool(param)] session_id: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Some(session_id) = &session_id {
+            if !self
+                .list_sessions()
+                .await?
+                .iter()
+                .any(|session| &session.session_id == session_id)
+            {
+                return Ok(CallToolResult::error(vec![Content::text(format!("No plugin session '{}' is connected. Use list_plugin_sessions to see the connected sessions.", session_id))]));
+            }
+        }
+        let message = match &session_id {
+            Some(session_id) => format!("Tool calls now go to plugin session '{}'.", session_id),
+            None => "Tool calls now go to whichever plugin session is free.".to_string(),
+        };
+        *self.target_session.lock().unwrap() = session_id;
+        Ok(CallToolResult::success(vec![Content::text(message)]))
+    }
+    #[tool(
+        description = "Lists the tasks the server is still waiting on, oldest first, as JSON: `task_id`, `tool`, `status` (queued or running), `age_secs` and `streamed_bytes` (output the plugin has streamed so far). Use a `task_id` from here with cancel_task."
+    )]
+    async fn list_pending_tasks(&self) -> Result<CallToolResult, McpError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        if self
+            .sm_command_tx
+            .send(StateManagerCommand::ListPendingTasks { response_tx })
+            .await
+            .is_err()
+        {
+            return Err(McpError::internal_error("StateManager unavailable.", None));
+        }
+        let tasks = response_rx
+            .await
+            .map_err(|_| McpError::internal_error("Oneshot channel dropped.", None))?;
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::json!({ "tasks": tasks }).to_string(),
+        )]))
+    }
+    #[tool(
+        description = "Cancels a queued or running task by its `task_id` (see list_pending_tasks). The tool call waiting on it fails at once with a cancellation error. A task Studio has already started still runs to completion, but its result is discarded."
+    )]
+    async fn cancel_task(
+        &self,
+        #[tool(param)] task_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        let Ok(task_id) = Uuid::parse_str(task_id.trim()) else {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'{}' is not a task id.",
+                task_id
+            ))]));
+        };
+        let (response_tx, response_rx) = oneshot::channel();
+        if self
+            .sm_command_tx
+            .send(StateManagerCommand::CancelTask {
+                task_id,
+                response_tx,
+            })
+            .await
+            .is_err()
+        {
+            return Err(McpError::internal_error("StateManager unavailable.", None));
+        }
+        let found = response_rx
+            .await
+            .map_err(|_| McpError::internal_error("Oneshot channel dropped.", None))?;
+        if !found {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "No pending task {}; it may have already finished or timed out.",
+                task_id
+            ))]));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Cancelled task {}.",
+            task_id
+        ))]))
+    }
+    #[tool(
+        description = "Compiles the Luau `code` in Studio without running it and returns JSON {\"ok\", \"error\", \"line\"} describing the first syntax error, if any."
+    )]
+    async fn check_luau(&self, #[tool(param)] code: String) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::CheckLuau { code })
+            .await
+    }
+    #[tool(
+        description = "Lists connected players as JSON: name, display name, user id, team and character position (tagged Vector3, absent without a character). In edit mode there are no players, so the list is empty and a note explains why."
+    )]
+    async fn get_players(&self) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::GetPlayers {})
+            .await
+    }
+    #[tool(
+        description = "Sets one property to the same value on every instance in `instance_paths` in a single round trip. `value` accepts the same forms as set_attributes (strings, numbers, booleans, null, tagged datatypes). Returns per-path success as JSON; a failing path does not stop the others."
+    )]
+    async fn set_property_bulk(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] property: String,
+        #[tool(param)] value: serde_json::Value,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_bulk_paths(&instance_paths, 1) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if property.trim().is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'property' must not be empty.",
+            )]));
+        }
+        let value = match luau::from_json(&value) {
+            Ok(value) => value,
+            Err(message) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "'value': {}",
+                    message
+                ))]))
+            }
+        };
+        self.generic_tool_run(ToolArgumentValues::SetPropertyBulk {
+            instance_paths,
+            property,
+            value,
+        })
+        .await
+    }
+    #[tool(
+        description = "Returns recent Output log lines (prints, warnings and errors, including those from the last play session) as JSON lines with `level`, `message` and `timestamp_ms` (Unix milliseconds). Pass `since_ms` to only get lines logged after that time. At most the newest lines up to a fixed cap are returned; `truncated` tells when older lines were dropped."
+    )]
+    async fn get_run_logs(
+        &self,
+        #[tool(param)] since_ms: Option<u64>,
+    ) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::GetRunLogs {
+            since_ms,
+            max_lines: MAX_RUN_LOG_LINES,
+        })
+        .await
+    }
+    #[tool(
+        description = "Creates a Script, LocalScript or ModuleScript named `name` with `source` and parents it to `parent_path` in one step, so it can't run before its code and Disabled state are set. `disabled` is ignored for ModuleScript."
+    )]
+    async fn create_script(
+        &self,
+        #[tool(param)] script_class: String,
+        #[tool(param)] parent_path: String,
+        #[tool(param)] name: String,
+        #[tool(param)] source: String,
+        #[tool(param)] disabled: bool,
+    ) -> Result<CallToolResult, McpError> {
+        if !SCRIPT_CLASSES.contains(&script_class.as_str()) {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'script_class' must be one of {}, got \"{}\".",
+                SCRIPT_CLASSES.join(", "),
+                script_class
+            ))]));
+        }
+        if let Err(message) =
+            validate_instance_path(&parent_path).and_then(|()| validate_instance_name(&name))
+        {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::CreateScript {
+            script_class,
+            parent_path,
+            name,
+            source,
+            disabled,
+        })
+        .await
+    }
+    #[tool(
+        description = "Lists every instance a dot-separated `path` could mean when siblings share names (path-based tools act on the first match only). Each match has its full name, class, `sibling_index` (1-based among same-named siblings along the path) and a Studio debug id. An empty list means nothing matches."
+    )]
+    async fn resolve_path(&self, #[tool(param)] path: String) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_instance_path(&path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::ResolvePath {
+            path,
+            limit: MAX_RESOLVE_MATCHES,
+        })
+        .await
+    }
+    #[tool(
+        description = "Compares `before`, a tree previously returned by export_tree (the whole result or its `tree`), with the current subtree at `after_path`, and returns JSON {\"added\", \"removed\", \"changed\", \"truncated\"}: instance paths that appeared or disappeared, and {path, property, before, after} for exported properties that differ. Siblings are paired by name and class."
+    )]
+    async fn diff_trees(
+        &self,
+        #[tool(param)] before: serde_json::Value,
+        #[tool(param)] after_path: String,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_instance_path(&after_path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let before = before.get("tree").unwrap_or(&before);
+        if !before
+            .get("properties")
+            .is_some_and(serde_json::Value::is_object)
+        {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'before' must be a tree returned by export_tree.",
+            )]));
+        }
+        let export = self
+            .generic_tool_run(ToolArgumentValues::ExportTree {
+                root_path: after_path,
+                max_depth: MAX_EXPORT_DEPTH,
+                max_nodes: MAX_EXPORT_NODES,
+            })
+            .await?;
+        if export.is_error == Some(true) {
+            return Ok(export);
+        }
+        let after = first_json_content(&export).ok_or_else(|| {
+            McpError::internal_error("export_tree returned no readable tree", None)
+        })?;
+        let mut diff = tree_diff::diff_trees(before, &after["tree"], MAX_DIFF_CHANGES);
+        if after["truncated"].as_bool() == Some(true) {
+            // Instances past the export limits would otherwise read as removed.
+            diff["export_truncated"] = serde_json::Value::Bool(true);
+        }
+        Ok(CallToolResult::success(vec![Content::text(
+            diff.to_string(),
+        )]))
+    }
+    #[tool(
+        description = "Reads properties of many instances in one round trip. Each request is {path, properties}; the result maps every path to {found, properties, errors}, with values tagged as in get_attributes. A missing instance or unreadable property only affects its own entry."
+    )]
+    async fn get_properties_bulk(
+        &self,
+        #[tool(param)] requests: Vec<PropertyRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if requests.is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'requests' must list at least one instance.",
+            )]));
+        }
+        if requests.len() > MAX_BULK_PATHS {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'requests' may list at most {} instances.",
+                MAX_BULK_PATHS
+            ))]));
+        }
+        if let Some(message) = requests
+            .iter()
+            .find_map(|request| validate_instance_path(&request.path).err())
+        {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if let Some(request) = requests
+            .iter()
+            .find(|request| request.properties.is_empty())
+        {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "The request for '{}' lists no properties.",
+                request.path
+            ))]));
+        }
+        let requests = requests
+            .into_iter()
+            .map(|request| (request.path, request.properties))
+            .collect();
+        self.generic_tool_run(ToolArgumentValues::GetPropertiesBulk { requests })
+            .await
+    }
+    #[tool(
+        description = "Searches the Creator Marketplace free models for `query` without inserting anything, returning up to `limit` candidates as JSON {asset_id, name, creator, thumbnail_url}. Pass a chosen asset_id to insert_model as its query."
+    )]
+    async fn search_models(
+        &self,
+        #[tool(param)] query: String,
+        #[tool(param)] limit: u32,
+    ) -> Result<CallToolResult, McpError> {
+        if query.trim().is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'query' must not be empty.",
+            )]));
+        }
+        let limit = limit.clamp(1, MAX_SEARCH_RESULTS);
+        self.generic_tool_run(ToolArgumentValues::SearchModels { query, limit })
+            .await
+    }
+    #[tool(
+        description = "Sets Anchored on every BasePart in `instance_paths`; a Model or Folder path applies to all BaseParts inside it. Returns per-path status as JSON."
+    )]
+    async fn set_anchored(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] anchored: bool,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_bulk_paths(&instance_paths, 1) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::SetAnchored {
+            instance_paths,
+            anchored,
+        })
+        .await
+    }
+    #[tool(
+        description = "Welds the BaseParts in `instance_paths` together: each part after the first gets a WeldConstraint to the first, so they move as one assembly. Returns per-path status as JSON."
+    )]
+    async fn weld_instances(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_bulk_paths(&instance_paths, 2) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::WeldInstances { instance_paths })
+            .await
+    }
+    #[tool(
+        description = "Puts every BasePart in `instance_paths` (or inside a Model/Folder path) into collision group `group`. The group must already be registered unless `create_if_missing` is true. Returns per-path status as JSON."
+    )]
+    async fn set_collision_group(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] group: String,
+        #[tool(param)] create_if_missing: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_bulk_paths(&instance_paths, 1) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if group.trim().is_empty() || group.chars().any(char::is_control) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'group' must be a non-empty name without control characters.",
+            )]));
+        }
+        self.generic_tool_run(ToolArgumentValues::SetCollisionGroup {
+            instance_paths,
+            group,
+            create_if_missing: create_if_missing.unwrap_or(false),
+        })
+        .await
+    }
+    #[tool(
+        description = "Sets CustomPhysicalProperties on every BasePart in `instance_paths` (or inside a Model/Folder path). `props` is {\"density\", \"friction\", \"elasticity\"} with optional \"friction_weight\" and \"elasticity_weight\" (default 1); null restores the material's defaults. Returns per-path status as JSON."
+    )]
+    async fn set_physical_properties(
+        &self,
+        #[tool(param)] instance_paths: Vec<String>,
+        #[tool(param)] props: serde_json::Value,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_bulk_paths(&instance_paths, 1) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        let properties = match encode_physical_properties(&props) {
+            Ok(properties) => properties,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        self.generic_tool_run(ToolArgumentValues::SetPhysicalProperties {
+            instance_paths,
+            properties,
+        })
+        .await
+    }
+    #[tool(
+        description = "Deletes every instance whose MCPTag attribute equals `tag` (set through create_instance's `tag`), returning how many were removed. Only available when the server was started with --allow-dangerous."
+    )]
+    async fn delete_tagged(&self, #[tool(param)] tag: String) -> Result<CallToolResult, McpError> {
+        if !self.options.allow_dangerous {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "delete_tagged is disabled; start the server with --allow-dangerous to enable it.",
+            )]));
+        }
+        if let Err(message) = validate_created_tag(&tag) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::DeleteTagged { tag })
+            .await
+    }
+    #[tool(
+        description = "Reads a world-physics property of Workspace. `property_name` must be one of: Gravity, FallenPartsDestroyHeight, AirDensity, GlobalWind, StreamingEnabled, TouchesUseCollisionGroups."
+    )]
+    async fn get_workspace_property(
+        &self,
+        #[tool(param)] property_name: String,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = workspace_property_kind(&property_name) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::GetWorkspaceProperty { property_name })
+            .await
+    }
+    #[tool(
+        description = "Sets a world-physics property of Workspace. `property_name` must be one of: Gravity, FallenPartsDestroyHeight, AirDensity (numbers), GlobalWind (a tagged {\"$type\":\"Vector3\",\"x\",\"y\",\"z\"}), StreamingEnabled, TouchesUseCollisionGroups (booleans)."
+    )]
+    async fn set_workspace_property(
+        &self,
+        #[tool(param)] property_name: String,
+        #[tool(param)] value: serde_json::Value,
+    ) -> Result<CallToolResult, McpError> {
+        let kind = match workspace_property_kind(&property_name) {
+            Ok(kind) => kind,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        let value = match kind
+            .check(&property_name, &value)
+            .and_then(|()| luau::from_json(&value))
+        {
+            Ok(value) => value,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        self.generic_tool_run(ToolArgumentValues::SetWorkspaceProperty {
+            property_name,
+            value,
+        })
+        .await
+    }
+    #[tool(
+        description = "Captures the Studio selection and editor camera as a JSON token ({\"selection\": [paths], \"camera\": {\"cframe\", \"focus\", \"field_of_view\"}}). Pass it unchanged to restore_view to put the user's view back after exploring."
+    )]
+    async fn snapshot_view(&self) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::SnapshotView {})
+            .await
+    }
+    #[tool(
+        description = "Restores a selection and camera captured by snapshot_view. `token` is the JSON snapshot_view returned. Instances that no longer exist are left out of the selection."
+    )]
+    async fn restore_view(
+        &self,
+        #[tool(param)] token: serde_json::Value,
+    ) -> Result<CallToolResult, McpError> {
+        let snapshot: ViewSnapshot = match serde_json::from_value(token) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "'token' is not a snapshot_view token: {}",
+                    e
+                ))]))
+            }
+        };
+        if let Err(message) = snapshot.validate() {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run(ToolArgumentValues::RestoreView {
+            selection: snapshot.selection,
+            camera: snapshot.camera,
+        })
+        .await
+    }
+    #[tool(
+        description = "Reads the named properties of the instance at `instance_path` and returns JSON {\"instance_path\", \"properties\", \"errors\"}. `properties` has every requested name, with values tagged as in get_attributes; a property the instance doesn't have is null and `errors` says why. `priority` (\"low\", \"normal\" (default) or \"high\") orders it among queued tasks."
+    )]
+    async fn get_instance_properties(
+        &self,
+        #[tool(param)] instance_path: String,
+        #[tool(param)] property_names: Vec<String>,
+        #[tool(param)] priority: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let priority = match TaskPriority::for_call(priority.as_deref()) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        if let Err(message) = validate_instance_path(&instance_path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if property_names.is_empty() || property_names.iter().any(|name| name.trim().is_empty()) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'property_names' must list at least one property, and no name may be empty.",
+            )]));
+        }
+        let args = ToolArgumentValues::GetInstanceProperties {
+            instance_path,
+            property_names: property_names.clone(),
+        };
+        let result = self
+            .generic_tool_run_with_timeout(args, None, priority, None)
+            .await?;
+        if result.is_error == Some(true) {
+            return Ok(result);
+        }
+        let Some(mut data) = first_json_content(&result) else {
+            return Ok(result);
+        };
+        // The plugin can't send nil table values, so unreadable properties come back absent.
+        let read = data["properties"].as_object().cloned().unwrap_or_default();
+        let properties: serde_json::Map<String, serde_json::Value> = property_names
+            .into_iter()
+            .map(|name| {
+                let value = read.get(&name).cloned().unwrap_or(serde_json::Value::Null);
+                (name, value)
+            })
+            .collect();
+        data["properties"] = serde_json::Value::Object(properties);
+        if !data["errors"].is_object() {
+            // An empty Luau table encodes as `[]`.
+            data["errors"] = serde_json::json!({});
+        }
+        let mut content = vec![Content::text(data.to_string())];
+        content.extend(result.content.into_iter().skip(1));
+        Ok(CallToolResult::success(content))
+    }
+    #[tool(
+        description = "Runs several tasks in Studio in one round trip (at most 50), in order, as one undo step. Each step is a tool call as a client would make it, e.g. {\"name\": \"get_attributes\", \"arguments\": {\"instance_path\": \"Workspace.Part\"}}, and is checked and encoded exactly as that tool is; tools answered without Studio, batch itself, undo, redo and set_waypoint can't be steps. `stop_on_error` (default true) skips the steps after the first failure. Returns JSON with each step's `tool`, `is_error` and `text`, and is an error result if any step failed. `priority` (\"low\", \"normal\" (default) or \"high\") orders it among queued tasks. `idempotency_key` makes a repeat of the call with the same key (within 10 minutes) return the first successful result instead of running again."
+    )]
+    async fn batch(
+        &self,
+        #[tool(param)] steps: Vec<serde_json::Value>,
+        #[tool(param)] stop_on_error: Option<bool>,
+        #[tool(param)] timeout_seconds: Option<u32>,
+        #[tool(param)] priority: Option<String>,
+        #[tool(param)] idempotency_key: Option<String>,
+    ) -> Result<CallToolResult, McpError> {
+        let priority = match TaskPriority::for_call(priority.as_deref()) {
+            Ok(priority) => priority,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        if steps.is_empty() || steps.len() > MAX_BATCH_STEPS {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "'steps' must list between 1 and {} tasks.",
+                MAX_BATCH_STEPS
+            ))]));
+        }
+        if timeout_seconds == Some(0) {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'timeout_seconds' must be at least 1.",
+            )]));
+        }
+        let mut parsed = Vec::with_capacity(steps.len());
+        for (index, step) in steps.into_iter().enumerate() {
+            let step = match serde_json::from_value::<CallToolRequestParam>(step) {
+                Ok(step) => step,
+                Err(e) => {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Step {}: not a tool call: {}",
+                        index + 1,
+                        e
+                    ))]))
+                }
+            };
+            let rejection = match step.name.as_ref() {
+                "batch" => Some("batches can't be nested.".to_string()),
+                "undo" | "redo" | "set_waypoint" => Some("undo, redo and set_waypoint can't run inside a batch, which is itself one undo step.".to_string()),
+                "execute_discovered_luau_tool" if step.arguments.as_ref().is_some_and(|arguments| arguments.contains_key("source")) => {
+                    Some("a tool's 'source' can't be supplied; the server ships it from the tool's file.".to_string())
+                }
+                _ => None,
+            };
+            let built = match rejection {
+                Some(message) => Err(message),
+                None => self.batch_step(step).await,
+            };
+            match built {
+                Ok(task) => parsed.push(task),
+                Err(message) => {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Step {}: {}",
+                        index + 1,
+                        message
+                    ))]))
+                }
+            }
+        }
+        if let Some(Err(message)) = idempotency_key.as_deref().map(validate_idempotency_key) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        self.generic_tool_run_with_timeout(
+            ToolArgumentValues::Batch {
+                steps: parsed,
+                stop_on_error: stop_on_error.unwrap_or(true),
+            },
+            timeout_seconds,
+            priority,
+            idempotency_key,
+        )
+        .await
+    }
+    #[tool(
+        description = "Sets properties of the instance at `instance_path` from a JSON object of name -> value, and returns JSON {\"instance_path\", \"succeeded_properties\", \"failed_properties\"} (name -> reason). Values: numbers, booleans and strings as-is (\"Enum.Material.Neon\" is resolved to the enum item); {\"x\",\"y\",\"z\"} becomes a Vector3, {\"x\",\"y\"} a Vector2 and {\"r\",\"g\",\"b\"} (0-1) a Color3; other datatypes use the {\"$type\": ..} tags accepted by set_attributes. `Parent` takes an instance path."
+    )]
+    async fn set_instance_properties(
+        &self,
+        #[tool(param)] instance_path: String,
+        #[tool(param)] properties: serde_json::Value,
+    ) -> Result<CallToolResult, McpError> {
+        let mut errors = Vec::new();
+        if let Err(message) = validate_instance_path(&instance_path) {
+            errors.push(ValidationError::new("instance_path", message));
+        }
+        match properties.as_object() {
+            Some(map) if !map.is_empty() => {
+                for (name, value) in map {
+                    if name.trim().is_empty() {
+                        errors.push(ValidationError::new(
+                            "properties",
+                            "Property names must not be empty.",
+                        ));
+                    } else if let Err(message) = luau::property_value(value) {
+                        errors.push(ValidationError::new(
+                            format!("properties.{}", name),
+                            message,
+                        ));
+                    }
+                }
+            }
+            _ => errors.push(ValidationError::new(
+                "properties",
+                "'properties' must be a JSON object with at least one property.",
+            )),
+        }
+        if !errors.is_empty() {
+            return Ok(validation_error_result(&errors));
+        }
+        let mut result = self
+            .generic_tool_run(ToolArgumentValues::SetInstanceProperties {
+                instance_path,
+                properties,
+            })
+            .await?;
+        // An empty Luau table encodes as `[]`; a partial failure still carries the JSON, so fix both.
+        if let Some(mut data) = first_json_content(&result) {
+            if data.get("succeeded_properties").is_some() && !data["failed_properties"].is_object()
+            {
+                data["failed_properties"] = serde_json::json!({});
+                result.content[0] = Content::text(data.to_string());
+            }
+        }
+        Ok(result)
+    }
+    #[tool(
+        description = "Undoes the last `steps` (default 1, at most 50) changes in Studio's undo history. Every mutating tool call is recorded as one step named \"StudioMCP - <Tool>\" (a batch is one step), so this reverts the most recent calls, or the user's own edits if they came later. Returns the names of the undone steps."
+    )]
+    async fn undo(&self, #[tool(param)] steps: Option<u32>) -> Result<CallToolResult, McpError> {
+        let steps = match history_steps(steps) {
+            Ok(steps) => steps,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        self.generic_tool_run(ToolArgumentValues::Undo { steps })
+            .await
+    }
+    #[tool(
+        description = "Redoes the last `steps` (default 1, at most 50) changes that undo reverted. Returns the names of the redone steps."
+    )]
+    async fn redo(&self, #[tool(param)] steps: Option<u32>) -> Result<CallToolResult, McpError> {
+        let steps = match history_steps(steps) {
+            Ok(steps) => steps,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        self.generic_tool_run(ToolArgumentValues::Redo { steps })
+            .await
+    }
+    #[tool(
+        description = "Records a named waypoint in Studio's undo history, closing off any edits made since the last one (e.g. by the user) as their own step so a later undo doesn't merge them with the next tool call."
+    )]
+    async fn set_waypoint(&self, #[tool(param)] name: String) -> Result<CallToolResult, McpError> {
+        if name.trim().is_empty() {
+            return Ok(CallToolResult::error(vec![Content::text(
+                "'name' must not be empty.",
+            )]));
+        }
+        self.generic_tool_run(ToolArgumentValues::SetWaypoint { name })
+            .await
+    }
+    #[tool(
+        description = "Destroys the instance at `instance_path` and returns JSON {\"instance_path\", \"deleted\"} with how many instances were removed, itself included. An instance with children is refused unless `recursive` is true, which deletes it with all of its descendants. Fails if nothing is at the path, and never deletes the DataModel, a service or Terrain. Revert with undo."
+    )]
+    async fn delete_instance(
+        &self,
+        #[tool(param)] instance_path: String,
+        #[tool(param)] recursive: Option<bool>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(message) = validate_instance_path(&instance_path) {
+            return Ok(CallToolResult::error(vec![Content::text(message)]));
+        }
+        if names_protected_instance(&instance_path) {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Refusing to delete '{}': the DataModel, services and Terrain can't be deleted.",
+                instance_path
+            ))]));
+        }
+        self.generic_tool_run(ToolArgumentValues::DeleteInstance {
+            instance_path,
+            recursive: recursive.unwrap_or(false),
+        })
+        .await
+    }
+    #[tool(
+        description = "Returns PlaceId, GameId, place name, creator and PlaceVersion of the open place as JSON. An unpublished place reports null ids with a note instead of failing."
+    )]
+    async fn get_place_info(&self) -> Result<CallToolResult, McpError> {
+        self.generic_tool_run(ToolArgumentValues::GetPlaceInfo {})
+            .await
+    }
+}
+
+/// Body the plugin posts back for a task: a `CallToolResult` plus extras that
+/// have no slot in the MCP result type.
+#[derive(rmcp::serde::Deserialize, Debug)]
+pub struct PluginTaskResult {
+    #[serde(flatten)]
+    pub result: CallToolResult,
+    /// Non-fatal notices (deprecated APIs, clamped values) from a run that still succeeded.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+// Warnings travel to the caller as a trailing text block so clients that only
+// read `content` still see them without the call being treated as failed.
+fn format_plugin_warnings(warnings: &[String]) -> String {
+    let lines: Vec<String> = warnings.iter().map(|w| format!("- {}", w)).collect();
+    format!("Warnings:\n{}", lines.join("\n"))
+}
+
+fn parse_plugin_task_result(
+    body: &str,
+    lenient_json: bool,
+    task_id: Uuid,
+) -> serde_json::Result<PluginTaskResult> {
+    let strict_err = match rmcp::serde_json::from_str::<PluginTaskResult>(body) {
+        Ok(parsed) => return Ok(parsed),
+        Err(e) => e,
+    };
+    if !lenient_json {
+        return Err(strict_err);
+    }
+    match json_repair::repair_json(body) {
+        Some(repaired) => {
+            let parsed = rmcp::serde_json::from_str::<PluginTaskResult>(&repaired)?;
+            info!(target: "mcp_server", task_id=%task_id, "Applied JSON repair to plugin result body.");
+            Ok(parsed)
+        }
+        None => Err(strict_err),
+    }
+}
+
+// Compares in time that depends only on the lengths, so a wrong token can't be found byte by byte.
+fn tokens_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Middleware for the plugin routes: with `--auth-token` set, answers 401 to any request without
+/// the matching bearer token before it reaches a handler. Without a token every request passes.
+pub async fn require_auth_token(
+    State(axum_state): State<AxumSharedState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if let Some(expected) = &axum_state.auth_token {
+        let given = request
+            .headers()
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        if !given.is_some_and(|given| tokens_match(given.as_bytes(), expected.as_bytes())) {
+            warn!(target: "mcp_server", path = %request.uri().path(), "Rejected request without a valid auth token.");
+            return (StatusCode::UNAUTHORIZED, "Missing or invalid auth token").into_response();
+        }
+    }
+    next.run(request).await
+}
+
+// The first characters of a result body, enough to recognise what the plugin sent.
+fn body_snippet(body: &str) -> String {
+    const SNIPPET_CHARS: usize = 200;
+    let mut snippet: String = body.chars().take(SNIPPET_CHARS).collect();
+    if body.chars().nth(SNIPPET_CHARS).is_some() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Parses a result the plugin posted for `task_id` and hands it to the `StateManager`. Shared by
+/// the long-poll and WebSocket transports so both treat results the same way.
+async fn submit_plugin_result(
+    axum_state: &AxumSharedState,
+    task_id: Uuid,
+    body: &str,
+) -> std::result::Result<(), (StatusCode, &'static str)> {
+    let PluginTaskResult {
+        mut result,
+        warnings,
+    } = match parse_plugin_task_result(body, axum_state.lenient_json, task_id) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!("Failed to parse result body: {}", e);
+            let message = format!(
+                "Malformed plugin response: {} (body starts: {:?})",
+                e,
+                body_snippet(body)
+            );
+            let (accepted_tx, accepted_rx) = oneshot::channel();
+            let cmd = StateManagerCommand::FailTask {
+                task_id,
+                message,
+                accepted_tx,
+            };
+            if axum_state.sm_command_tx.send(cmd).await.is_ok() {
+                let _ = accepted_rx.await;
+            }
+            return Err((StatusCode::BAD_REQUEST, "Invalid result JSON"));
+        }
+    };
+    if !warnings.is_empty() {
+        for warning in &warnings {
+            warn!(target: "mcp_server", task_id=%task_id, warning=%warning, "Plugin reported a warning.");
+        }
+        result
+            .content
+            .push(Content::text(format_plugin_warnings(&warnings)));
+    }
+    let (accepted_tx, accepted_rx) = oneshot::channel();
+    let cmd = StateManagerCommand::SubmitTaskResult {
+        task_id,
+        result,
+        response_bytes: body.len(),
+        accepted_tx,
+    };
+    if axum_state.sm_command_tx.send(cmd).await.is_err() {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, ""));
+    }
+    match accepted_rx.await {
+        Ok(true) => Ok(()),
+        // Tells the plugin its result was dropped: the task timed out, was cancelled, or never existed.
+        Ok(false) => Err((StatusCode::NOT_FOUND, "Unknown or expired task")),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "")),
+    }
+}
+
+// The 426 answer for a plugin whose `X-MCP-Plugin-Version` is outside the supported range, or
+// `None` when it may go on.
+fn reject_incompatible_plugin(headers: &HeaderMap) -> Option<axum::response::Response> {
+    let Some(value) = headers.get(PLUGIN_VERSION_HEADER) else {
+        LEGACY_PLUGIN_WARNING.call_once(|| {
+            warn!(target: "mcp_server", "A plugin polled without an {} header; it predates version checks and may misread tasks. Reinstall MCPStudioPlugin.rbxm from this build.", PLUGIN_VERSION_HEADER);
+        });
+        return None;
+    };
+    let given = value.to_str().unwrap_or_default().trim();
+    if given.parse::<u32>().is_ok_and(|version| {
+        (MIN_PLUGIN_PROTOCOL_VERSION..=PLUGIN_PROTOCOL_VERSION).contains(&version)
+    }) {
+        return None;
+    }
+    warn!(target: "mcp_server", plugin_version = given, "Rejected a plugin with an incompatible protocol version.");
+    let message = format!(
+        "The installed MCPStudioPlugin speaks protocol version {}, but this server supports versions {} to {}. Reinstall the plugin from this server build (run rbx-studio-mcp once without arguments, or copy MCPStudioPlugin.rbxm into Studio's Plugins folder) and restart Studio.",
+        given, MIN_PLUGIN_PROTOCOL_VERSION, PLUGIN_PROTOCOL_VERSION
+    );
+    Some((StatusCode::UPGRADE_REQUIRED, message).into_response())
+}
+
+/// Middleware for the routes the Studio plugin calls: answers 426 to a plugin whose protocol
+/// version this server can't serve, before it reaches a handler.
+pub async fn require_compatible_plugin(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    if let Some(rejection) = reject_incompatible_plugin(request.headers()) {
+        return rejection;
+    }
+    next.run(request).await
+}
+
+pub async fn unified_handler(
+    State(axum_state): State<AxumSharedState>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    if let Some(task_id_header) = headers.get("X-MCP-Task-ID") {
+        let task_id_str = task_id_header.to_str().unwrap_or_default();
+        if let Ok(task_id) = Uuid::parse_str(task_id_str) {
+            return match submit_plugin_result(&axum_state, task_id, &body).await {
+                Ok(()) => (StatusCode::NO_CONTENT, "").into_response(),
+                Err(rejection) => rejection.into_response(),
+            };
+        } else {
+            (StatusCode::BAD_REQUEST, "Invalid X-MCP-Task-ID header").into_response()
+        }
+    } else {
+        // This is a poll for a new task.
+        let (response_tx, response_rx) = oneshot::channel();
+        let session = headers
+            .get("X-MCP-Plugin-Session")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let cmd = StateManagerCommand::PollForTask {
+            session,
+            response_tx,
+        };
+
+        if axum_state.sm_command_tx.send(cmd).await.is_err() {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+        }
+
+        let poll_started = Instant::now();
+        let outcome = tokio::time::timeout(LONG_POLL_DURATION, response_rx).await;
+        let _ = axum_state
+            .sm_command_tx
+            .send(StateManagerCommand::RecordPollWait {
+                waited: poll_started.elapsed(),
+            })
+            .await;
+        match outcome {
+            Ok(Ok(PollOutcome::Task(task))) => {
+                let luau_string = task.to_luau_string();
+                (
+                    StatusCode::OK,
+                    [("Content-Type", "application/luau")],
+                    luau_string,
+                )
+                    .into_response()
+            }
+            Ok(Ok(PollOutcome::Paused)) => (
+                StatusCode::NO_CONTENT,
+                [(DISPATCH_PAUSED_HEADER, "true")],
+                "",
+            )
+                .into_response(),
+            _ => (StatusCode::NO_CONTENT, "").into_response(),
+        }
+    }
+}
+
+/// `POST /mcp/stream`: a chunk of output for the running task named by `X-MCP-Task-ID`. The
+/// chunks are kept in order and added to the task's result when it arrives, so a long script's
+/// progress isn't lost; `list_pending_tasks` shows how much has streamed meanwhile.
+pub async fn stream_output_handler(
+    State(axum_state): State<AxumSharedState>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    let Some(task_id) = headers
+        .get("X-MCP-Task-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| Uuid::parse_str(v).ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "Invalid X-MCP-Task-ID header").into_response();
+    };
+    let (accepted_tx, accepted_rx) = oneshot::channel();
+    let cmd = StateManagerCommand::AppendTaskOutput {
+        task_id,
+        chunk: body,
+        accepted_tx,
+    };
+    if axum_state.sm_command_tx.send(cmd).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+    }
+    match accepted_rx.await {
+        Ok(true) => (StatusCode::NO_CONTENT, "").into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Unknown or expired task").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+    }
+}
+
+/// `GET /ws`: an alternative to long-polling `/mcp`. The plugin connects once and tasks are pushed
+/// to it as the same Luau chunks `/mcp` returns; it answers each with a text frame holding the task
+/// id, a newline, and the result JSON. One task is in flight per connection, as with long-polling.
+/// The session comes from `X-MCP-Plugin-Session` or, for clients that can't set headers, `?session=`.
+pub async fn ws_handler(
+    State(axum_state): State<AxumSharedState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let session = headers
+        .get("X-MCP-Plugin-Session")
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .or_else(|| query.get("session").cloned());
+    ws.on_upgrade(move |socket| serve_plugin_socket(axum_state, session, socket))
+}
+
+async fn serve_plugin_socket(
+    axum_state: AxumSharedState,
+    session: Option<String>,
+    mut socket: WebSocket,
+) {
+    info!(target: "mcp_server", session = session.as_deref().unwrap_or(DEFAULT_SESSION), "Plugin connected over WebSocket.");
+    let mut paused = false;
+    loop {
+        let (response_tx, response_rx) = oneshot::channel();
+        let cmd = StateManagerCommand::PollForTask {
+            session: session.clone(),
+            response_tx,
+        };
+        if axum_state.sm_command_tx.send(cmd).await.is_err() {
+            break;
+        }
+        // Re-poll every LONG_POLL_DURATION so the session keeps counting as recently seen.
+        let outcome = tokio::select! {
+            outcome = tokio::time::timeout(LONG_POLL_DURATION, response_rx) => outcome,
+            frame = socket.recv() => match frame {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        let task = match outcome {
+            Ok(Ok(PollOutcome::Task(task))) => task,
+            Ok(Ok(PollOutcome::Paused)) => {
+                if !paused {
+                    paused = true;
+                    if socket
+                        .send(Message::Text(WS_PAUSED_FRAME.into()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                tokio::time::sleep(WS_PAUSED_RETRY).await;
+                continue;
+            }
+            _ => continue,
+        };
+        if paused {
+            paused = false;
+            if socket
+                .send(Message::Text(WS_RESUMED_FRAME.into()))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        if socket
+            .send(Message::Text(task.to_luau_string().into()))
+            .await
+            .is_err()
+        {
+            // The task is already marked as picked up; it resolves through its execution timeout.
+            break;
+        }
+        if !receive_socket_result(&axum_state, &mut socket).await {
+            break;
+        }
+    }
+    info!(target: "mcp_server", session = session.as_deref().unwrap_or(DEFAULT_SESSION), "Plugin WebSocket closed.");
+}
+
+// Waits for the plugin's answer to the task just sent. Returns false once the connection is gone.
+async fn receive_socket_result(axum_state: &AxumSharedState, socket: &mut WebSocket) -> bool {
+    let deadline = Instant::now() + MAX_TOOL_EXECUTION_TIMEOUT + WAIT_FOR_TIMEOUT_MARGIN;
+    loop {
+        let frame = match tokio::time::timeout_at(deadline, socket.recv()).await {
+            Ok(Some(Ok(frame))) => frame,
+            Ok(Some(Err(_)) | None) => return false,
+            Err(_) => {
+                warn!(target: "mcp_server", "No result over the plugin WebSocket in time; closing it so the plugin reconnects.");
+                return false;
+            }
+        };
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => return false,
+            _ => continue,
+        };
+        let Some((task_id, body)) = text.as_str().split_once('\n') else {
+            warn!(target: "mcp_server", "Ignoring a WebSocket frame without a task id line.");
+            continue;
+        };
+        let Ok(task_id) = Uuid::parse_str(task_id.trim()) else {
+            warn!(target: "mcp_server", "Ignoring a WebSocket frame with an invalid task id.");
+            continue;
+        };
+        if let Err((_, reason)) = submit_plugin_result(axum_state, task_id, body).await {
+            warn!(target: "mcp_server", task_id=%task_id, "Result over WebSocket was rejected: {}", reason);
+        }
+        return true;
+    }
+}
+
+/// `GET /tools/manifest`: the discovered tool names with a hash of each file, so the
+/// plugin can spot tools it doesn't bundle.
+pub async fn tools_manifest_handler(
+    State(axum_state): State<AxumSharedState>,
+) -> impl IntoResponse {
+    let discovered_luau_tools = axum_state.discovered_luau_tools.snapshot();
+    let mut tools: Vec<_> = discovered_luau_tools
+        .iter()
+        .map(|(name, tool)| serde_json::json!({ "name": name, "hash": tool.content_hash() }))
+        .collect();
+    tools.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Json(serde_json::json!({ "tools": tools }))
+}
+
+/// `GET /tools/bundle`: the source of every discovered tool keyed by name, so the plugin can
+/// run the tools on disk instead of the copies built into its rbxm. Like the rest of the plugin
+/// API this is only reachable from localhost.
+pub async fn tools_bundle_handler(State(axum_state): State<AxumSharedState>) -> impl IntoResponse {
+    let discovered_luau_tools = axum_state.discovered_luau_tools.snapshot();
+    let mut names: Vec<_> = discovered_luau_tools.keys().collect();
+    names.sort();
+    let mut tools = serde_json::Map::new();
+    for name in names {
+        let tool = &discovered_luau_tools[name];
+        match tokio::fs::read_to_string(&tool.file_path).await {
+            Ok(source) => {
+                tools.insert(
+                    name.clone(),
+                    serde_json::json!({ "hash": tool.content_hash(), "readonly": tool.readonly, "source": source }),
+                );
+            }
+            Err(e) => tracing::warn!(
+                "Leaving '{}' out of the tool bundle: could not read {}: {}",
+                name,
+                tool.file_path.display(),
+                e
+            ),
+        }
+    }
+    Json(serde_json::json!({ "tools": tools }))
+}
+
+/// `GET /tasks/{id}/status`: lets the plugin ask whether the server still wants a task's result,
+/// and if not, why (`{"status": "cancelled", "reason": "execution_timeout"}`).
+pub async fn task_status_handler(
+    State(axum_state): State<AxumSharedState>,
+    axum::extract::Path(task_id): axum::extract::Path<Uuid>,
+) -> impl IntoResponse {
+    let (response_tx, response_rx) = oneshot::channel();
+    let command = StateManagerCommand::GetTaskStatus {
+        task_id,
+        response_tx,
+    };
+    if axum_state.sm_command_tx.send(command).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+    }
+    match response_rx.await {
+        Ok(status) => Json(status).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+    }
+}
+
+/// `GET /healthz`: always 200 while the server is up, with `ServerStats` as the body so callers
+/// can apply their own thresholds (e.g. treat `client_waiting: false` for a while as disconnected).
+pub async fn healthz_handler(State(axum_state): State<AxumSharedState>) -> impl IntoResponse {
+    let (response_tx, response_rx) = oneshot::channel();
+    if axum_state
+        .sm_command_tx
+        .send(StateManagerCommand::GetStats { response_tx })
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+    }
+    match response_rx.await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+    }
+}
+
+/// `GET /metrics`: task counters, queue gauges and the plugin poll wait histogram in the
+/// Prometheus text exposition format.
+pub async fn metrics_handler(State(axum_state): State<AxumSharedState>) -> impl IntoResponse {
+    let (response_tx, response_rx) = oneshot::channel();
+    if axum_state
+        .sm_command_tx
+        .send(StateManagerCommand::GetMetrics { response_tx })
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response();
+    }
+    match response_rx.await {
+        Ok(snapshot) => (
+            StatusCode::OK,
+            [("Content-Type", "text/plain; version=0.0.4")],
+            metrics::render(&snapshot),
+        )
+            .into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+    }
+}
+
+#[derive(rmcp::serde::Deserialize, Debug)]
+pub struct DispatchToggle {
+    pub enabled: bool,
+}
+
+/// `POST /admin/dispatch` with `{"enabled": bool}`: the same switch as the `set_dispatch_enabled` tool,
+/// for use from outside the MCP session.
+pub async fn admin_dispatch_handler(
+    State(axum_state): State<AxumSharedState>,
+    Json(toggle): Json<DispatchToggle>,
+) -> impl IntoResponse {
+    match set_dispatch_enabled(&axum_state.sm_command_tx, toggle.enabled).await {
+        Ok(state) => Json(state).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+    }
+}
+
+/// The HTTP routes the plugin, health checks and scrapers use, as `main` serves them on the
+/// plugin port.
+pub fn plugin_router(axum_shared_state: AxumSharedState) -> axum::Router {
+    let plugin_routes = axum::Router::new()
+        .route("/mcp", post(unified_handler))
+        .route("/mcp/stream", post(stream_output_handler))
+        .route("/tools/manifest", get(tools_manifest_handler))
+        .route("/tools/bundle", get(tools_bundle_handler))
+        // The plugin asks whether a long-running task was cancelled before sending its result.
+        .route("/tasks/{id}/status", get(task_status_handler))
+        // Optional push transport; `/mcp` long-polling keeps working alongside it.
+        .route("/ws", get(ws_handler))
+        .route_layer(axum::middleware::from_fn(require_compatible_plugin));
+    axum::Router::new()
+        .merge(plugin_routes)
+        .route("/admin/dispatch", post(admin_dispatch_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            axum_shared_state.clone(),
+            require_auth_token,
+        ))
+        // Added after the auth layer so readiness checks and scrapers work without the token.
+        .route("/healthz", get(healthz_handler))
+        .route("/metrics", get(metrics_handler))
+        .with_state(axum_shared_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_plugin::{MockPlugin, Poll};
+    use rmcp::model::ServerNotification;
+    use rmcp::service::{AtomicU32RequestIdProvider, PeerSinkMessage};
+
+    /// The server as `main` wires it: a real `StateManager` behind the plugin routes on a free
+    /// local port, for exercising the dispatch -> poll -> submit loop with `MockPlugin` as Studio.
+    struct TestServer {
+        sm_command_tx: mpsc::Sender<StateManagerCommand>,
+        state: AxumSharedState,
+        port: u16,
+    }
+
+    impl TestServer {
+        async fn start() -> Self {
+            Self::start_with(StateManager::new(), |_| {}).await
+        }
+
+        async fn start_with(
+            state_manager: StateManager,
+            configure: impl FnOnce(&mut AxumSharedState),
+        ) -> Self {
+            let (sm_command_tx, sm_command_rx) = mpsc::channel(100);
+            tokio::spawn(state_manager.run(sm_command_rx));
+            let mut state = AxumSharedState {
+                sm_command_tx: sm_command_tx.clone(),
+                lenient_json: false,
+                discovered_luau_tools: ToolRegistry::new(HashMap::new()),
+                auth_token: None,
+            };
+            configure(&mut state);
+            let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+                .await
+                .unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let app = plugin_router(state.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            Self {
+                sm_command_tx,
+                state,
+                port,
+            }
+        }
+
+        fn plugin(&self) -> MockPlugin {
+            MockPlugin::new(
+                self.port,
+                self.state.auth_token.as_deref().map(String::from),
+            )
+            .unwrap()
+        }
+
+        /// An MCP-side server sharing this one's `StateManager`, as one client connection sees it.
+        fn mcp_server(&self, options: ServerOptions) -> RBXStudioServer {
+            RBXStudioServer::new(
+                self.sm_command_tx.clone(),
+                self.state.discovered_luau_tools.clone(),
+                options,
+            )
+        }
+    }
+
+    // Polls once as `plugin` and panics unless a task came back.
+    async fn poll_task(plugin: &MockPlugin) -> (Uuid, String, String) {
+        match plugin.poll().await.unwrap() {
+            Poll::Task {
+                task_id,
+                tool,
+                chunk,
+            } => (task_id, tool, chunk),
+            other => panic!("expected a task, got {:?}", other),
+        }
+    }
+
+    fn result_text(result: &CallToolResult) -> String {
+        result
+            .content
+            .iter()
+            .filter_map(|content| match &content.raw {
+                RawContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[tokio::test]
+    async fn mock_plugin_completes_a_task_end_to_end() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+
+        let plugin = server.plugin();
+        let (task_id, tool, chunk) = poll_task(&plugin).await;
+        assert_eq!(tool, "GetPlaceInfo");
+        assert!(chunk.contains(&task_id.to_string()));
+        let status = plugin
+            .submit(
+                task_id,
+                &CallToolResult::success(vec![Content::text("Place 1")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+
+        let result = call.await.unwrap().unwrap();
+        assert_eq!(result_text(&result), "Place 1");
+        // The task is gone once resolved, so a second submit is refused.
+        let status = plugin
+            .submit(task_id, &CallToolResult::success(vec![]))
+            .await
+            .unwrap();
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    }
+
+    fn test_peer() -> (
+        Peer<RoleServer>,
+        mpsc::Receiver<PeerSinkMessage<RoleServer>>,
+    ) {
+        let client = InitializeRequestParam {
+            protocol_version: ProtocolVersion::default(),
+            capabilities: ClientCapabilities::default(),
+            client_info: Implementation::from_build_env(),
+        };
+        Peer::new(Arc::new(AtomicU32RequestIdProvider::default()), client)
+    }
+
+    fn completion(text: &str) -> TaskCompletion {
+        TaskCompletion {
+            result: CallToolResult::success(vec![Content::text(text)]),
+            queue_wait: None,
+            response_bytes: text.len(),
+        }
+    }
+
+    #[test]
+    fn progress_token_comes_from_the_calls_meta() {
+        assert!(
+            ProgressReporter::for_current_call().is_none(),
+            "no reporter outside a tool call"
+        );
+        let (peer, _outbound) = test_peer();
+        for (meta, expected) in [
+            (serde_json::json!({ "progressToken": "abc" }), Some("abc")),
+            (serde_json::json!({ "progressToken": 7 }), Some("7")),
+            (serde_json::json!({ "progressToken": -1 }), None),
+            (serde_json::json!({}), None),
+        ] {
+            let call = CallContext {
+                peer: peer.clone(),
+                meta: meta.as_object().unwrap().clone(),
+            };
+            let token = CALL_CONTEXT.sync_scope(call, || {
+                ProgressReporter::for_current_call().map(|reporter| reporter.token.to_string())
+            });
+            assert_eq!(token.as_deref(), expected, "meta {}", meta);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_slow_task_reports_progress_every_interval() {
+        let (peer, mut outbound) = test_peer();
+        let progress = ProgressReporter {
+            peer,
+            token: ProgressToken::String("slow".into()),
+            sent: Arc::new(AtomicU32::new(0)),
+        };
+        let notifications = tokio::spawn(async move {
+            let mut sent = Vec::new();
+            while let Some(message) = outbound.recv().await {
+                if let PeerSinkMessage::Notification(
+                    ServerNotification::ProgressNotification(notification),
+                    responder,
+                ) = message
+                {
+                    sent.push(notification.params.progress);
+                    let _ = responder.send(Ok(()));
+                }
+            }
+            sent
+        });
+        let (sm_command_tx, mut sm_command_rx) = mpsc::channel(4);
+        let plugin = tokio::spawn(async move {
+            let Some(StateManagerCommand::DispatchTask {
+                response_tx,
+                started_tx,
+                ..
+            }) = sm_command_rx.recv().await
+            else {
+                panic!("expected a dispatched task")
+            };
+            started_tx.send(()).unwrap();
+            tokio::time::sleep(PROGRESS_INTERVAL * 2 + Duration::from_secs(1)).await;
+            response_tx.send(Ok(completion("done"))).unwrap();
+        });
+
+        let (args, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let options = DispatchOptions {
+            progress: Some(progress),
+            ..DispatchOptions::default()
+        };
+        dispatch_and_wait(&sm_command_tx, args, options)
+            .await
+            .unwrap();
+        plugin.await.unwrap();
+
+        // Queued, picked up, then once per interval while it ran.
+        assert_eq!(notifications.await.unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    fn luau_for(args: ToolArgumentValues) -> String {
+        format_tool_argument_values_to_luau_string(&args)
+    }
+
+    #[test]
+    fn create_event_keeps_names_with_spaces_in_one_string() {
+        let args = ToolArgumentValues::CreateEvent {
+            class_name: "RemoteEvent".to_string(),
+            name: "Door Opened".to_string(),
+            parent_path: "ReplicatedStorage.Game Events".to_string(),
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"CreateEvent = { class_name = "RemoteEvent", name = "Door Opened", parent_path = "ReplicatedStorage.Game Events" }"#
+        );
+        let args = ToolArgumentValues::CreateEvent {
+            class_name: "BindableEvent".to_string(),
+            name: "Say \"hi\"".to_string(),
+            parent_path: "ServerStorage".to_string(),
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"CreateEvent = { class_name = "BindableEvent", name = "Say \"hi\"", parent_path = "ServerStorage" }"#
+        );
+    }
+
+    #[test]
+    fn instance_names_may_have_spaces_but_not_dots() {
+        assert!(validate_instance_name("Door Opened").is_ok());
+        assert!(validate_instance_name("Door.Opened").is_err());
+        assert!(validate_instance_name("  ").is_err());
+    }
+
+    // Log output written while the guard is held on this thread, as plain text without ANSI codes.
+    fn capture_logs() -> (tracing::subscriber::DefaultGuard, Arc<Mutex<Vec<u8>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || LogWriter(writer.clone()))
+            .finish();
+        (tracing::subscriber::set_default(subscriber), buffer)
+    }
+
+    struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogWriter {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn plugin_warnings_reach_the_caller_and_the_log() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let (task_id, _, _) = poll_task(&server.plugin()).await;
+
+        let (_guard, logs) = capture_logs();
+        let body = r#"{"content":[{"type":"text","text":"Place 1"}],"isError":false,"warnings":["Workspace.FilteringEnabled is deprecated"]}"#;
+        submit_plugin_result(&server.state, task_id, body)
+            .await
+            .unwrap();
+
+        let result = call.await.unwrap().unwrap();
+        assert_ne!(result.is_error, Some(true));
+        assert_eq!(
+            result_text(&result),
+            "Place 1\nWarnings:\n- Workspace.FilteringEnabled is deprecated"
+        );
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let line = logs
+            .lines()
+            .find(|line| line.contains("Plugin reported a warning."))
+            .expect("the warning is logged");
+        assert!(line.contains("WARN"), "{}", line);
+        assert!(line.contains(&format!("task_id={}", task_id)), "{}", line);
+        assert!(line.contains("FilteringEnabled is deprecated"), "{}", line);
+    }
+
+    #[test]
+    fn clone_instance_formats_optional_fields_as_nil_when_absent() {
+        let args = ToolArgumentValues::CloneInstance {
+            instance_path: "Workspace.Tree".to_string(),
+            new_parent_path: Some("Workspace.Forest".to_string()),
+            new_name: Some("Tree Copy".to_string()),
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"CloneInstance = { instance_path = "Workspace.Tree", new_parent_path = "Workspace.Forest", new_name = "Tree Copy" }"#
+        );
+        let args = ToolArgumentValues::CloneInstance {
+            instance_path: "Workspace.Tree".to_string(),
+            new_parent_path: None,
+            new_name: None,
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"CloneInstance = { instance_path = "Workspace.Tree", new_parent_path = nil, new_name = nil }"#
+        );
+    }
+
+    // Polls one task as `plugin` and answers it with `result`; returns what was polled.
+    async fn answer_next(plugin: &MockPlugin, result: CallToolResult) -> (Uuid, String, String) {
+        let polled = poll_task(plugin).await;
+        let status = plugin.submit(polled.0, &result).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+        polled
+    }
+
+    #[tokio::test]
+    async fn result_timing_appends_a_server_timing_block_when_enabled() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        for result_timing in [true, false] {
+            let mcp = server.mcp_server(ServerOptions {
+                result_timing,
+                ..ServerOptions::default()
+            });
+            let call = tokio::spawn(async move { mcp.get_place_info().await });
+            answer_next(
+                &plugin,
+                CallToolResult::success(vec![Content::text("Place 1")]),
+            )
+            .await;
+            let result = call.await.unwrap().unwrap();
+
+            assert_eq!(result.content.len(), if result_timing { 2 } else { 1 });
+            assert_eq!(
+                result.content[0].as_text().unwrap().text,
+                "Place 1",
+                "the plugin's content comes first, untouched"
+            );
+            if result_timing {
+                let block: serde_json::Value =
+                    serde_json::from_str(&result.content[1].as_text().unwrap().text).unwrap();
+                let timing = &block["server_timing"];
+                for field in [
+                    "dispatched_at_ms",
+                    "completed_at_ms",
+                    "queue_wait_ms",
+                    "total_ms",
+                    "request_bytes",
+                    "response_bytes",
+                ] {
+                    assert!(timing[field].is_u64(), "{} in {}", field, timing);
+                }
+                assert!(timing["request_bytes"].as_u64().unwrap() > 0);
+                assert!(timing["completed_at_ms"].as_u64() >= timing["dispatched_at_ms"].as_u64());
+            }
+        }
+    }
+
+    // A server with no StateManager behind it, for checks that must fail before anything is dispatched.
+    fn offline_server(options: ServerOptions) -> RBXStudioServer {
+        let (sm_command_tx, _) = mpsc::channel(1);
+        RBXStudioServer::new(sm_command_tx, ToolRegistry::new(HashMap::new()), options)
+    }
+
+    fn error_text(result: Result<CallToolResult, McpError>) -> String {
+        let result = result.expect("a tool error result, not a protocol error");
+        assert_eq!(result.is_error, Some(true), "{:?}", result);
+        result_text(&result)
+    }
+
+    #[test]
+    fn find_instances_formats_each_filter_combination() {
+        let find = |class_name: Option<&str>, name_pattern: Option<&str>| {
+            luau_for(ToolArgumentValues::FindInstances {
+                root_path: "Workspace.Map".to_string(),
+                class_name: class_name.map(String::from),
+                name_pattern: name_pattern.map(String::from),
+                limit: MAX_FIND_RESULTS,
+            })
+        };
+        assert_eq!(
+            find(Some("Part"), None),
+            r#"FindInstances = { root_path = "Workspace.Map", class_name = "Part", name_pattern = nil, limit = 200 }"#
+        );
+        assert_eq!(
+            find(None, Some("^Door%d+$")),
+            r#"FindInstances = { root_path = "Workspace.Map", class_name = nil, name_pattern = "^Door%d+$", limit = 200 }"#
+        );
+        assert_eq!(
+            find(Some("Model"), Some("\"quoted\"")),
+            r#"FindInstances = { root_path = "Workspace.Map", class_name = "Model", name_pattern = "\"quoted\"", limit = 200 }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn find_instances_requires_a_filter() {
+        let server = offline_server(ServerOptions::default());
+        let message = error_text(server.find_instances(None, None, None).await);
+        assert!(message.contains("at least one of"), "{}", message);
+    }
+
+    #[test]
+    fn set_attributes_formats_strings_numbers_and_bools() {
+        let attribute = |value: serde_json::Value| luau::from_json(&value).unwrap();
+        let args = ToolArgumentValues::SetAttributes {
+            instance_path: "Workspace.Crate".to_string(),
+            attributes: vec![
+                (
+                    "Owner".to_string(),
+                    attribute(serde_json::json!("Team \"Red\"")),
+                ),
+                ("Health".to_string(), attribute(serde_json::json!(87.5))),
+                ("Locked".to_string(), attribute(serde_json::json!(true))),
+            ],
+            clear: vec!["Stale".to_string()],
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"SetAttributes = { instance_path = "Workspace.Crate", attributes = { ["Owner"] = "Team \"Red\"", ["Health"] = 87.5, ["Locked"] = true }, clear = { "Stale" } }"#
+        );
+    }
+
+    #[test]
+    fn attribute_names_follow_roblox_rules() {
+        assert!(validate_attribute_name("Health_2").is_ok());
+        for name in ["", "RBXHealth", "Has Space", "Dash-ed"] {
+            assert!(validate_attribute_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_rejects_mutating_tools() {
+        let server = offline_server(ServerOptions {
+            read_only: true,
+            ..ServerOptions::default()
+        });
+        let message = error_text(
+            server
+                .create_remote_event("Opened".to_string(), "ReplicatedStorage".to_string())
+                .await,
+        );
+        assert!(
+            message.contains("The server is read-only: 'CreateEvent'"),
+            "{}",
+            message
+        );
+        // A batch is only as read-only as its steps.
+        assert!(!server.is_read_only_task(&ToolArgumentValues::Batch {
+            steps: vec![
+                ToolArgumentValues::GetPlaceInfo {},
+                ToolArgumentValues::DeleteTagged { tag: "x".into() },
+            ],
+            stop_on_error: true,
+        }));
+        // Read-only tools get past the check and on to dispatch, which fails here for lack of a StateManager.
+        assert!(server.get_place_info().await.is_err());
+    }
+
+    #[test]
+    fn get_place_info_formats_without_arguments() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::GetPlaceInfo {}),
+            "GetPlaceInfo = {}"
+        );
+        let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        assert!(task
+            .to_luau_string()
+            .contains(&format!("id = \"{}\"", task_id)));
+    }
+
+    #[test]
+    fn insert_model_formats_its_parent_and_position() {
+        let args = ToolArgumentValues::InsertModel {
+            query: "oak tree".to_string(),
+            parent_path: Some("Workspace.Forest".to_string()),
+            position: Some([10.0, 0.5, -3.0]),
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"InsertModel = { query = "oak tree", parent_path = "Workspace.Forest", position = Vector3.new(10, 0.5, -3) }"#
+        );
+        let args = ToolArgumentValues::InsertModel {
+            query: "1234".to_string(),
+            parent_path: None,
+            position: None,
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"InsertModel = { query = "1234", parent_path = nil, position = nil }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_model_rejects_a_malformed_parent_path() {
+        let server = offline_server(ServerOptions::default());
+        for parent_path in ["Workspace..Forest", ""] {
+            error_text(
+                server
+                    .insert_model(
+                        "tree".to_string(),
+                        Some(parent_path.to_string()),
+                        None,
+                        None,
+                    )
+                    .await,
+            );
+        }
+    }
+
+    // A `StateManager` running on its own, driven through its command channel.
+    fn start_state_manager(state_manager: StateManager) -> mpsc::Sender<StateManagerCommand> {
+        let (sm_command_tx, sm_command_rx) = mpsc::channel(100);
+        tokio::spawn(state_manager.run(sm_command_rx));
+        sm_command_tx
+    }
+
+    async fn send_poll(
+        sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+        session: &str,
+    ) -> oneshot::Receiver<PollOutcome> {
+        let (response_tx, response_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::PollForTask {
+                session: Some(session.to_string()),
+                response_tx,
+            })
+            .await
+            .unwrap();
+        response_rx
+    }
+
+    async fn send_task(
+        sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+        task: ToolArguments,
+        session: Option<&str>,
+    ) -> oneshot::Receiver<Result<TaskCompletion, McpError>> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let (started_tx, _) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::DispatchTask {
+                args: task,
+                session: session.map(String::from),
+                response_tx,
+                started_tx,
+            })
+            .await
+            .unwrap();
+        response_rx
+    }
+
+    async fn sessions_of(sm_command_tx: &mpsc::Sender<StateManagerCommand>) -> Vec<SessionInfo> {
+        let (response_tx, response_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::ListSessions { response_tx })
+            .await
+            .unwrap();
+        response_rx.await.unwrap()
+    }
+
+    fn polled_task_id(outcome: PollOutcome) -> Uuid {
+        match outcome {
+            PollOutcome::Task(task) => task.id.unwrap(),
+            PollOutcome::Paused => panic!("expected a task, dispatch is paused"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_reloaded_plugin_gets_the_tasks_and_the_old_session_is_dropped() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        // The old plugin's long-poll request dies with it when the plugin reloads.
+        drop(send_poll(&sm_command_tx, "before-reload").await);
+        let new_poll = send_poll(&sm_command_tx, "after-reload").await;
+
+        let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let _caller = send_task(&sm_command_tx, task, None).await;
+        assert_eq!(polled_task_id(new_poll.await.unwrap()), task_id);
+
+        let sessions = sessions_of(&sm_command_tx).await;
+        let old = sessions
+            .iter()
+            .find(|session| session.session_id == "before-reload")
+            .unwrap();
+        assert!(!old.waiting, "the stale waiter doesn't count");
+        // Keep the new session alive while the old one goes quiet for longer than the idle expiry.
+        tokio::time::advance(SESSION_IDLE_EXPIRY / 2).await;
+        drop(send_poll(&sm_command_tx, "after-reload").await);
+        tokio::time::advance(SESSION_IDLE_EXPIRY / 2).await;
+        let sessions = sessions_of(&sm_command_tx).await;
+        let ids: Vec<&str> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, ["after-reload"]);
+    }
+
+    fn create_instance_params(payload: serde_json::Value) -> CreateInstanceParams {
+        serde_json::from_value(payload).unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_instance_dispatches_a_valid_payload() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let params = create_instance_params(serde_json::json!({
+            "class_name": "Part",
+            "parent_path": "Workspace.Map",
+            "properties": { "Anchored": true, "Size": { "x": 4, "y": 1, "z": 2 } },
+        }));
+        let call = tokio::spawn(async move { mcp.create_instance(params).await });
+        let (_, tool, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("Workspace.Map.Part")]),
+        )
+        .await;
+        assert_eq!(tool, "CreateInstance");
+        assert!(
+            chunk.contains(r#"class_name = "Part", parent_path = "Workspace.Map""#),
+            "{}",
+            chunk
+        );
+        assert!(chunk.contains(r#"["Anchored"] = true"#), "{}", chunk);
+        assert_eq!(
+            result_text(&call.await.unwrap().unwrap()),
+            "Workspace.Map.Part"
+        );
+    }
+
+    #[tokio::test]
+    async fn create_instance_reports_every_invalid_field() {
+        let server = offline_server(ServerOptions::default());
+        let params = create_instance_params(serde_json::json!({
+            "class_name": "Part; os.exit()",
+            "parent_path": "Workspace..Map",
+            "properties": { "Parent": "Workspace", "Color": { "$type": "Nope" } },
+        }));
+        let body: serde_json::Value =
+            serde_json::from_str(&error_text(server.create_instance(params).await)).unwrap();
+        let mut fields: Vec<&str> = body["validation_errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(
+            fields,
+            [
+                "class_name",
+                "parent_path",
+                "properties.Color",
+                "properties.Parent"
+            ]
+        );
+        // The schema clients see requires a class name.
+        let schema =
+            serde_json::to_value(rmcp::schemars::schema_for!(CreateInstanceParams)).unwrap();
+        assert_eq!(schema["required"], serde_json::json!(["class_name"]));
+    }
+
+    #[test]
+    fn wait_for_escapes_the_condition() {
+        let args = ToolArgumentValues::WaitFor {
+            condition_luau: "workspace:FindFirstChild(\"Map\") ~= nil\nand true".to_string(),
+            timeout_ms: 5000,
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"WaitFor = { condition_luau = "workspace:FindFirstChild(\"Map\") ~= nil\nand true", timeout_ms = 5000 }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_for_timeout_must_end_before_the_tool_timeout() {
+        let server = offline_server(ServerOptions::default());
+        let max = (TOOL_EXECUTION_TIMEOUT - WAIT_FOR_TIMEOUT_MARGIN).as_millis() as u32;
+        for timeout_ms in [0, max + 1] {
+            let message = error_text(server.wait_for("true".to_string(), timeout_ms).await);
+            assert!(
+                message.contains(&format!("between 1 and {}", max)),
+                "{}",
+                message
+            );
+        }
+    }
+
+    async fn stats_of(sm_command_tx: &mpsc::Sender<StateManagerCommand>) -> ServerStats {
+        let (response_tx, response_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::GetStats { response_tx })
+            .await
+            .unwrap();
+        response_rx.await.unwrap()
+    }
+
+    async fn send_result(
+        sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+        task_id: Uuid,
+        text: &str,
+    ) -> bool {
+        let (accepted_tx, accepted_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::SubmitTaskResult {
+                task_id,
+                result: CallToolResult::success(vec![Content::text(text)]),
+                response_bytes: text.len(),
+                accepted_tx,
+            })
+            .await
+            .unwrap();
+        accepted_rx.await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn repeated_timeouts_open_the_breaker_until_a_probe_succeeds() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        for _ in 0..BREAKER_FAILURE_THRESHOLD {
+            let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+            let _caller = send_task(&sm_command_tx, task, None).await;
+            sm_command_tx
+                .send(StateManagerCommand::TaskTimedOut {
+                    task_id,
+                    reason: CancelReason::ExecutionTimeout,
+                    output_tx: None,
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(stats_of(&sm_command_tx).await.breaker, BreakerState::Open);
+
+        let (task, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let rejected = send_task(&sm_command_tx, task, None).await.await.unwrap();
+        let message = rejected.unwrap_err().message;
+        assert!(
+            message.contains("repeatedly failed to respond"),
+            "{}",
+            message
+        );
+
+        tokio::time::advance(BREAKER_COOLDOWN).await;
+        let (probe, probe_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let probe_caller = send_task(&sm_command_tx, probe, None).await;
+        assert_eq!(
+            stats_of(&sm_command_tx).await.breaker,
+            BreakerState::HalfOpen
+        );
+        // Only the probe goes through while it is out.
+        let (task, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        assert!(send_task(&sm_command_tx, task, None)
+            .await
+            .await
+            .unwrap()
+            .is_err());
+
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), probe_id);
+        assert!(send_result(&sm_command_tx, probe_id, "ok").await);
+        assert!(probe_caller.await.unwrap().is_ok());
+        assert_eq!(stats_of(&sm_command_tx).await.breaker, BreakerState::Closed);
+    }
+
+    #[test]
+    fn group_instances_formats_the_paths_as_an_escaped_array() {
+        let args = ToolArgumentValues::GroupInstances {
+            instance_paths: vec![
+                "Workspace.Wall \"A\"".to_string(),
+                "Workspace.Wall B".to_string(),
+            ],
+            container_class: "Model".to_string(),
+            container_name: "Walls".to_string(),
+        };
+        assert_eq!(
+            luau_for(args),
+            r#"GroupInstances = { instance_paths = { "Workspace.Wall \"A\"", "Workspace.Wall B" }, container_class = "Model", container_name = "Walls" }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn group_instances_needs_paths_and_defaults_to_a_model() {
+        let server = offline_server(ServerOptions::default());
+        let message = error_text(
+            server
+                .group_instances(vec![], None, "Walls".to_string())
+                .await,
+        );
+        assert!(message.contains("at least one instance"), "{}", message);
+
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.group_instances(
+                vec!["Workspace.Wall".to_string()],
+                None,
+                "Walls".to_string(),
+            )
+            .await
+        });
+        let (_, _, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("Workspace.Walls")]),
+        )
+        .await;
+        assert!(chunk.contains(r#"container_class = "Model""#), "{}", chunk);
+        call.await.unwrap().unwrap();
+    }
+
+    async fn send_output(
+        sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+        task_id: Uuid,
+        chunk: &str,
+    ) -> bool {
+        let (accepted_tx, accepted_rx) = oneshot::channel();
+        sm_command_tx
+            .send(StateManagerCommand::AppendTaskOutput {
+                task_id,
+                chunk: chunk.to_string(),
+                accepted_tx,
+            })
+            .await
+            .unwrap();
+        accepted_rx.await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timed_out_task_reports_the_output_it_streamed() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::RunCommand {
+            command: "for i = 1, 3 do print(i) task.wait(10) end".to_string(),
+            context: None,
+        });
+        let dispatcher = sm_command_tx.clone();
+        let call = tokio::spawn(async move {
+            let options = DispatchOptions {
+                execution_timeout: Duration::from_secs(20),
+                ..DispatchOptions::default()
+            };
+            dispatch_and_wait(&dispatcher, task, options).await
+        });
+        assert_eq!(polled_task_id(poll.await.unwrap()), task_id);
+        assert!(send_output(&sm_command_tx, task_id, "step 1\n").await);
+        assert!(send_output(&sm_command_tx, task_id, "step 2\n").await);
+
+        let error = call.await.unwrap().unwrap_err();
+        assert!(
+            error.message.contains("timed out after 20s"),
+            "{}",
+            error.message
+        );
+        assert!(
+            error
+                .message
+                .ends_with("Output streamed before the timeout:\nstep 1\nstep 2\n"),
+            "{}",
+            error.message
+        );
+        let data = error.data.unwrap();
+        assert_eq!(data["timeout_phase"], "execution");
+        assert_eq!(data["partial_output"], "step 1\nstep 2\n");
+    }
+
+    #[test]
+    fn the_output_tail_keeps_the_end_on_a_char_boundary() {
+        let output = format!("{}{}", "é".repeat(3000), "done");
+        let tail = output_tail(&output);
+        assert!(tail.len() <= 4096 && tail.ends_with("done"));
+        assert_eq!(output_tail("short"), "short");
+    }
+
+    #[test]
+    fn set_service_enabled_formats_the_flag_as_a_boolean() {
+        let set = |enabled| {
+            luau_for(ToolArgumentValues::SetServiceEnabled {
+                service: "HttpService".to_string(),
+                property: "HttpEnabled".to_string(),
+                enabled,
+            })
+        };
+        assert_eq!(
+            set(true),
+            r#"SetServiceEnabled = { service = "HttpService", property = "HttpEnabled", enabled = true }"#
+        );
+        assert_eq!(
+            set(false),
+            r#"SetServiceEnabled = { service = "HttpService", property = "HttpEnabled", enabled = false }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn set_service_enabled_only_flips_allowlisted_switches() {
+        let server = offline_server(ServerOptions::default());
+        let rejected = error_text(
+            server
+                .set_service_enabled("Workspace".to_string(), "Gravity".to_string(), true)
+                .await,
+        );
+        assert!(
+            rejected.starts_with("'Workspace.Gravity' is not a togglable service property."),
+            "{}",
+            rejected
+        );
+        assert!(rejected.contains("HttpService.HttpEnabled"), "{}", rejected);
+        // The service and property are matched exactly, not case-insensitively.
+        let rejected = error_text(
+            server
+                .set_service_enabled("httpservice".to_string(), "HttpEnabled".to_string(), true)
+                .await,
+        );
+        assert!(rejected.contains("is not a togglable"), "{}", rejected);
+
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.set_service_enabled("HttpService".to_string(), "HttpEnabled".to_string(), true)
+                .await
+        });
+        let (_, tool, _) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text(r#"{"previous":false,"current":true}"#)]),
+        )
+        .await;
+        assert_eq!(tool, "SetServiceEnabled");
+        assert!(call.await.unwrap().unwrap().is_error != Some(true));
+    }
+
+    #[test]
+    fn get_place_stats_sends_the_traversal_cap() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::GetPlaceStats {
+                max_instances: MAX_STATS_TRAVERSAL,
+            }),
+            "GetPlaceStats = { max_instances = 200000 }"
+        );
+    }
+
+    #[test]
+    fn run_command_formats_each_context_as_a_string() {
+        let run = |context: Option<&str>| {
+            luau_for(ToolArgumentValues::RunCommand {
+                command: "return 1".to_string(),
+                context: context.map(String::from),
+            })
+        };
+        for context in RUN_CONTEXTS {
+            let chunk = run(Some(context));
+            assert!(
+                chunk.contains(&format!(
+                    r#", context = "{}", returns_json = true }}"#,
+                    context
+                )),
+                "{}",
+                chunk
+            );
+        }
+        let chunk = run(None);
+        assert!(chunk.contains(", context = nil, "), "{}", chunk);
+    }
+
+    #[tokio::test]
+    async fn run_command_rejects_an_unknown_context() {
+        let server = offline_server(ServerOptions::default());
+        let text = error_text(
+            server
+                .run_command(
+                    "print(1)".to_string(),
+                    Some("client".to_string()),
+                    None,
+                    None,
+                    None,
+                )
+                .await,
+        );
+        assert_eq!(
+            text,
+            r#"'context' must be one of plugin, server, got "client"."#
+        );
+    }
+
+    // A fresh, empty folder for one test, under the system temp dir.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rbx-studio-mcp-server-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_discovered_tool_named_like_a_native_one_is_skipped_with_a_warning() {
+        let tools_dir = test_dir("shadowing");
+        for file in ["RunCommand.luau", "get_Place_Info.luau", "SetAmbient.luau"] {
+            fs::write(tools_dir.join(file), "return 1").unwrap();
+        }
+        fs::create_dir(tools_dir.join("Lighting")).unwrap();
+        fs::write(
+            tools_dir.join("Lighting").join("run_command.luau"),
+            "return 1",
+        )
+        .unwrap();
+
+        let (_guard, logs) = capture_logs();
+        let tools = discover_luau_tools(&tools_dir);
+        let mut names: Vec<_> = tools.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, ["Lighting.run_command", "SetAmbient"]);
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains("RunCommand.luau: its name shadows the native tool 'run_command'"),
+            "{}",
+            logs
+        );
+        assert!(
+            logs.contains("get_Place_Info.luau: its name shadows the native tool 'get_place_info'"),
+            "{}",
+            logs
+        );
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_manifest_lists_each_tool_with_a_stable_hash() {
+        // The published FNV-1a 64 test vector, so hashes match whatever the plugin computes.
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+        let tools_dir = test_dir("manifest");
+        fs::write(tools_dir.join("Spin.luau"), "return 1").unwrap();
+        fs::create_dir(tools_dir.join("Lighting")).unwrap();
+        fs::write(tools_dir.join("Lighting").join("Dim.luau"), "return 2").unwrap();
+        let tools = discover_luau_tools(&tools_dir);
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.discovered_luau_tools = ToolRegistry::new(tools);
+        })
+        .await;
+
+        let url = format!("http://127.0.0.1:{}/tools/manifest", server.port);
+        let fetch = || async {
+            reqwest::get(&url)
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+        let manifest = fetch().await;
+        assert_eq!(
+            manifest,
+            serde_json::json!({ "tools": [
+                { "name": "Lighting.Dim", "hash": format!("fnv1a64:{:016x}", fnv1a_64(b"return 2")) },
+                { "name": "Spin", "hash": format!("fnv1a64:{:016x}", fnv1a_64(b"return 1")) },
+            ] })
+        );
+        assert_eq!(fetch().await, manifest);
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[test]
+    fn with_selection_escapes_the_paths_and_the_command() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::WithSelection {
+                instance_paths: vec![
+                    "Workspace.Map.Door".to_string(),
+                    "Workspace.Map.\"Big\" Door".to_string(),
+                ],
+                command: "print(\"selected\")\nreturn #game:GetService(\"Selection\"):Get()"
+                    .to_string(),
+            }),
+            r#"WithSelection = { instance_paths = { "Workspace.Map.Door", "Workspace.Map.\"Big\" Door" }, command = "print(\"selected\")\nreturn #game:GetService(\"Selection\"):Get()" }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_model_applies_the_no_match_policy() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let no_match = CallToolResult::error(vec![Content::text(format!(
+            "{}No assets found for query: 'glowing tree'",
+            INSERT_NO_MATCH_SENTINEL
+        ))]);
+        for (policy, is_error, expected) in [
+            (
+                NoMatchPolicy::Error,
+                true,
+                "No assets matched query 'glowing tree'.",
+            ),
+            (
+                NoMatchPolicy::Noop,
+                false,
+                "No assets matched query 'glowing tree'; nothing was inserted.",
+            ),
+        ] {
+            let mcp = server.mcp_server(ServerOptions {
+                insert_on_no_match: policy,
+                ..ServerOptions::default()
+            });
+            let call = tokio::spawn(async move {
+                mcp.insert_model("glowing tree".to_string(), None, None, None)
+                    .await
+            });
+            answer_next(&plugin, no_match.clone()).await;
+            let result = call.await.unwrap().unwrap();
+            assert_eq!(result.is_error, Some(is_error), "{:?}", policy);
+            assert_eq!(result_text(&result), expected);
+        }
+
+        // Other failures pass through untouched under either policy.
+        let mcp = server.mcp_server(ServerOptions {
+            insert_on_no_match: NoMatchPolicy::Noop,
+            ..ServerOptions::default()
+        });
+        let call =
+            tokio::spawn(
+                async move { mcp.insert_model("tree".to_string(), None, None, None).await },
+            );
+        answer_next(
+            &plugin,
+            CallToolResult::error(vec![Content::text("Asset 123 failed to load.")]),
+        )
+        .await;
+        assert_eq!(error_text(call.await.unwrap()), "Asset 123 failed to load.");
+    }
+
+    #[test]
+    fn export_tree_formats_its_bounds() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::ExportTree {
+                root_path: "Workspace.Map".to_string(),
+                max_depth: 5,
+                max_nodes: MAX_EXPORT_NODES,
+            }),
+            r#"ExportTree = { root_path = "Workspace.Map", max_depth = 5, max_nodes = 2000 }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn export_tree_defaults_and_caps_the_depth() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        for (max_depth, sent) in [(None, "max_depth = 5,"), (Some(99), "max_depth = 20,")] {
+            let mcp = server.mcp_server(ServerOptions::default());
+            let call =
+                tokio::spawn(
+                    async move { mcp.export_tree("Workspace".to_string(), max_depth).await },
+                );
+            let (_, tool, chunk) =
+                answer_next(&plugin, CallToolResult::success(vec![Content::text("{}")])).await;
+            assert_eq!(tool, "ExportTree");
+            assert!(chunk.contains(sent), "{}", chunk);
+            call.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn paused_dispatch_queues_tasks_and_resuming_delivers_them_in_order() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let open_poll = send_poll(&sm_command_tx, "studio").await;
+        let state = set_dispatch_enabled(&sm_command_tx, false).await.unwrap();
+        assert!(!state.dispatch_enabled);
+        // Pausing releases a plugin's open long-poll instead of leaving it to hand out a task.
+        assert!(matches!(open_poll.await.unwrap(), PollOutcome::Paused));
+
+        let (first, first_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let (second, second_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlayers {});
+        let _first_caller = send_task(&sm_command_tx, first, None).await;
+        let _second_caller = send_task(&sm_command_tx, second, None).await;
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        assert!(matches!(poll.await.unwrap(), PollOutcome::Paused));
+
+        let state = set_dispatch_enabled(&sm_command_tx, true).await.unwrap();
+        assert!(state.dispatch_enabled);
+        assert_eq!(state.queued, 2);
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), first_id);
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), second_id);
+    }
+
+    #[test]
+    fn preambles_are_prepended_once_in_dependency_order() {
+        let tools_dir = test_dir("preambles");
+        fs::create_dir(tools_dir.join("shared")).unwrap();
+        fs::write(tools_dir.join("shared").join("Base.luau"), "_G.base = true").unwrap();
+        fs::write(
+            tools_dir.join("shared").join("Setup.luau"),
+            "--- @preamble shared.Base\n_G.setup = true",
+        )
+        .unwrap();
+        fs::write(
+            tools_dir.join("Build.luau"),
+            "--- @preamble shared.Setup\n--- @preamble shared.Base\nreturn 1",
+        )
+        .unwrap();
+        fs::write(
+            tools_dir.join("Broken.luau"),
+            "--- @preamble shared.Missing\nreturn 1",
+        )
+        .unwrap();
+        let (sm_command_tx, _) = mpsc::channel(1);
+        let server = RBXStudioServer::new(
+            sm_command_tx,
+            ToolRegistry::new(discover_luau_tools(&tools_dir)),
+            ServerOptions::default(),
+        );
+
+        assert_eq!(
+            server
+                .with_preambles("Build", "return { size = 2 }".to_string())
+                .unwrap(),
+            concat!(
+                "-- preamble: shared.Base\n(function()\n_G.base = true\nend)()\n",
+                "-- preamble: shared.Setup\n(function()\n--- @preamble shared.Base\n_G.setup = true\nend)()\n",
+                "return { size = 2 }",
+            )
+        );
+        assert_eq!(
+            server.with_preambles("shared.Base", "return {}".to_string()),
+            Ok("return {}".to_string())
+        );
+        assert_eq!(
+            server.with_preambles("Broken", "return {}".to_string()),
+            Err(
+                "Preamble 'shared.Missing' (required by 'Broken') is not a discovered Luau tool."
+                    .to_string()
+            )
+        );
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[test]
+    fn check_luau_escapes_the_code_into_one_string() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::CheckLuau {
+                code: "local s = \"]]\"\nprint(s\t)\\".to_string(),
+            }),
+            r#"CheckLuau = { code = "local s = \"]]\"\nprint(s\t)\\" }"#
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_name_the_phase_that_ran_out() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let (task, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let error = dispatch_and_wait(&sm_command_tx, task, DispatchOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.data.unwrap()["timeout_phase"], "queue_wait");
+        assert!(
+            error.message.contains("it never started"),
+            "{}",
+            error.message
+        );
+
+        // A long wait in the queue doesn't eat into the execution deadline: 25s queued plus
+        // 25s running is fine, though it's longer than either deadline on its own.
+        let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let dispatcher = sm_command_tx.clone();
+        let call = tokio::spawn(async move {
+            dispatch_and_wait(&dispatcher, task, DispatchOptions::default()).await
+        });
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), task_id);
+        tokio::time::sleep(Duration::from_secs(25)).await;
+        assert!(send_result(&sm_command_tx, task_id, "Place 1").await);
+        call.await.unwrap().unwrap();
+
+        // Picked up at once but never answered: the execution deadline is the one reported.
+        let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let poll = send_poll(&sm_command_tx, "studio").await;
+        let dispatcher = sm_command_tx.clone();
+        let call = tokio::spawn(async move {
+            dispatch_and_wait(&dispatcher, task, DispatchOptions::default()).await
+        });
+        assert_eq!(polled_task_id(poll.await.unwrap()), task_id);
+        let error = call.await.unwrap().unwrap_err();
+        assert_eq!(error.data.unwrap()["timeout_phase"], "execution");
+        assert!(
+            error.message.contains("plugin started the task"),
+            "{}",
+            error.message
+        );
+    }
+
+    #[test]
+    fn get_players_formats_without_arguments() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::GetPlayers {}),
+            "GetPlayers = {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn set_property_bulk_sends_one_task_for_every_path() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        for (value, encoded) in [
+            (serde_json::json!(0.5), "0.5"),
+            (serde_json::json!("Neon \"glow\""), r#""Neon \"glow\"""#),
+        ] {
+            let mcp = server.mcp_server(ServerOptions::default());
+            let call = tokio::spawn(async move {
+                mcp.set_property_bulk(
+                    vec![
+                        "Workspace.Map.Wall".to_string(),
+                        "Workspace.Map.Big Door".to_string(),
+                    ],
+                    "Transparency".to_string(),
+                    value,
+                )
+                .await
+            });
+            let (_, tool, chunk) =
+                answer_next(&plugin, CallToolResult::success(vec![Content::text("[]")])).await;
+            assert_eq!(tool, "SetPropertyBulk");
+            let expected = format!(
+                r#"SetPropertyBulk = {{ instance_paths = {{ "Workspace.Map.Wall", "Workspace.Map.Big Door" }}, property = "Transparency", value = {} }}"#,
+                encoded
+            );
+            assert!(chunk.contains(&expected), "{}", chunk);
+            call.await.unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn get_run_logs_formats_the_optional_start_time() {
+        let logs = |since_ms| {
+            luau_for(ToolArgumentValues::GetRunLogs {
+                since_ms,
+                max_lines: MAX_RUN_LOG_LINES,
+            })
+        };
+        assert_eq!(
+            logs(Some(1_700_000_000_123)),
+            "GetRunLogs = { since_ms = 1700000000123, max_lines = 500 }"
+        );
+        assert_eq!(
+            logs(None),
+            "GetRunLogs = { since_ms = nil, max_lines = 500 }"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_bundle_serves_each_tool_source_behind_the_auth_token() {
+        let tools_dir = test_dir("bundle");
+        fs::write(
+            tools_dir.join("Spin.luau"),
+            "--- @readonly\nreturn function() end",
+        )
+        .unwrap();
+        fs::create_dir(tools_dir.join("Lighting")).unwrap();
+        fs::write(tools_dir.join("Lighting").join("Dim.luau"), "return 2").unwrap();
+        let tools = discover_luau_tools(&tools_dir);
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.discovered_luau_tools = ToolRegistry::new(tools);
+            state.auth_token = Some("secret".into());
+        })
+        .await;
+        let url = format!("http://127.0.0.1:{}/tools/bundle", server.port);
+        let client = reqwest::Client::new();
+
+        let status = client.get(&url).send().await.unwrap().status();
+        assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+
+        let bundle: serde_json::Value = client
+            .get(&url)
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let tools = bundle["tools"].as_object().unwrap();
+        assert_eq!(tools.keys().collect::<Vec<_>>(), ["Lighting.Dim", "Spin"]);
+        assert_eq!(
+            tools["Spin"]["source"],
+            "--- @readonly\nreturn function() end"
+        );
+        assert_eq!(tools["Spin"]["readonly"], true);
+        assert_eq!(tools["Lighting.Dim"]["readonly"], false);
+        assert_eq!(
+            tools["Lighting.Dim"]["hash"],
+            format!("fnv1a64:{:016x}", fnv1a_64(b"return 2"))
+        );
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[test]
+    fn create_script_keeps_source_with_closing_brackets_intact() {
+        let script = |script_class: &str, source: &str, disabled| {
+            luau_for(ToolArgumentValues::CreateScript {
+                script_class: script_class.to_string(),
+                parent_path: "ServerScriptService".to_string(),
+                name: "Main".to_string(),
+                source: source.to_string(),
+                disabled,
+            })
+        };
+        assert_eq!(
+            script("Script", "local t = a[b[1]]\nprint(\"]]\")", true),
+            r#"CreateScript = { script_class = "Script", parent_path = "ServerScriptService", name = "Main", source = "local t = a[b[1]]\nprint(\"]]\")", disabled = true }"#
+        );
+        assert_eq!(
+            script("ModuleScript", "return {}", false),
+            r#"CreateScript = { script_class = "ModuleScript", parent_path = "ServerScriptService", name = "Main", source = "return {}", disabled = false }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn create_script_rejects_other_classes() {
+        let server = offline_server(ServerOptions::default());
+        let text = error_text(
+            server
+                .create_script(
+                    "CoreScript".to_string(),
+                    "ServerScriptService".to_string(),
+                    "Main".to_string(),
+                    "return 1".to_string(),
+                    false,
+                )
+                .await,
+        );
+        assert_eq!(
+            text,
+            r#"'script_class' must be one of Script, LocalScript, ModuleScript, got "CoreScript"."#
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_health_summary_counts_the_tasks_of_its_interval() {
+        let (_guard, logs) = capture_logs();
+        let sm_command_tx =
+            start_state_manager(StateManager::new().with_summary_interval(Duration::from_secs(60)));
+        for _ in 0..2 {
+            let poll = send_poll(&sm_command_tx, "studio").await;
+            let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+            let caller = send_task(&sm_command_tx, task, None).await;
+            assert_eq!(polled_task_id(poll.await.unwrap()), task_id);
+            assert!(send_result(&sm_command_tx, task_id, "Place 1").await);
+            caller.await.unwrap().unwrap();
+        }
+        // A plugin waiting in a long-poll counts as connected.
+        let _open_poll = send_poll(&sm_command_tx, "studio").await;
+        tokio::time::sleep(Duration::from_secs(121)).await;
+
+        let logs = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let summaries: Vec<_> = logs
+            .lines()
+            .filter(|line| line.contains("Health summary for the last 60s."))
+            .collect();
+        assert_eq!(summaries.len(), 2, "{}", logs);
+        assert!(
+            summaries[0].contains("dispatched=2 completed=2 timed_out=0 queued=0"),
+            "{}",
+            summaries[0]
+        );
+        assert!(
+            summaries[0].contains("plugin_connected=true"),
+            "{}",
+            summaries[0]
+        );
+        // Counts start over each interval.
+        assert!(
+            summaries[1].contains("dispatched=0 completed=0 timed_out=0"),
+            "{}",
+            summaries[1]
+        );
+    }
+
+    #[test]
+    fn resolve_path_formats_the_path_as_one_string() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::ResolvePath {
+                path: "Workspace.Map.\"Old\" Part".to_string(),
+                limit: MAX_RESOLVE_MATCHES,
+            }),
+            r#"ResolvePath = { path = "Workspace.Map.\"Old\" Part", limit = 100 }"#
+        );
+    }
+
+    fn call_with_meta(meta: serde_json::Value) -> CallContext {
+        CallContext {
+            peer: test_peer().0,
+            meta: meta.as_object().unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn a_calls_priority_comes_from_its_argument_then_its_meta() {
+        assert_eq!(TaskPriority::for_call(None), Ok(TaskPriority::Normal));
+        let high = || call_with_meta(serde_json::json!({ "priority": "high" }));
+        assert_eq!(
+            CALL_CONTEXT.sync_scope(high(), || TaskPriority::for_call(None)),
+            Ok(TaskPriority::High)
+        );
+        assert_eq!(
+            CALL_CONTEXT.sync_scope(high(), || TaskPriority::for_call(Some("low"))),
+            Ok(TaskPriority::Low)
+        );
+        assert_eq!(
+            CALL_CONTEXT.sync_scope(call_with_meta(serde_json::json!({ "priority": 1 })), || {
+                TaskPriority::for_call(None)
+            }),
+            Err("'_meta.priority' must be 'low', 'normal' or 'high', got 1.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn a_high_priority_meta_jumps_the_queue() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let (queued, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlayers {});
+        let _queued_caller = send_task(&sm_command_tx, queued, None).await;
+        let mcp = RBXStudioServer::new(
+            sm_command_tx.clone(),
+            ToolRegistry::new(HashMap::new()),
+            ServerOptions::default(),
+        );
+        let _urgent_call = tokio::spawn(CALL_CONTEXT.scope(
+            call_with_meta(serde_json::json!({ "priority": "high" })),
+            async move { mcp.get_place_info().await },
+        ));
+        while stats_of(&sm_command_tx).await.queue_len < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        for expected in ["GetPlaceInfo", "GetPlayers"] {
+            match send_poll(&sm_command_tx, "studio").await.await.unwrap() {
+                PollOutcome::Task(task) => assert_eq!(task.args.variant_name(), expected),
+                PollOutcome::Paused => panic!("dispatch is not paused"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn an_invalid_meta_priority_fails_the_call() {
+        let server = offline_server(ServerOptions::default());
+        let text = error_text(
+            CALL_CONTEXT
+                .scope(
+                    call_with_meta(serde_json::json!({ "priority": "urgent" })),
+                    server.get_place_info(),
+                )
+                .await,
+        );
+        assert_eq!(
+            text,
+            "'priority' must be 'low', 'normal' or 'high', got 'urgent'."
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_trees_exports_the_live_subtree_and_compares_it() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let before = serde_json::json!({ "tree": {
+            "className": "Model",
+            "properties": { "Name": "Door" },
+            "children": [{ "className": "Part", "properties": { "Name": "Knob", "Transparency": 0 } }],
+        } });
+        let call =
+            tokio::spawn(async move { mcp.diff_trees(before, "Workspace.Door".to_string()).await });
+        let after = serde_json::json!({ "truncated": false, "tree": {
+            "className": "Model",
+            "properties": { "Name": "Door" },
+            "children": [{ "className": "Part", "properties": { "Name": "Knob", "Transparency": 0.5 } }],
+        } });
+        let (_, tool, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text(after.to_string())]),
+        )
+        .await;
+        assert_eq!(tool, "ExportTree");
+        assert!(
+            chunk.contains(
+                r#"ExportTree = { root_path = "Workspace.Door", max_depth = 20, max_nodes = 2000 }"#
+            ),
+            "{}",
+            chunk
+        );
+        let diff: serde_json::Value =
+            serde_json::from_str(&result_text(&call.await.unwrap().unwrap())).unwrap();
+        assert_eq!(
+            diff["changed"],
+            serde_json::json!([{ "path": "Door.Knob", "property": "Transparency", "before": 0, "after": 0.5 }])
+        );
+        assert_eq!(diff["added"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn diff_trees_wants_an_export_tree_result() {
+        let server = offline_server(ServerOptions::default());
+        let text = error_text(
+            server
+                .diff_trees(
+                    serde_json::json!({ "Name": "Door" }),
+                    "Workspace.Door".to_string(),
+                )
+                .await,
+        );
+        assert_eq!(text, "'before' must be a tree returned by export_tree.");
+    }
+
+    #[test]
+    fn get_properties_bulk_formats_each_request() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::GetPropertiesBulk {
+                requests: vec![
+                    (
+                        "Workspace.Baseplate".to_string(),
+                        vec!["Size".to_string(), "Anchored".to_string()],
+                    ),
+                    (
+                        "Workspace.Missing \"Part\"".to_string(),
+                        vec!["Name".to_string()]
+                    ),
+                ],
+            }),
+            r#"GetPropertiesBulk = { requests = { { path = "Workspace.Baseplate", properties = { "Size", "Anchored" } }, { path = "Workspace.Missing \"Part\"", properties = { "Name" } } } }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn a_poll_while_paused_says_so() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        set_dispatch_enabled(&server.sm_command_tx, false)
+            .await
+            .unwrap();
+        assert!(matches!(plugin.poll().await.unwrap(), Poll::Paused));
+
+        set_dispatch_enabled(&server.sm_command_tx, true)
+            .await
+            .unwrap();
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let (_, tool, _) = answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text("Place 1")]),
+        )
+        .await;
+        assert_eq!(tool, "GetPlaceInfo");
+        call.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn search_models_formats_and_bounds_its_arguments() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::SearchModels {
+                query: "pine \"tree\"".to_string(),
+                limit: 10,
+            }),
+            r#"SearchModels = { query = "pine \"tree\"", limit = 10 }"#
+        );
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        for (limit, sent) in [(0, "limit = 1 }"), (500, "limit = 50 }")] {
+            let mcp = server.mcp_server(ServerOptions::default());
+            let call =
+                tokio::spawn(async move { mcp.search_models("tree".to_string(), limit).await });
+            let (_, _, chunk) =
+                answer_next(&plugin, CallToolResult::success(vec![Content::text("[]")])).await;
+            assert!(chunk.contains(sent), "{}", chunk);
+            call.await.unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn set_anchored_and_weld_instances_format_their_paths() {
+        let paths = vec![
+            "Workspace.Cart.Base".to_string(),
+            "Workspace.Cart.\"Front\" Wheel".to_string(),
+        ];
+        assert_eq!(
+            luau_for(ToolArgumentValues::SetAnchored {
+                instance_paths: paths.clone(),
+                anchored: false,
+            }),
+            r#"SetAnchored = { instance_paths = { "Workspace.Cart.Base", "Workspace.Cart.\"Front\" Wheel" }, anchored = false }"#
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::WeldInstances {
+                instance_paths: paths,
+            }),
+            r#"WeldInstances = { instance_paths = { "Workspace.Cart.Base", "Workspace.Cart.\"Front\" Wheel" } }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn set_anchored_and_weld_instances_need_enough_paths() {
+        let server = offline_server(ServerOptions::default());
+        assert_eq!(
+            error_text(server.set_anchored(Vec::new(), true).await),
+            "'instance_paths' must list at least 1 instance(s)."
+        );
+        assert_eq!(
+            error_text(
+                server
+                    .weld_instances(vec!["Workspace.Cart.Base".to_string()])
+                    .await
+            ),
+            "'instance_paths' must list at least 2 instance(s)."
+        );
+    }
+
+    #[test]
+    fn physical_properties_are_encoded_in_constructor_order() {
+        let encode = |props| encode_physical_properties(&props);
+        assert_eq!(
+            encode(serde_json::json!({ "elasticity": 0.5, "friction": 0.3, "density": 0.7 })),
+            Ok("PhysicalProperties.new(0.7, 0.3, 0.5, 1, 1)".to_string())
+        );
+        assert_eq!(
+            encode(serde_json::json!({
+                "density": 2, "friction": 0, "elasticity": 1,
+                "friction_weight": 50, "elasticity_weight": 0.25,
+            })),
+            Ok("PhysicalProperties.new(2, 0, 1, 50, 0.25)".to_string())
+        );
+        assert_eq!(encode(serde_json::Value::Null), Ok("nil".to_string()));
+        assert_eq!(
+            encode(serde_json::json!({ "density": 1, "friction": 0.3 })),
+            Err("'props' is missing 'elasticity'.".to_string())
+        );
+        assert_eq!(
+            encode(serde_json::json!({ "density": 1, "friction": 3, "elasticity": 0 })),
+            Err("'friction' must be between 0 and 2, got 3.".to_string())
+        );
+        assert_eq!(
+            encode(serde_json::json!({ "density": 1, "friction": 0, "elasticity": 0, "mass": 1 })),
+            Err("Unknown field 'mass' in 'props'.".to_string())
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::SetPhysicalProperties {
+                instance_paths: vec!["Workspace.Ball".to_string()],
+                properties: "PhysicalProperties.new(0.7, 0.3, 0.5, 1, 1)".to_string(),
+            }),
+            r#"SetPhysicalProperties = { instance_paths = { "Workspace.Ball" }, properties = PhysicalProperties.new(0.7, 0.3, 0.5, 1, 1) }"#
+        );
+    }
+
+    #[test]
+    fn set_collision_group_formats_the_group_and_flag() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::SetCollisionGroup {
+                instance_paths: vec!["Workspace.Ball".to_string()],
+                group: "Bouncy \"Balls\"".to_string(),
+                create_if_missing: true,
+            }),
+            r#"SetCollisionGroup = { instance_paths = { "Workspace.Ball" }, group = "Bouncy \"Balls\"", create_if_missing = true }"#
+        );
+    }
+
+    #[test]
+    fn delete_tagged_formats_the_escaped_tag() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::DeleteTagged {
+                tag: "session \"7\"\n".to_string(),
+            }),
+            r#"DeleteTagged = { attribute = "MCPTag", tag = "session \"7\"\n" }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn typed_tool_modules_are_not_callable_by_name() {
+        let tools_dir = test_dir("typed-modules");
+        for file in ["DeleteTagged.luau", "RunCode.luau", "Spin.luau"] {
+            fs::write(tools_dir.join(file), "return 1").unwrap();
+        }
+        fs::create_dir(tools_dir.join("Cleanup")).unwrap();
+        fs::write(
+            tools_dir.join("Cleanup").join("DeleteTagged.luau"),
+            "return 1",
+        )
+        .unwrap();
+        let tools = discover_luau_tools(&tools_dir);
+        let mut names: Vec<_> = tools.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, ["Cleanup.DeleteTagged", "Spin"]);
+
+        let (sm_command_tx, _) = mpsc::channel(1);
+        let server = RBXStudioServer::new(
+            sm_command_tx,
+            ToolRegistry::new(tools),
+            ServerOptions::default(),
+        );
+        let text = error_text(
+            server
+                .execute_discovered_luau_tool(
+                    "DeleteTagged".to_string(),
+                    r#"return { attribute = "MCPTag", tag = "x" }"#.to_string(),
+                    None,
+                    None,
+                    None,
+                )
+                .await,
+        );
+        assert!(
+            text.starts_with("Luau tool 'DeleteTagged' not found."),
+            "{}",
+            text
+        );
+        fs::remove_dir_all(&tools_dir).unwrap();
+
+        // Every listed module is one the plugin ships, and none of them is discovered from it.
+        let plugin_tools = Path::new(env!("CARGO_MANIFEST_DIR")).join("plugin/src/Tools");
+        for module in TYPED_TASK_MODULES {
+            assert!(
+                plugin_tools.join(format!("{}.luau", module)).is_file(),
+                "{}",
+                module
+            );
+        }
+        let discovered = discover_luau_tools(&plugin_tools);
+        assert!(
+            !discovered
+                .keys()
+                .any(|name| TYPED_TASK_MODULES.contains(&name.as_str())),
+            "{:?}",
+            discovered.keys()
+        );
+    }
+
+    fn initialize_request(name: &str, capabilities: serde_json::Value) -> InitializeRequestParam {
+        InitializeRequestParam {
+            protocol_version: ProtocolVersion::default(),
+            capabilities: serde_json::from_value(capabilities).unwrap(),
+            client_info: Implementation {
+                name: name.into(),
+                version: "1.0".into(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn image_content_is_flattened_for_a_text_only_client() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let image = || {
+            CallToolResult::success(vec![
+                Content::text("Screenshot"),
+                Content::image("aGVsbG8=", "image/png"),
+            ])
+        };
+        let text_only = serde_json::json!({ "experimental": { "content": { "types": ["text"] } } });
+        let with_images =
+            serde_json::json!({ "experimental": { "content": { "types": ["text", "image"] } } });
+        for (request, flattened) in [
+            (initialize_request("plain", text_only), true),
+            (initialize_request("rich", with_images.clone()), false),
+            (initialize_request("silent", serde_json::json!({})), false),
+            // The configured name list wins over what the client declares.
+            (initialize_request("Listed", with_images), true),
+        ] {
+            let mcp = server.mcp_server(ServerOptions {
+                text_only_clients: vec!["listed".into()],
+                ..ServerOptions::default()
+            });
+            mcp.negotiate_content(&request);
+            let call = tokio::spawn(async move { mcp.get_place_info().await });
+            answer_next(&plugin, image()).await;
+            let result = call.await.unwrap().unwrap();
+
+            let client = &request.client_info.name;
+            assert_eq!(result.content.len(), 2, "{}", client);
+            match &result.content[1].raw {
+                RawContent::Text(text) if flattened => {
+                    assert_eq!(text.text, "[image omitted: image/png, 8 bytes of base64]")
+                }
+                RawContent::Image(_) if !flattened => {}
+                other => panic!("{}: unexpected content {:?}", client, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_connection_renegotiates_when_initialized_again() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let other_connection = server.mcp_server(ServerOptions::default());
+        mcp.negotiate_content(&initialize_request(
+            "plain",
+            serde_json::json!({ "experimental": { "content": { "types": [] } } }),
+        ));
+        assert!(mcp.text_only_client.load(Ordering::Relaxed));
+        assert!(!other_connection.text_only_client.load(Ordering::Relaxed));
+        mcp.negotiate_content(&initialize_request("plain", serde_json::json!({})));
+        assert!(!mcp.text_only_client.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn workspace_property_tasks_format_the_name_and_encoded_value() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::GetWorkspaceProperty {
+                property_name: "Gravity".to_string(),
+            }),
+            r#"GetWorkspaceProperty = { property_name = "Gravity" }"#
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::SetWorkspaceProperty {
+                property_name: "GlobalWind".to_string(),
+                value: "Vector3.new(1, 0, -2)".to_string(),
+            }),
+            r#"SetWorkspaceProperty = { property_name = "GlobalWind", value = Vector3.new(1, 0, -2) }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn workspace_properties_are_limited_to_the_allowlist() {
+        let server = offline_server(ServerOptions::default());
+        for property in ["Name", "gravity", "CurrentCamera"] {
+            let rejected = error_text(server.get_workspace_property(property.to_string()).await);
+            assert!(
+                rejected.starts_with(&format!(
+                    "'{}' is not an allowed Workspace property;",
+                    property
+                )),
+                "{}",
+                rejected
+            );
+            assert!(
+                rejected.contains("FallenPartsDestroyHeight"),
+                "{}",
+                rejected
+            );
+            let rejected = error_text(
+                server
+                    .set_workspace_property(property.to_string(), serde_json::json!(1))
+                    .await,
+            );
+            assert!(
+                rejected.contains("is not an allowed Workspace property"),
+                "{}",
+                rejected
+            );
+        }
+        for (property, value, expected) in [
+            ("Gravity", serde_json::json!("196.2"), "a finite number"),
+            ("StreamingEnabled", serde_json::json!(1), "a boolean"),
+            (
+                "GlobalWind",
+                serde_json::json!([1, 0, 0]),
+                "a tagged Vector3",
+            ),
+        ] {
+            let rejected = error_text(
+                server
+                    .set_workspace_property(property.to_string(), value.clone())
+                    .await,
+            );
+            assert_eq!(
+                rejected,
+                format!("'{}' must be set to {}, got {}.", property, expected, value)
+            );
+        }
+
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.set_workspace_property(
+                "GlobalWind".to_string(),
+                serde_json::json!({ "$type": "Vector3", "x": 4, "y": 0, "z": 2.5 }),
+            )
+            .await
+        });
+        let (_, tool, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("ok")]),
+        )
+        .await;
+        assert_eq!(tool, "SetWorkspaceProperty");
+        assert!(
+            chunk.contains(r#"property_name = "GlobalWind", value = Vector3.new(4, 0, 2.5)"#),
+            "{}",
+            chunk
+        );
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "ok");
+    }
+
+    #[tokio::test]
+    async fn the_status_endpoint_reports_why_a_task_was_cancelled() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions {
+            execution_timeout: Duration::from_millis(300),
+            ..ServerOptions::default()
+        });
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let (task_id, _, _) = poll_task(&server.plugin()).await;
+        let status = |task_id: Uuid| {
+            let url = format!("http://127.0.0.1:{}/tasks/{}/status", server.port, task_id);
+            async move {
+                reqwest::get(&url)
+                    .await
+                    .unwrap()
+                    .json::<serde_json::Value>()
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(
+            status(task_id).await,
+            serde_json::json!({ "status": "running" })
+        );
+
+        assert!(call.await.unwrap().is_err(), "the call times out");
+        assert_eq!(
+            status(task_id).await,
+            serde_json::json!({ "status": "cancelled", "reason": "execution_timeout" })
+        );
+        assert_eq!(
+            status(Uuid::new_v4()).await,
+            serde_json::json!({ "status": "unknown" })
+        );
+    }
+
+    #[test]
+    fn view_snapshot_tasks_format_the_selection_and_camera() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::SnapshotView {}),
+            "SnapshotView = {}"
+        );
+        let identity_at = |x: f64| [x, 10.0, -5.5, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        assert_eq!(
+            luau_for(ToolArgumentValues::RestoreView {
+                selection: vec![
+                    "Workspace.Base".to_string(),
+                    "Workspace.\"Odd\"".to_string()
+                ],
+                camera: Some(CameraState {
+                    cframe: identity_at(2.0),
+                    focus: identity_at(0.0),
+                    field_of_view: 70.0,
+                }),
+            }),
+            concat!(
+                r#"RestoreView = { selection = { "Workspace.Base", "Workspace.\"Odd\"" }, camera = { "#,
+                "cframe = CFrame.new(2, 10, -5.5, 1, 0, 0, 0, 1, 0, 0, 0, 1), ",
+                "focus = CFrame.new(0, 10, -5.5, 1, 0, 0, 0, 1, 0, 0, 0, 1), ",
+                "field_of_view = 70 } }"
+            )
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::RestoreView {
+                selection: vec![],
+                camera: None,
+            }),
+            "RestoreView = { selection = {}, camera = nil }"
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_view_rejects_malformed_tokens() {
+        let server = offline_server(ServerOptions::default());
+        let components = serde_json::json!([0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1]);
+        for (token, expected) in [
+            (
+                serde_json::json!("opaque"),
+                "'token' is not a snapshot_view token:",
+            ),
+            (
+                serde_json::json!({ "selection": [], "extra": 1 }),
+                "'token' is not a snapshot_view token:",
+            ),
+            (
+                serde_json::json!({ "selection": [], "camera": { "cframe": [1, 2], "focus": components, "field_of_view": 70 } }),
+                "'token' is not a snapshot_view token:",
+            ),
+            (
+                serde_json::json!({ "selection": [], "camera": { "cframe": components, "focus": components, "field_of_view": 500 } }),
+                "Camera field_of_view must be between 1 and 120, got 500.",
+            ),
+        ] {
+            let rejected = error_text(server.restore_view(token.clone()).await);
+            assert!(rejected.starts_with(expected), "{}: {}", token, rejected);
+        }
+    }
+
+    #[test]
+    fn luau_payloads_containing_closing_brackets_stay_one_literal() {
+        let payload = "local t = {{1}}; print(t[1][1]) --[[ ]] ]=]".to_string();
+        let run = luau_for(ToolArgumentValues::RunCommand {
+            command: payload.clone(),
+            context: None,
+        });
+        // The command is wrapped to collect its return values, but stays inside one `[==[` literal.
+        assert!(run.starts_with("RunCommand = { command = [==["), "{}", run);
+        assert!(run.contains(&payload), "{}", run);
+        assert_eq!(run.matches("]==]").count(), 1, "{}", run);
+        assert!(
+            run.ends_with("]==], context = nil, returns_json = true }"),
+            "{}",
+            run
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::ExecuteLuauByName {
+                tool_name: "Spin".to_string(),
+                arguments_luau: "{ code = [[x]] }".to_string(),
+                source: None,
+            }),
+            r#"ExecuteLuauByName = { tool_name = "Spin", arguments_luau = [=[{ code = [[x]] }]=], source = nil }"#
+        );
+        let insert = luau_for(ToolArgumentValues::InsertModel {
+            query: "tree]]\n\\".to_string(),
+            parent_path: None,
+            position: None,
+        });
+        assert!(
+            insert.starts_with(r#"InsertModel = { query = "tree]]\n\\""#),
+            "{}",
+            insert
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn each_plugin_session_only_receives_its_own_tasks() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let poll_a = send_poll(&sm_command_tx, "studio-a").await;
+        let poll_b = send_poll(&sm_command_tx, "studio-b").await;
+        let (for_b, for_b_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let (for_a, for_a_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let _b_caller = send_task(&sm_command_tx, for_b, Some("studio-b")).await;
+        let _a_caller = send_task(&sm_command_tx, for_a, Some("studio-a")).await;
+        assert_eq!(polled_task_id(poll_a.await.unwrap()), for_a_id);
+        assert_eq!(polled_task_id(poll_b.await.unwrap()), for_b_id);
+
+        // Queued while nobody polls, a task still waits for the session it names.
+        let (queued, queued_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let _queued_caller = send_task(&sm_command_tx, queued, Some("studio-b")).await;
+        let mut poll_a = send_poll(&sm_command_tx, "studio-a").await;
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), &mut poll_a)
+                .await
+                .is_err(),
+            "studio-a was handed studio-b's task"
+        );
+        drop(poll_a);
+        let poll_b = send_poll(&sm_command_tx, "studio-b").await;
+        assert_eq!(polled_task_id(poll_b.await.unwrap()), queued_id);
+
+        // Untargeted tasks go to the waiting sessions in turn.
+        let poll_a = send_poll(&sm_command_tx, "studio-a").await;
+        let poll_b = send_poll(&sm_command_tx, "studio-b").await;
+        let (first, first_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let (second, second_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let _first_caller = send_task(&sm_command_tx, first, None).await;
+        let _second_caller = send_task(&sm_command_tx, second, None).await;
+        let mut delivered = [
+            polled_task_id(poll_a.await.unwrap()),
+            polled_task_id(poll_b.await.unwrap()),
+        ];
+        delivered.sort();
+        let mut expected = [first_id, second_id];
+        expected.sort();
+        assert_eq!(delivered, expected);
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_task_fails_its_caller_at_once() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions::default());
+        let caller = mcp.clone();
+        let call = tokio::spawn(async move {
+            caller
+                .run_command("wait(60)".to_string(), None, None, None, None)
+                .await
+        });
+        let (task_id, _, _) = poll_task(&plugin).await;
+
+        let cancelled = mcp.cancel_task(task_id.to_string()).await.unwrap();
+        assert_eq!(
+            result_text(&cancelled),
+            format!("Cancelled task {}.", task_id)
+        );
+        let error = call.await.unwrap().unwrap_err();
+        assert_eq!(error.message, "The task was cancelled with cancel_task.");
+        assert_eq!(error.data.unwrap()["cancelled"], true);
+
+        // The task is gone: a second cancel finds nothing and the plugin's late result is refused.
+        let again = error_text(mcp.cancel_task(task_id.to_string()).await);
+        assert!(again.starts_with("No pending task"), "{}", again);
+        let status = plugin
+            .submit(
+                task_id,
+                &CallToolResult::success(vec![Content::text("done")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+        let rejected = error_text(mcp.cancel_task("not-a-uuid".to_string()).await);
+        assert_eq!(rejected, "'not-a-uuid' is not a task id.");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_seconds_lets_a_slow_task_finish() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let mcp = RBXStudioServer::new(
+            sm_command_tx.clone(),
+            ToolRegistry::new(HashMap::new()),
+            ServerOptions::default(),
+        );
+        // A plugin that takes `secs` to run each task it is handed.
+        let slow_task = |secs: u64| {
+            let sm_command_tx = sm_command_tx.clone();
+            async move {
+                let task_id =
+                    polled_task_id(send_poll(&sm_command_tx, "slow").await.await.unwrap());
+                tokio::time::sleep(Duration::from_secs(secs)).await;
+                send_result(&sm_command_tx, task_id, "done").await
+            }
+        };
+        for (timeout_seconds, takes, finishes) in [
+            (None, 45, false),
+            (Some(60), 45, true),
+            // Overrides are capped at MAX_TOOL_EXECUTION_TIMEOUT.
+            (Some(1000), 310, false),
+        ] {
+            let caller = mcp.clone();
+            let call = tokio::spawn(async move {
+                caller
+                    .run_command(
+                        "task.wait(45)".to_string(),
+                        None,
+                        timeout_seconds,
+                        None,
+                        None,
+                    )
+                    .await
+            });
+            let accepted = slow_task(takes).await;
+            assert_eq!(accepted, finishes, "timeout {:?}", timeout_seconds);
+            match call.await.unwrap() {
+                Ok(result) => assert!(finishes, "{:?}", result),
+                Err(error) => {
+                    assert!(
+                        !finishes,
+                        "timeout {:?}: {}",
+                        timeout_seconds, error.message
+                    );
+                    let limit = timeout_seconds.map_or(30, |seconds| seconds.min(300));
+                    assert!(
+                        error
+                            .message
+                            .starts_with(&format!("Tool execution timed out after {}s;", limit)),
+                        "{}",
+                        error.message
+                    );
+                }
+            }
+        }
+        let rejected = error_text(
+            mcp.run_command("x".to_string(), None, Some(0), None, None)
+                .await,
+        );
+        assert_eq!(rejected, "'timeout_seconds' must be at least 1.");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_restarted_server_requeues_undelivered_journaled_tasks() {
+        let path = test_dir("queue-journal").join("queue.jsonl");
+        let (undelivered, undelivered_id) =
+            ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let (delivered, delivered_id) =
+            ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let (completed, completed_id) =
+            ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        {
+            let (mut journal, recovered) = QueueJournal::open(&path).unwrap();
+            assert!(recovered.is_empty());
+            for task in [&delivered, &undelivered, &completed] {
+                journal.record_queued(task);
+            }
+            journal.record_delivered(delivered_id);
+            journal.record_completed(completed_id);
+        }
+        // A line cut short by a crash is skipped.
+        fs::write(
+            &path,
+            fs::read_to_string(&path).unwrap() + r#"{"queued":{"args":"#,
+        )
+        .unwrap();
+
+        let sm_command_tx =
+            start_state_manager(StateManager::new().with_queue_journal(&path).unwrap());
+        let poll = send_poll(&sm_command_tx, "after-restart").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), undelivered_id);
+        let mut poll = send_poll(&sm_command_tx, "after-restart").await;
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), &mut poll)
+                .await
+                .is_err(),
+            "delivered and completed tasks are not replayed"
+        );
+        // Opening compacted the journal down to the re-queued task, which is now delivered.
+        let (_, recovered) = QueueJournal::open(&path).unwrap();
+        assert!(recovered.is_empty(), "{} task(s) left", recovered.len());
+    }
+
+    #[tokio::test]
+    async fn nested_tools_are_discovered_under_namespaced_names() {
+        let tools_dir = test_dir("namespaced-discovery");
+        for (path, source) in [
+            ("Spin.luau", "return 1"),
+            ("Lighting/SetAmbient.luau", "return 2"),
+            ("Lighting/Sky/Night.luau", "return 3"),
+            ("Lighting/Dim.luau", "return 4"),
+            // Also named `Lighting.Dim`; the folder sorts first, so its file always wins.
+            ("Lighting.Dim.luau", "return 5"),
+            ("Lighting/notes.md", "not a tool"),
+            ("Lighting/.Draft.luau", "return 6"),
+            (".hidden/Secret.luau", "return 7"),
+        ] {
+            let path = tools_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        let tools = discover_luau_tools(&tools_dir);
+        let mut names: Vec<&str> = tools.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "Lighting.Dim",
+                "Lighting.SetAmbient",
+                "Lighting.Sky.Night",
+                "Spin"
+            ]
+        );
+        assert_eq!(
+            tools["Lighting.Dim"].file_path,
+            tools_dir.join("Lighting").join("Dim.luau")
+        );
+
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.discovered_luau_tools = ToolRegistry::new(tools);
+        })
+        .await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let missing = error_text(
+            mcp.execute_discovered_luau_tool(
+                "SetAmbient".to_string(),
+                "{}".to_string(),
+                None,
+                None,
+                None,
+            )
+            .await,
+        );
+        assert_eq!(missing, "Luau tool 'SetAmbient' not found.");
+        let call = tokio::spawn(async move {
+            mcp.execute_discovered_luau_tool(
+                "Lighting.Sky.Night".to_string(),
+                "{}".to_string(),
+                None,
+                None,
+                None,
+            )
+            .await
+        });
+        let (_, tool, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("night")]),
+        )
+        .await;
+        assert_eq!(tool, "ExecuteLuauByName");
+        assert!(
+            chunk.contains(r#"tool_name = "Lighting.Sky.Night""#),
+            "{}",
+            chunk
+        );
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "night");
+    }
+
+    #[tokio::test]
+    async fn a_batch_runs_its_steps_in_one_task_and_reports_a_failed_step() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let steps = || {
+            vec![
+                serde_json::json!({ "name": "get_place_info" }),
+                serde_json::json!({
+                    "name": "get_attributes",
+                    "arguments": { "instance_path": "Workspace.Missing" },
+                }),
+                serde_json::json!({ "name": "get_place_info", "arguments": {} }),
+            ]
+        };
+
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.batch(steps(), None, None, None, None).await });
+        let succeeded = serde_json::json!({ "results": [
+            { "step": 1, "tool": "GetPlaceInfo", "is_error": false, "text": "Place 1" },
+            { "step": 2, "tool": "GetAttributes", "is_error": false, "text": "{}" },
+            { "step": 3, "tool": "GetPlaceInfo", "is_error": false, "text": "Place 1" },
+        ], "stopped_early": false });
+        let (_, tool, chunk) = answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text(succeeded.to_string())]),
+        )
+        .await;
+        assert_eq!(tool, "Batch");
+        assert!(
+            chunk.contains(concat!(
+                r#"steps = { { GetPlaceInfo = {} }, { GetAttributes = { instance_path = "Workspace.Missing" } }, { GetPlaceInfo = {} } }, "#,
+                "stop_on_error = true",
+            )),
+            "{}",
+            chunk
+        );
+        let result = call.await.unwrap().unwrap();
+        assert_eq!(result.is_error, Some(false));
+        assert_eq!(result_text(&result), succeeded.to_string());
+
+        // The plugin stops after the failing step 2 and marks the whole result as an error.
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.batch(steps(), None, None, None, None).await });
+        let failed = serde_json::json!({ "results": [
+            { "step": 1, "tool": "GetPlaceInfo", "is_error": false, "text": "Place 1" },
+            { "step": 2, "tool": "GetAttributes", "is_error": true, "text": "Instance not found" },
+        ], "stopped_early": true });
+        answer_next(
+            &plugin,
+            CallToolResult::error(vec![Content::text(failed.to_string())]),
+        )
+        .await;
+        let result = call.await.unwrap().unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(result_text(&result), failed.to_string());
+
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call =
+            tokio::spawn(async move { mcp.batch(steps(), Some(false), None, None, None).await });
+        let (_, _, chunk) = answer_next(
+            &plugin,
+            CallToolResult::error(vec![Content::text(failed.to_string())]),
+        )
+        .await;
+        assert!(chunk.contains("stop_on_error = false"), "{}", chunk);
+        call.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_batch_rejects_nested_and_malformed_steps() {
+        let server = offline_server(ServerOptions::default());
+        for (steps, expected) in [
+            (vec![], "'steps' must list between 1 and 50 tasks."),
+            (
+                vec![serde_json::json!({ "name": "batch", "arguments": { "steps": [] } })],
+                "Step 1: batches can't be nested.",
+            ),
+            (
+                vec![
+                    serde_json::json!({ "name": "get_place_info" }),
+                    serde_json::json!({ "name": "undo", "arguments": { "steps": 1 } }),
+                ],
+                "Step 2: undo, redo and set_waypoint can't run inside a batch, which is itself one undo step.",
+            ),
+            (
+                vec![serde_json::json!({ "name": "list_luau_tools" })],
+                "Step 1: 'list_luau_tools' is answered by the server without a task in Studio, so it can't be batched.",
+            ),
+        ] {
+            assert_eq!(
+                error_text(server.batch(steps, None, None, None, None).await),
+                expected
+            );
+        }
+        for (step, expected) in [
+            (
+                serde_json::json!({ "GetPlaceInfo": {} }),
+                "Step 1: not a tool call:",
+            ),
+            (
+                serde_json::json!({ "name": "teleport" }),
+                "Step 1: tool not found",
+            ),
+        ] {
+            let rejected = error_text(server.batch(vec![step], None, None, None, None).await);
+            assert!(rejected.starts_with(expected), "{}", rejected);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_batch_step_gets_the_checks_of_its_tool() {
+        let tools_dir = test_dir("batch_checks");
+        fs::write(tools_dir.join("Spin.luau"), "return function() end").unwrap();
+        let (sm_command_tx, _) = mpsc::channel(1);
+        let server = RBXStudioServer::new(
+            sm_command_tx,
+            ToolRegistry::new(discover_luau_tools(&tools_dir)),
+            ServerOptions::default(),
+        );
+        for (step, expected) in [
+            (
+                serde_json::json!({ "name": "delete_tagged", "arguments": { "tag": "MCPCreated" } }),
+                "Step 1: delete_tagged is disabled; start the server with --allow-dangerous to enable it.",
+            ),
+            (
+                serde_json::json!({ "name": "delete_instance", "arguments": { "instance_path": "game.Workspace" } }),
+                "Step 1: Refusing to delete 'game.Workspace': the DataModel, services and Terrain can't be deleted.",
+            ),
+            (
+                serde_json::json!({ "name": "set_service_enabled", "arguments": { "service": "Players", "property": "Parent", "enabled": false } }),
+                "Step 1: 'Players.Parent' is not a togglable service property.",
+            ),
+            (
+                serde_json::json!({ "name": "set_workspace_property", "arguments": { "property_name": "Parent", "value": "nil" } }),
+                "Step 1: 'Parent' is not an allowed Workspace property;",
+            ),
+            (
+                serde_json::json!({ "name": "execute_discovered_luau_tool", "arguments": { "tool_name": "DeleteTagged", "tool_arguments_luau": "return {}" } }),
+                "Step 1: Luau tool 'DeleteTagged' not found.",
+            ),
+            (
+                serde_json::json!({ "name": "execute_discovered_luau_tool", "arguments": { "tool_name": "Spin", "tool_arguments_luau": "return {}", "source": "return function() end" } }),
+                "Step 1: a tool's 'source' can't be supplied; the server ships it from the tool's file.",
+            ),
+        ] {
+            let rejected = error_text(server.batch(vec![step], None, None, None, None).await);
+            assert!(rejected.starts_with(expected), "{}", rejected);
+        }
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_batch_step_is_encoded_as_its_tool_encodes_it() {
+        let tools_dir = test_dir("batch_encoding");
+        fs::write(tools_dir.join("Base.luau"), "_G.base = true").unwrap();
+        fs::write(
+            tools_dir.join("Spin.luau"),
+            "--- @preamble Base\nreturn function() end",
+        )
+        .unwrap();
+        let tools = discover_luau_tools(&tools_dir);
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.discovered_luau_tools = ToolRegistry::new(tools)
+        })
+        .await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let steps = vec![
+            serde_json::json!({ "name": "create_instance", "arguments": {
+                "class_name": "Part",
+                "parent_path": "Workspace",
+                "properties": { "Text": "os.exit()" },
+            } }),
+            serde_json::json!({ "name": "execute_discovered_luau_tool", "arguments": {
+                "tool_name": "Spin",
+                "tool_arguments_luau": "return {}",
+            } }),
+        ];
+        let call = tokio::spawn(async move { mcp.batch(steps, None, None, None, None).await });
+        let (_, tool, chunk) = answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("{}")]),
+        )
+        .await;
+        assert_eq!(tool, "Batch");
+        // The property is a string, not the Luau it looks like, and Spin's preamble runs first.
+        assert!(chunk.contains(r#"["Text"] = "os.exit()""#), "{}", chunk);
+        assert!(
+            chunk.contains("-- preamble: Base\n(function()\n_G.base = true\nend)()\nreturn {}"),
+            "{}",
+            chunk
+        );
+        call.await.unwrap().unwrap();
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn healthz_reports_the_queue_and_whether_a_plugin_is_polling() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let url = format!("http://127.0.0.1:{}/healthz", server.port);
+        let healthz = || async {
+            let response = reqwest::get(&url).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            response.json::<serde_json::Value>().await.unwrap()
+        };
+        let idle = healthz().await;
+        assert_eq!(idle["queue_len"], 0);
+        assert_eq!(idle["pending_tasks"], 0);
+        assert_eq!(idle["client_waiting"], false);
+
+        let mut calls = Vec::new();
+        let mcp = server.mcp_server(ServerOptions::default());
+        calls.push(tokio::spawn(async move { mcp.get_place_info().await }));
+        let (running_id, _, _) = poll_task(&plugin).await;
+        for _ in 0..2 {
+            let mcp = server.mcp_server(ServerOptions::default());
+            calls.push(tokio::spawn(async move { mcp.get_place_info().await }));
+        }
+        let mut stats = healthz().await;
+        for _ in 0..50 {
+            if stats["pending_tasks"] == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stats = healthz().await;
+        }
+        assert_eq!(stats["queue_len"], 2, "{}", stats);
+        assert_eq!(stats["pending_tasks"], 3, "{}", stats);
+        assert_eq!(stats["client_waiting"], false, "{}", stats);
+        assert_eq!(stats["sessions"], 1, "{}", stats);
+        assert_eq!(stats["dispatch_enabled"], true, "{}", stats);
+
+        // Drain the queue, then leave a poll open.
+        let done = CallToolResult::success(vec![Content::text("Place 1")]);
+        plugin.submit(running_id, &done).await.unwrap();
+        answer_next(&plugin, done.clone()).await;
+        answer_next(&plugin, done).await;
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+        let waiting_plugin = server.plugin();
+        let _open_poll = tokio::spawn(async move { waiting_plugin.poll().await });
+        let mut stats = healthz().await;
+        for _ in 0..50 {
+            if stats["client_waiting"] == true {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stats = healthz().await;
+        }
+        assert_eq!(stats["client_waiting"], true, "{}", stats);
+        assert_eq!(
+            (stats["queue_len"].clone(), stats["pending_tasks"].clone()),
+            (0.into(), 0.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn plugin_routes_require_the_configured_auth_token() {
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.auth_token = Some("s3cret".into());
+        })
+        .await;
+        let url = format!("http://127.0.0.1:{}/mcp", server.port);
+        let client = reqwest::Client::new();
+        for authorization in [
+            None,
+            Some("Bearer wrong"),
+            Some("s3cret"),
+            Some("Bearer s3cret!"),
+        ] {
+            let mut request = client
+                .post(&url)
+                .header(PLUGIN_VERSION_HEADER, PLUGIN_PROTOCOL_VERSION.to_string())
+                .header("X-MCP-Task-ID", Uuid::new_v4().to_string())
+                .body("{}");
+            if let Some(authorization) = authorization {
+                request = request.header("Authorization", authorization);
+            }
+            let response = request.send().await.unwrap();
+            assert_eq!(
+                response.status(),
+                reqwest::StatusCode::UNAUTHORIZED,
+                "{:?}",
+                authorization
+            );
+        }
+        // Readiness checks don't need the token.
+        let healthz = reqwest::get(format!("http://127.0.0.1:{}/healthz", server.port))
+            .await
+            .unwrap();
+        assert_eq!(healthz.status(), reqwest::StatusCode::OK);
+
+        // The plugin sends the token and gets its task.
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        answer_next(
+            &server.plugin(),
+            CallToolResult::success(vec![Content::text("Place 1")]),
+        )
+        .await;
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "Place 1");
+    }
+
+    #[tokio::test]
+    async fn without_an_auth_token_plugin_routes_stay_open() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let plugin = MockPlugin::new(server.port, None).unwrap();
+        answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text("Place 1")]),
+        )
+        .await;
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "Place 1");
+    }
+
+    #[tokio::test]
+    async fn a_websocket_plugin_receives_pushed_tasks_and_answers_over_the_socket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message as WsMessage};
+
+        let server = TestServer::start().await;
+        let mut request = format!("ws://127.0.0.1:{}/ws?session=ws-plugin", server.port)
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            PLUGIN_VERSION_HEADER,
+            PLUGIN_PROTOCOL_VERSION.to_string().parse().unwrap(),
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let chunk = match socket.next().await.unwrap().unwrap() {
+            WsMessage::Text(chunk) => chunk.to_string(),
+            other => panic!("expected a task frame, got {:?}", other),
+        };
+        assert!(chunk.contains("GetPlaceInfo = {}"), "{}", chunk);
+        let task_id = crate::mock_plugin::task_id_from_chunk(&chunk).unwrap();
+
+        let result = CallToolResult::success(vec![Content::text("Place over ws")]);
+        let frame = format!("{}\n{}", task_id, serde_json::to_string(&result).unwrap());
+        socket.send(WsMessage::Text(frame.into())).await.unwrap();
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "Place over ws");
+        let sessions = sessions_of(&server.sm_command_tx).await;
+        assert!(
+            sessions
+                .iter()
+                .any(|session| session.session_id == "ws-plugin"),
+            "{:?}",
+            sessions
+        );
+    }
+
+    #[tokio::test]
+    async fn garbage_result_json_fails_the_caller_promptly() {
+        let server = TestServer::start().await;
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let (task_id, _, _) = poll_task(&server.plugin()).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}/mcp", server.port))
+            .header(PLUGIN_VERSION_HEADER, PLUGIN_PROTOCOL_VERSION.to_string())
+            .header("X-MCP-Task-ID", task_id.to_string())
+            .body("<html>Studio crashed</html>")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let error = tokio::time::timeout(Duration::from_secs(5), call)
+            .await
+            .expect("the caller isn't left waiting for the execution timeout")
+            .unwrap()
+            .unwrap_err();
+        assert!(
+            error.message.starts_with("Malformed plugin response:"),
+            "{}",
+            error.message
+        );
+        assert!(
+            error
+                .message
+                .contains(r#"(body starts: "<html>Studio crashed</html>")"#),
+            "{}",
+            error.message
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_tasks_are_reaped_after_the_ttl() {
+        let ttl = MIN_PENDING_TASK_TTL;
+        let sm_command_tx = start_state_manager(StateManager::new().with_task_ttl(Duration::ZERO));
+        // Taken by a plugin that never answers, and with a caller that is still listening.
+        let (running, running_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let mut running_caller = send_task(&sm_command_tx, running, None).await;
+        let poll = send_poll(&sm_command_tx, "silent").await;
+        assert_eq!(polled_task_id(poll.await.unwrap()), running_id);
+        // Queued, with a caller whose future was dropped.
+        let (queued, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        drop(send_task(&sm_command_tx, queued, None).await);
+        assert_eq!(stats_of(&sm_command_tx).await.pending_tasks, 2);
+
+        tokio::time::sleep(ttl - PENDING_TASK_REAP_INTERVAL - Duration::from_secs(1)).await;
+        assert!(running_caller.try_recv().is_err(), "reaped before the TTL");
+        assert_eq!(stats_of(&sm_command_tx).await.pending_tasks, 2);
+
+        tokio::time::sleep(PENDING_TASK_REAP_INTERVAL * 2).await;
+        let error = running_caller.await.unwrap().unwrap_err();
+        assert_eq!(
+            error.message,
+            format!(
+                "The task was dropped after {}s without a result.",
+                ttl.as_secs()
+            )
+        );
+        let stats = stats_of(&sm_command_tx).await;
+        assert_eq!((stats.pending_tasks, stats.queue_len), (0, 0));
+        assert!(
+            !send_result(&sm_command_tx, running_id, "late").await,
+            "a late result finds nothing to resolve"
+        );
+    }
+
+    #[tokio::test]
+    async fn list_luau_tools_describes_each_discovered_tool() {
+        let tools_dir = test_dir("list-luau-tools");
+        fs::write(
+            tools_dir.join("Spin.luau"),
+            "--- @description Spins a part\n--- around its Y axis.\n--- @arg speed:number Turns per second\n--- @readonly\nreturn 1",
+        )
+        .unwrap();
+        fs::create_dir(tools_dir.join("Lighting")).unwrap();
+        fs::write(
+            tools_dir.join("Lighting").join("Dim.luau"),
+            "--- @preamble Spin\nreturn 2",
+        )
+        .unwrap();
+        let (sm_command_tx, _) = mpsc::channel(1);
+        let server = RBXStudioServer::new(
+            sm_command_tx,
+            ToolRegistry::new(discover_luau_tools(&tools_dir)),
+            ServerOptions::default(),
+        );
+
+        let result = server.list_luau_tools().await.unwrap();
+        let listing: serde_json::Value = serde_json::from_str(&result_text(&result)).unwrap();
+        assert_eq!(
+            listing,
+            serde_json::json!({
+                "tools": [
+                    {
+                        "name": "Lighting.Dim",
+                        "file_path": tools_dir.join("Lighting").join("Dim.luau"),
+                        "description": null,
+                        "arg_schema": null,
+                        "readonly": false,
+                        "preambles": ["Spin"],
+                    },
+                    {
+                        "name": "Spin",
+                        "file_path": tools_dir.join("Spin.luau"),
+                        "description": "Spins a part around its Y axis.",
+                        "arg_schema": {
+                            "type": "object",
+                            "properties": {
+                                "speed": { "type": "number", "description": "Turns per second" }
+                            },
+                            "required": ["speed"],
+                        },
+                        "readonly": true,
+                        "preambles": [],
+                    },
+                ],
+                "read_only_mode": false,
+            })
+        );
+    }
+
+    #[test]
+    fn a_well_formed_block_header_gives_a_description_and_arg_schema() {
+        let header = parse_tool_header(concat!(
+            "--[[\n",
+            "@description Sets the ambient colour\n",
+            "of Lighting.\n",
+            "@arg color:table {r, g, b} from 0 to 1\n",
+            "@arg fade?:number Seconds to fade over\n",
+            "]]\n",
+            "local args = ...\n",
+            "--- @readonly\n",
+        ));
+        assert!(header.warnings.is_empty(), "{:?}", header.warnings);
+        assert_eq!(
+            header.description.as_deref(),
+            Some("Sets the ambient colour of Lighting.")
+        );
+        assert!(
+            !header.readonly,
+            "tags after the first line of code are ignored"
+        );
+        assert_eq!(
+            tool_arg_schema(&header.args),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "color": { "type": "object", "description": "{r, g, b} from 0 to 1" },
+                    "fade": { "type": "number", "description": "Seconds to fade over" },
+                },
+                "required": ["color"],
+            })
+        );
+    }
+
+    #[test]
+    fn a_tool_without_a_header_has_no_metadata() {
+        for source in ["", "return function() end", "-- plain comment\nreturn 1"] {
+            let header = parse_tool_header(source);
+            assert!(header.description.is_none(), "{:?}", source);
+            assert!(header.args.is_empty(), "{:?}", source);
+            assert!(header.warnings.is_empty(), "{:?}", source);
+            assert!(
+                !header.readonly && header.preambles.is_empty(),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn a_malformed_header_keeps_what_it_can_and_warns_about_the_rest() {
+        let header = parse_tool_header(concat!(
+            "--- @description Still described\n",
+            "--- @arg top-speed:number\n",
+            "--- @arg speed:float\n",
+            "--- @arg count:integer\n",
+            "--- @color red\n",
+            "return 1",
+        ));
+        assert_eq!(header.description.as_deref(), Some("Still described"));
+        assert_eq!(header.args.len(), 1);
+        assert_eq!(header.args[0].name, "count");
+        assert_eq!(header.warnings.len(), 3, "{:?}", header.warnings);
+        assert!(header.warnings[0].starts_with("ignoring malformed '@arg top-speed:number'"));
+        assert!(header.warnings[1].starts_with("ignoring malformed '@arg speed:float'"));
+        assert_eq!(
+            header.warnings[2],
+            "ignoring unknown header tag '@color red'"
+        );
+
+        let unclosed = parse_tool_header("--[[\n@description Never closed\nreturn 1");
+        assert!(unclosed.description.is_none());
+        assert_eq!(
+            unclosed.warnings,
+            ["the leading --[[ metadata block is never closed; ignoring it"]
+        );
+    }
+
+    #[test]
+    fn set_instance_properties_encodes_vectors_colours_and_scalars() {
+        let luau = luau_for(ToolArgumentValues::SetInstanceProperties {
+            instance_path: "Workspace.Part".to_string(),
+            properties: serde_json::json!({
+                "Position": { "x": 1, "y": 2.5, "z": -3 },
+                "Color": { "r": 1, "g": 0.5, "b": 0 },
+                "Transparency": 0.25,
+                "Anchored": true,
+                "Name": "Door \"A\"",
+                "Material": "Enum.Material.Neon",
+            }),
+        });
+        assert_eq!(
+            luau,
+            concat!(
+                r#"SetInstanceProperties = { instance_path = "Workspace.Part", properties = { "#,
+                r#"["Position"] = Vector3.new(1, 2.5, -3), ["Color"] = Color3.new(1, 0.5, 0), "#,
+                r#"["Transparency"] = 0.25, ["Anchored"] = true, ["Name"] = "Door \"A\"", "#,
+                r#"["Material"] = "Enum.Material.Neon" } }"#
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn streamed_chunks_are_assembled_ahead_of_the_final_result() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.run_command(
+                "for i = 1, 2 do print(i) end".to_string(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+        });
+        let (task_id, _, _) = poll_task(&plugin).await;
+        let stream = |task_id: Uuid, chunk: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://127.0.0.1:{}/mcp/stream", server.port))
+                .header(PLUGIN_VERSION_HEADER, PLUGIN_PROTOCOL_VERSION.to_string())
+                .header("X-MCP-Task-ID", task_id.to_string())
+                .body(chunk)
+                .send()
+        };
+        for chunk in ["step 1\n", "step 2\n"] {
+            let response = stream(task_id, chunk).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        }
+        let unknown = stream(Uuid::new_v4(), "lost\n").await.unwrap();
+        assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(!call.is_finished(), "chunks don't resolve the call");
+
+        let status = plugin
+            .submit(
+                task_id,
+                &CallToolResult::success(vec![Content::text("finished")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+        let result = call.await.unwrap().unwrap();
+        assert_eq!(
+            result_text(&result),
+            "finished\nStreamed output:\nstep 1\nstep 2\n"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_tasks_are_handed_out_by_priority_then_arrival() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let mut ids = HashMap::new();
+        let mut callers = Vec::new();
+        for (label, priority) in [
+            ("low", TaskPriority::Low),
+            ("normal 1", TaskPriority::Normal),
+            ("high 1", TaskPriority::High),
+            ("normal 2", TaskPriority::Normal),
+            ("high 2", TaskPriority::High),
+        ] {
+            let (task, task_id) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+            callers.push(send_task(&sm_command_tx, task.with_priority(priority), None).await);
+            ids.insert(task_id, label);
+        }
+        let mut delivered = Vec::new();
+        for _ in 0..ids.len() {
+            let task_id = polled_task_id(send_poll(&sm_command_tx, "studio").await.await.unwrap());
+            delivered.push(ids[&task_id]);
+        }
+        assert_eq!(
+            delivered,
+            ["high 1", "high 2", "normal 1", "normal 2", "low"]
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_fails_every_outstanding_caller() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let (delivered, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let delivered_caller = send_task(&sm_command_tx, delivered, None).await;
+        polled_task_id(send_poll(&sm_command_tx, "studio").await.await.unwrap());
+        let (queued, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let queued_caller = send_task(&sm_command_tx, queued, None).await;
+
+        assert_eq!(shutdown_state_manager(&sm_command_tx).await, 2);
+        for caller in [delivered_caller, queued_caller] {
+            let error = caller.await.unwrap().unwrap_err();
+            assert_eq!(error.message, "The server is shutting down.");
+        }
+        assert!(
+            sm_command_tx.is_closed(),
+            "the StateManager stops after shutting down"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_transient_plugin_error_is_retried_with_a_fresh_task_id() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions {
+            max_retries: 2,
+            ..ServerOptions::default()
+        });
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        let mut task_ids = Vec::new();
+        for _ in 0..2 {
+            let (task_id, ..) = answer_next(
+                &plugin,
+                CallToolResult::error(vec![Content::text("Studio is compiling, try later")]),
+            )
+            .await;
+            task_ids.push(task_id);
+        }
+        let (task_id, ..) = answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text("Place 1")]),
+        )
+        .await;
+        task_ids.push(task_id);
+
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "Place 1");
+        task_ids.dedup();
+        assert_eq!(task_ids.len(), 3, "each attempt is a new task");
+    }
+
+    #[tokio::test]
+    async fn other_plugin_errors_and_exhausted_retries_fail_the_call() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        for (message, max_retries) in [("Part not found", 2), ("Studio is busy", 0)] {
+            let mcp = server.mcp_server(ServerOptions {
+                max_retries,
+                ..ServerOptions::default()
+            });
+            let call = tokio::spawn(async move { mcp.get_place_info().await });
+            answer_next(&plugin, CallToolResult::error(vec![Content::text(message)])).await;
+
+            assert_eq!(error_text(call.await.unwrap()), message);
+        }
+    }
+
+    #[test]
+    fn history_tools_format_their_luau_tables() {
+        assert_eq!(
+            luau_for(ToolArgumentValues::Undo { steps: 3 }),
+            "Undo = { steps = 3 }"
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::Redo { steps: 1 }),
+            "Redo = { steps = 1 }"
+        );
+        assert_eq!(
+            luau_for(ToolArgumentValues::SetWaypoint {
+                name: "Before lighting pass".to_string()
+            }),
+            r#"SetWaypoint = { name = "Before lighting pass" }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn history_tools_reject_bad_arguments_before_dispatch() {
+        let server = offline_server(ServerOptions::default());
+        for steps in [Some(0), Some(MAX_HISTORY_STEPS + 1)] {
+            assert_eq!(
+                error_text(server.undo(steps).await),
+                "'steps' must be between 1 and 50."
+            );
+            assert_eq!(
+                error_text(server.redo(steps).await),
+                "'steps' must be between 1 and 50."
+            );
+        }
+        assert_eq!(
+            error_text(server.set_waypoint("  ".to_string()).await),
+            "'name' must not be empty."
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_repeated_idempotency_key_returns_the_cached_result_without_a_new_task() {
+        let sm_command_tx = start_state_manager(StateManager::new());
+        let keyed = |args: ToolArgumentValues| {
+            ToolArguments::new_with_id(args)
+                .0
+                .with_idempotency_key(Some("insert-tree".to_string()))
+        };
+        let insert = || ToolArgumentValues::InsertModel {
+            query: "tree".to_string(),
+            parent_path: None,
+            position: None,
+        };
+
+        let first = send_task(&sm_command_tx, keyed(insert()), None).await;
+        let task_id = polled_task_id(send_poll(&sm_command_tx, "studio").await.await.unwrap());
+        let early = send_task(&sm_command_tx, keyed(insert()), None).await;
+        assert!(early
+            .await
+            .unwrap()
+            .unwrap_err()
+            .message
+            .contains("hasn't finished yet"));
+        assert!(send_result(&sm_command_tx, task_id, "Inserted Tree").await);
+        assert_eq!(
+            result_text(&first.await.unwrap().unwrap().result),
+            "Inserted Tree"
+        );
+
+        let repeat = send_task(&sm_command_tx, keyed(insert()), None).await;
+        assert_eq!(
+            result_text(&repeat.await.unwrap().unwrap().result),
+            "Inserted Tree"
+        );
+        let mut poll = send_poll(&sm_command_tx, "studio").await;
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), &mut poll)
+                .await
+                .is_err(),
+            "the repeat must not be queued"
+        );
+
+        let other_tool = send_task(
+            &sm_command_tx,
+            keyed(ToolArgumentValues::GetPlaceInfo {}),
+            None,
+        )
+        .await;
+        assert_eq!(
+            other_tool.await.unwrap().unwrap_err().message,
+            "Idempotency key 'insert-tree' was already used for InsertModel, not GetPlaceInfo."
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_count_a_task_dispatched_and_completed() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let url = format!("http://127.0.0.1:{}/metrics", server.port);
+        let scrape = || async {
+            let response = reqwest::get(&url).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            response.text().await.unwrap()
+        };
+        let before = scrape().await;
+        assert!(
+            before.contains("rbx_studio_mcp_tasks_dispatched_total 0\n"),
+            "{}",
+            before
+        );
+
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move { mcp.get_place_info().await });
+        answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text("Place 1")]),
+        )
+        .await;
+        call.await.unwrap().unwrap();
+
+        let after = scrape().await;
+        for line in [
+            "rbx_studio_mcp_tasks_dispatched_total 1\n",
+            "rbx_studio_mcp_tasks_completed_total 1\n",
+            "rbx_studio_mcp_pending_tasks 0\n",
+            "rbx_studio_mcp_poll_wait_seconds_count 1\n",
+        ] {
+            assert!(after.contains(line), "missing {:?} in\n{}", line, after);
+        }
+    }
+
+    #[test]
+    fn run_command_wraps_the_command_to_pack_every_return_value() {
+        let wrapped = wrap_run_command("local t = {1, 2}\nreturn t, workspace");
+        assert_eq!(
+            wrapped,
+            format!(
+                "local __mcp_results = table.pack((function(...) local t = {{1, 2}}\nreturn t, workspace\nend)(...))\n{}",
+                RUN_COMMAND_RETURN_ENCODER
+            )
+        );
+        // Instances are encoded as their path, and the values come back as one JSON array.
+        assert!(RUN_COMMAND_RETURN_ENCODER.contains("return value:GetFullName()"));
+        assert!(wrapped.ends_with(r#"return "[" .. table.concat(__mcp_parts, ",") .. "]""#));
+    }
+
+    #[tokio::test]
+    async fn run_command_passes_a_returned_table_through_as_json() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.run_command(
+                "return { name = \"Tree\", size = {1, 2} }, workspace.Tree".to_string(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .await
+        });
+        let returned =
+            r#"{"output":"","return_values":[{"name":"Tree","size":[1,2]},"Workspace.Tree"]}"#;
+        let (_, tool, chunk) = answer_next(
+            &plugin,
+            CallToolResult::success(vec![Content::text(returned)]),
+        )
+        .await;
+        assert_eq!(tool, "RunCommand");
+        assert!(
+            chunk.contains("table.pack((function(...) return { name"),
+            "{}",
+            chunk
+        );
+
+        let result: serde_json::Value =
+            serde_json::from_str(&result_text(&call.await.unwrap().unwrap())).unwrap();
+        assert_eq!(
+            result["return_values"][0]["size"],
+            serde_json::json!([1, 2])
+        );
+        assert_eq!(result["return_values"][1], "Workspace.Tree");
+    }
+
+    #[test]
+    fn delete_instance_formats_its_path_and_recursive_flag() {
+        for recursive in [false, true] {
+            assert_eq!(
+                luau_for(ToolArgumentValues::DeleteInstance {
+                    instance_path: "Workspace.Old Map".to_string(),
+                    recursive,
+                }),
+                format!(
+                    r#"DeleteInstance = {{ instance_path = "Workspace.Old Map", recursive = {} }}"#,
+                    recursive
+                )
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_instance_refuses_protected_and_malformed_paths_before_dispatch() {
+        let server = offline_server(ServerOptions::default());
+        for path in ["game", "Workspace", "game.Lighting", "workspace/Terrain"] {
+            assert_eq!(
+                error_text(server.delete_instance(path.to_string(), Some(true)).await),
+                format!(
+                    "Refusing to delete '{}': the DataModel, services and Terrain can't be deleted.",
+                    path
+                )
+            );
+        }
+        assert_eq!(
+            error_text(server.delete_instance(" ".to_string(), None).await),
+            "Instance path must not be empty."
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_instance_reports_a_path_that_does_not_resolve() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions::default());
+        let call = tokio::spawn(async move {
+            mcp.delete_instance("Workspace.Missing".to_string(), None)
+                .await
+        });
+        let not_found = "No instance at 'Workspace.Missing' to delete: Missing not found";
+        let (_, tool, chunk) = answer_next(
+            &plugin,
+            CallToolResult::error(vec![Content::text(not_found)]),
+        )
+        .await;
+        assert_eq!(tool, "DeleteInstance");
+        assert!(chunk.contains("recursive = false"), "{}", chunk);
+        assert_eq!(error_text(call.await.unwrap()), not_found);
+    }
+
+    #[test]
+    fn instance_paths_are_normalized_to_dotted_form() {
+        for (path, expected) in [
+            ("game.Workspace.Map", "Workspace.Map"),
+            ("Game.Workspace", "Workspace"),
+            ("Workspace/Map/Spawn", "Workspace.Map.Spawn"),
+            ("Workspace\\Map", "Workspace.Map"),
+            ("  Lighting  ", "Lighting"),
+            (
+                "game/ReplicatedStorage/Remotes",
+                "ReplicatedStorage.Remotes",
+            ),
+            ("gameplay.Folder", "gameplay.Folder"),
+        ] {
+            assert_eq!(normalize_instance_path(path), expected, "{}", path);
+        }
+        assert_eq!(
+            luau_for(ToolArgumentValues::FindInstances {
+                root_path: "game/Workspace/Map".to_string(),
+                class_name: Some("Part".to_string()),
+                name_pattern: None,
+                limit: 5,
+            }),
+            r#"FindInstances = { root_path = "Workspace.Map", class_name = "Part", name_pattern = nil, limit = 5 }"#
+        );
+    }
+
+    #[tokio::test]
+    async fn find_instances_normalizes_its_root_and_passes_no_matches_through() {
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions::default());
+        assert!(error_text(
+            mcp.find_instances(
+                None,
+                Some("Part".to_string()),
+                Some("game..Map".to_string())
+            )
+            .await
+        )
+        .starts_with("'root_path': Instance path '.Map' has an empty segment"));
+
+        let call = tokio::spawn(async move {
+            mcp.find_instances(
+                Some("^Door".to_string()),
+                None,
+                Some("game\\Workspace\\Map".to_string()),
+            )
+            .await
+        });
+        let none = r#"{"root_path":"Workspace.Map","matches":[],"truncated":false}"#;
+        let (_, tool, chunk) =
+            answer_next(&plugin, CallToolResult::success(vec![Content::text(none)])).await;
+        assert_eq!(tool, "FindInstances");
+        assert!(
+            chunk.contains(r#"root_path = "Workspace.Map""#),
+            "{}",
+            chunk
+        );
+
+        let result = call.await.unwrap().unwrap();
+        assert_ne!(result.is_error, Some(true));
+        let found: serde_json::Value = serde_json::from_str(&result_text(&result)).unwrap();
+        assert_eq!(found["matches"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_rejects_the_next_dispatch_until_a_task_is_taken() {
+        let sm_command_tx = start_state_manager(StateManager::new().with_max_queued_tasks(2));
+        let mut callers = Vec::new();
+        for _ in 0..2 {
+            let (task, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+            callers.push(send_task(&sm_command_tx, task, None).await);
+        }
+        let (overflow, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let error = send_task(&sm_command_tx, overflow, None)
+            .await
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            error.message,
+            "The server is busy: 2 tasks are already waiting for the Studio plugin. Retry once some have finished."
+        );
+        assert_eq!(error.data.unwrap()["queue_full"], true);
+
+        polled_task_id(send_poll(&sm_command_tx, "studio").await.await.unwrap());
+        let (accepted, accepted_id) =
+            ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let accepted_caller = send_task(&sm_command_tx, accepted, None).await;
+        let mut delivered = Vec::new();
+        for _ in 0..2 {
+            delivered.push(polled_task_id(
+                send_poll(&sm_command_tx, "studio").await.await.unwrap(),
+            ));
+        }
+        assert!(delivered.contains(&accepted_id));
+        drop((callers, accepted_caller));
+    }
+
+    #[test]
+    fn luau_files_resolve_only_inside_the_allowed_folder() {
+        let dir = test_dir("luau-files");
+        let root = dir.join("scripts");
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join("build").join("walls.luau"), "print('walls')").unwrap();
+        fs::write(dir.join("secret.luau"), "print('secret')").unwrap();
+
+        assert_eq!(
+            resolve_luau_file(&root, "build/walls.luau").unwrap(),
+            fs::canonicalize(root.join("build").join("walls.luau")).unwrap()
+        );
+        assert!(resolve_luau_file(&root, "./build/../build/walls.luau").is_ok());
+        let root_display = fs::canonicalize(&root).unwrap().display().to_string();
+        for path in [
+            "../../etc/passwd",
+            "../secret.luau",
+            "build/../../secret.luau",
+            "/etc/passwd",
+        ] {
+            assert_eq!(
+                resolve_luau_file(&root, path).unwrap_err(),
+                format!(
+                    "'{}' is outside {}; execute_luau_file only reads files in that folder.",
+                    path, root_display
+                )
+            );
+        }
+        assert_eq!(
+            resolve_luau_file(&root, "missing.luau").unwrap_err(),
+            format!("No file 'missing.luau' in {}.", root_display)
+        );
+        assert_eq!(
+            resolve_luau_file(&root, "build").unwrap_err(),
+            "'build' is not a file."
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn execute_luau_file_runs_an_allowed_file_and_is_off_without_a_folder() {
+        assert!(error_text(
+            offline_server(ServerOptions::default())
+                .execute_luau_file("walls.luau".to_string(), None, None)
+                .await
+        )
+        .starts_with("execute_luau_file is disabled"));
+
+        let dir = test_dir("execute-luau-file");
+        fs::write(dir.join("walls.luau"), "return workspace.Walls").unwrap();
+        let server = TestServer::start().await;
+        let plugin = server.plugin();
+        let mcp = server.mcp_server(ServerOptions {
+            luau_files_dir: Some(dir.clone()),
+            ..ServerOptions::default()
+        });
+        let call = tokio::spawn(async move {
+            mcp.execute_luau_file("walls.luau".to_string(), None, None)
+                .await
+        });
+        let (_, tool, chunk) =
+            answer_next(&plugin, CallToolResult::success(vec![Content::text("ok")])).await;
+        assert_eq!(tool, "RunCommand");
+        assert!(chunk.contains("return workspace.Walls"), "{}", chunk);
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "ok");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn every_plugin_route_checks_the_plugin_version() {
+        let server = TestServer::start().await;
+        let client = reqwest::Client::new();
+        let base = format!("http://127.0.0.1:{}", server.port);
+        let unknown_task = Uuid::new_v4().to_string();
+        let plugin_routes = [
+            (reqwest::Method::POST, "/mcp".to_string()),
+            (reqwest::Method::POST, "/mcp/stream".to_string()),
+            (reqwest::Method::GET, "/tools/manifest".to_string()),
+            (reqwest::Method::GET, "/tools/bundle".to_string()),
+            (
+                reqwest::Method::GET,
+                format!("/tasks/{}/status", unknown_task),
+            ),
+            (reqwest::Method::GET, "/ws".to_string()),
+        ];
+        let send = |method: reqwest::Method, path: &str, version: Option<&str>| {
+            // Carrying a task id keeps `/mcp` from long-polling.
+            let mut request = client
+                .request(method, format!("{}{}", base, path))
+                .header("X-MCP-Task-ID", &unknown_task)
+                .body("{}");
+            if let Some(version) = version {
+                request = request.header(PLUGIN_VERSION_HEADER, version);
+            }
+            request.send()
+        };
+        for (method, path) in &plugin_routes {
+            for version in ["0", "2", "not a number"] {
+                let response = send(method.clone(), path, Some(version)).await.unwrap();
+                assert_eq!(
+                    response.status(),
+                    reqwest::StatusCode::UPGRADE_REQUIRED,
+                    "{} {} with version {:?}",
+                    method,
+                    path,
+                    version
+                );
+                let message = response.text().await.unwrap();
+                assert!(message.contains("Reinstall the plugin"), "{}", message);
+            }
+            // The current version and plugins from before the header both get through.
+            for version in [Some("1"), None] {
+                let response = send(method.clone(), path, version).await.unwrap();
+                assert_ne!(
+                    response.status(),
+                    reqwest::StatusCode::UPGRADE_REQUIRED,
+                    "{} {} with version {:?}",
+                    method,
+                    path,
+                    version
+                );
+            }
+        }
+        // Routes that aren't the plugin's don't care which version a caller claims.
+        for path in ["/healthz", "/metrics"] {
+            let response = send(reqwest::Method::GET, path, Some("0")).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_takes_discovered_tool_arguments_as_data_only() {
+        let tools_dir = test_dir("read_only_arguments");
+        fs::write(
+            tools_dir.join("CountParts.luau"),
+            "--- @readonly\nreturn function(args) return args.limit end",
+        )
+        .unwrap();
+        let tools = discover_luau_tools(&tools_dir);
+        let server = TestServer::start_with(StateManager::new(), |state| {
+            state.discovered_luau_tools = ToolRegistry::new(tools)
+        })
+        .await;
+        let plugin = server.plugin();
+        let read_only = ServerOptions {
+            read_only: true,
+            ..ServerOptions::default()
+        };
+
+        let text = error_text(
+            server
+                .mcp_server(read_only.clone())
+                .execute_discovered_luau_tool(
+                    "CountParts".to_string(),
+                    "workspace.Baseplate:Destroy() return { limit = 1 }".to_string(),
+                    None,
+                    None,
+                    None,
+                )
+                .await,
+        );
+        assert!(
+            text.contains("must be a JSON object of the tool's arguments, not Luau code"),
+            "{}",
+            text
+        );
+
+        let mcp = server.mcp_server(read_only);
+        let call = tokio::spawn(async move {
+            mcp.execute_discovered_luau_tool(
+                "CountParts".to_string(),
+                r#"{"limit": 5}"#.to_string(),
+                None,
+                None,
+                None,
+            )
+            .await
+        });
+        let (_, tool, chunk) =
+            answer_next(&plugin, CallToolResult::success(vec![Content::text("5")])).await;
+        assert_eq!(tool, "ExecuteLuauByName");
+        assert!(
+            chunk.contains(r#"arguments_luau = [[return { ["limit"] = 5 }]]"#),
+            "{}",
+            chunk
+        );
+        assert_eq!(result_text(&call.await.unwrap().unwrap()), "5");
+        fs::remove_dir_all(&tools_dir).unwrap();
+    }
+
+    #[test]
+    fn shipped_tool_source_comes_only_from_the_server() {
+        let task: ToolArgumentValues = serde_json::from_value(serde_json::json!({
+            "ExecuteLuauByName": {
+                "tool_name": "Spin",
+                "arguments_luau": "return {}",
+                "source": "return function() workspace:ClearAllChildren() end",
+            }
+        }))
+        .unwrap();
+        assert!(
+            matches!(
+                task,
+                ToolArgumentValues::ExecuteLuauByName { source: None, .. }
+            ),
+            "{:?}",
+            task
+        );
+
+        // The plugin refuses shipped source for exactly the modules that run typed tasks.
+        let main = fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("plugin/src/Main.server.luau"),
+        )
+        .unwrap();
+        let table = main
+            .split("local TYPED_TASK_MODULES: { [string]: boolean } = {")
+            .nth(1)
+            .and_then(|rest| rest.split('}').next())
+            .expect("Main.server.luau declares TYPED_TASK_MODULES");
+        let mut refused: Vec<&str> = table
+            .split(',')
+            .filter_map(|entry| entry.trim().strip_suffix(" = true"))
+            .collect();
+        let mut typed = TYPED_TASK_MODULES.to_vec();
+        refused.sort();
+        typed.sort();
+        assert_eq!(refused, typed);
+    }
+
+    #[test]
+    fn set_instance_properties_reports_values_it_cannot_encode() {
+        let luau = luau_for(ToolArgumentValues::SetInstanceProperties {
+            instance_path: "Workspace.Part".to_string(),
+            properties: serde_json::json!({
+                "Anchored": true,
+                "Color": { "$type": "Nope" },
+                "Parent": null,
+            }),
+        });
+        assert!(
+            luau.contains(r#"properties = { ["Anchored"] = true }"#),
+            "{}",
+            luau
+        );
+        assert!(!luau.contains("nil"), "{}", luau);
+        assert!(
+            luau.contains(r#"rejected_properties = { ["Color"] = ""#),
+            "{}",
+            luau
+        );
+        assert!(
+            luau.contains(r#"["Parent"] = "null can't be assigned to a property.""#),
+            "{}",
+            luau
+        );
+    }
+
+    #[tokio::test]
+    async fn set_instance_properties_fails_up_front_on_an_unencodable_value() {
+        let server = offline_server(ServerOptions::default());
+        let body: serde_json::Value = serde_json::from_str(&error_text(
+            server
+                .set_instance_properties(
+                    "Workspace.Part".to_string(),
+                    serde_json::json!({ "Anchored": true, "Color": { "$type": "Nope" } }),
+                )
+                .await,
+        ))
+        .unwrap();
+        let fields: Vec<&str> = body["validation_errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["properties.Color"]);
+    }
+}
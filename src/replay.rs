@@ -0,0 +1,144 @@
+// replay.rs - re-dispatches recorded tasks against a live plugin (`--replay <file>`)
+
+use crate::rbx_studio_server::{
+    dispatch_and_wait, DispatchOptions, StateManagerCommand, ToolArgumentValues, ToolArguments,
+};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use rmcp::model::RawContent;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// One line of a replay file. Either a full task as the server serializes it
+/// (`{"args": {...}, "id": ...}`, the id is ignored and a fresh one assigned)
+/// or just the bare `ToolArgumentValues`.
+#[derive(rmcp::serde::Deserialize)]
+#[serde(untagged)]
+enum ReplayRecord {
+    Task { args: ToolArgumentValues },
+    Bare(ToolArgumentValues),
+}
+
+/// Parses a JSON-lines replay file. Blank lines and lines starting with `#` are skipped.
+pub fn parse_replay(contents: &str) -> Result<Vec<ToolArgumentValues>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let record: ReplayRecord = serde_json::from_str(line)
+                .map_err(|e| eyre!("Line {}: not a recorded task: {}", line_number, e))?;
+            Ok(match record {
+                ReplayRecord::Task { args } | ReplayRecord::Bare(args) => args,
+            })
+        })
+        .collect()
+}
+
+/// Dispatches every task in `path` in order, printing each result to stdout.
+/// Tasks that may modify the place are skipped unless `allow_dangerous` is set.
+/// Each task may run for `execution_timeout` once the plugin picks it up.
+pub async fn run_replay(
+    path: &Path,
+    sm_command_tx: &mpsc::Sender<StateManagerCommand>,
+    allow_dangerous: bool,
+    execution_timeout: Duration,
+) -> Result<()> {
+    let contents = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Could not read replay file {}", path.display()))?;
+    let tasks = parse_replay(&contents)
+        .wrap_err_with(|| format!("Could not parse replay file {}", path.display()))?;
+    let total = tasks.len();
+    println!("INFO: Replaying {} task(s) from {}.", total, path.display());
+
+    let mut skipped = 0;
+    let mut failed = 0;
+    for (index, args_values) in tasks.into_iter().enumerate() {
+        let label = format!("[{}/{}] {}", index + 1, total, args_values.variant_name());
+        if !allow_dangerous && !args_values.is_read_only() {
+            println!(
+                "{} skipped: may modify the place (pass --allow-dangerous to replay it).",
+                label
+            );
+            skipped += 1;
+            continue;
+        }
+        let (args, _) = ToolArguments::new_with_id(args_values);
+        match dispatch_and_wait(
+            sm_command_tx,
+            args,
+            DispatchOptions {
+                execution_timeout,
+                ..DispatchOptions::default()
+            },
+        )
+        .await
+        {
+            Ok(completion) => {
+                let result = completion.result;
+                let is_error = result.is_error.unwrap_or(false);
+                if is_error {
+                    failed += 1;
+                }
+                println!(
+                    "{} {}",
+                    label,
+                    if is_error {
+                        "returned an error:"
+                    } else {
+                        "ok:"
+                    }
+                );
+                for content in &result.content {
+                    match &content.raw {
+                        RawContent::Text(text) => println!("    {}", text.text),
+                        _ => println!("    <non-text content>"),
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{} failed: {}", label, e.message);
+            }
+        }
+    }
+    println!(
+        "INFO: Replay finished: {} dispatched, {} skipped, {} failed.",
+        total - skipped,
+        skipped,
+        failed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_recorded_tasks_and_bare_arguments_in_order() {
+        let (recorded, _) = ToolArguments::new_with_id(ToolArgumentValues::GetPlaceInfo {});
+        let contents = format!(
+            "# captured session\n{}\n\n{}\n",
+            serde_json::to_string(&recorded).unwrap(),
+            r#"{"RunCommand": {"command": "print(1)", "context": null}}"#
+        );
+        let tasks = parse_replay(&contents).unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert!(matches!(tasks[0], ToolArgumentValues::GetPlaceInfo {}));
+        assert!(
+            matches!(&tasks[1], ToolArgumentValues::RunCommand { command, context: None } if command == "print(1)")
+        );
+        // Only the read-only one would be replayed without --allow-dangerous.
+        assert!(tasks[0].is_read_only());
+        assert!(!tasks[1].is_read_only());
+    }
+
+    #[test]
+    fn names_the_line_that_is_not_a_task() {
+        let error = parse_replay("{\"GetPlaceInfo\": {}}\n{\"NoSuchTool\": {}}\n").unwrap_err();
+        assert!(error.to_string().starts_with("Line 2:"), "{}", error);
+    }
+}
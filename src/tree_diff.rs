@@ -0,0 +1,147 @@
+// Compares two trees in the shape the ExportTree plugin tool produces:
+// `{ "className", "properties": { "Name", .. }, "children": [..] }`.
+
+use serde_json::{json, Map, Value};
+
+#[derive(Default)]
+struct TreeDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<Value>,
+    truncated: bool,
+}
+
+impl TreeDiff {
+    fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+}
+
+/// Reports instances added or removed under the root, and property values that differ on
+/// instances present in both, as `{ "added", "removed", "changed", "truncated" }`. Siblings are
+/// paired by name and class, in order, so renaming an instance shows up as a remove plus an add.
+/// Stops after `max_changes` entries and sets `truncated`.
+pub fn diff_trees(before: &Value, after: &Value, max_changes: usize) -> Value {
+    let mut diff = TreeDiff::default();
+    diff_node(before, after, &node_name(after), &mut diff, max_changes);
+    json!({
+        "added": diff.added,
+        "removed": diff.removed,
+        "changed": diff.changed,
+        "truncated": diff.truncated,
+    })
+}
+
+fn node_name(node: &Value) -> String {
+    node["properties"]["Name"]
+        .as_str()
+        .unwrap_or("?")
+        .to_string()
+}
+
+fn node_key(node: &Value) -> (String, String) {
+    (
+        node_name(node),
+        node["className"].as_str().unwrap_or_default().to_string(),
+    )
+}
+
+fn children(node: &Value) -> &[Value] {
+    node["children"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn diff_node(before: &Value, after: &Value, path: &str, diff: &mut TreeDiff, max_changes: usize) {
+    let empty = Map::new();
+    let before_properties = before["properties"].as_object().unwrap_or(&empty);
+    let after_properties = after["properties"].as_object().unwrap_or(&empty);
+    let mut names: Vec<&String> = before_properties
+        .keys()
+        .chain(after_properties.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let (old, new) = (before_properties.get(name), after_properties.get(name));
+        if old != new {
+            if diff.len() >= max_changes {
+                diff.truncated = true;
+                return;
+            }
+            diff.changed
+                .push(json!({ "path": path, "property": name, "before": old, "after": new }));
+        }
+    }
+
+    let mut unmatched: Vec<Option<&Value>> = children(before).iter().map(Some).collect();
+    for after_child in children(after) {
+        let key = node_key(after_child);
+        let child_path = format!("{}.{}", path, key.0);
+        let matched = unmatched
+            .iter_mut()
+            .find(|slot| slot.is_some_and(|child| node_key(child) == key))
+            .and_then(Option::take);
+        match matched {
+            Some(before_child) => {
+                diff_node(before_child, after_child, &child_path, diff, max_changes)
+            }
+            None => {
+                if diff.len() >= max_changes {
+                    diff.truncated = true;
+                    return;
+                }
+                diff.added.push(child_path);
+            }
+        }
+        if diff.truncated {
+            return;
+        }
+    }
+    for before_child in unmatched.into_iter().flatten() {
+        if diff.len() >= max_changes {
+            diff.truncated = true;
+            return;
+        }
+        diff.removed
+            .push(format!("{}.{}", path, node_name(before_child)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(class_name: &str, name: &str, children: Vec<Value>) -> Value {
+        json!({ "className": class_name, "properties": { "Name": name }, "children": children })
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_instances() {
+        let before = node(
+            "Model",
+            "House",
+            vec![node("Part", "Wall", vec![]), node("Part", "Roof", vec![])],
+        );
+        let mut after = node(
+            "Model",
+            "House",
+            vec![node("Part", "Wall", vec![]), node("Part", "Door", vec![])],
+        );
+        after["children"][0]["properties"]["Anchored"] = json!(true);
+
+        assert_eq!(
+            diff_trees(&before, &after, 10),
+            json!({
+                "added": ["House.Door"],
+                "removed": ["House.Roof"],
+                "changed": [{ "path": "House.Wall", "property": "Anchored", "before": null, "after": true }],
+                "truncated": false,
+            })
+        );
+        let capped = diff_trees(&before, &after, 2);
+        assert_eq!(capped["truncated"], true);
+        assert_eq!(capped["removed"], json!([]));
+    }
+}